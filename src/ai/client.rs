@@ -1,140 +1,107 @@
-use crate::types::ChatMessage;
-use anyhow::Result;
-use once_cell::sync::Lazy;
+use crate::tools::{MAX_TOOL_TURNS, ToolContext};
+use crate::types::{ChatMessage, Role};
+use futures::StreamExt;
+use rig::agent::{Agent, AgentBuilder, CancelSignal, MultiTurnStreamItem, PromptHook};
 use rig::client::CompletionClient;
-use rig::completion::{Chat, Prompt};
-use std::collections::HashMap;
-use std::env;
-use std::sync::Mutex;
-use std::sync::atomic::{AtomicU64, Ordering};
-
+use rig::completion::{CompletionModel, CompletionResponse, GetTokenUsage, Prompt, Usage};
+use rig::streaming::{StreamedAssistantContent, StreamingChat};
+use serde_json::{Value, json};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use super::context;
+use super::continuation::{self, CONTINUE_PROMPT, Completion, MAX_CONTINUATIONS, Stitcher};
+use super::error::{ChatError, ChatResult};
+use super::fixtures::{FixtureMode, FixtureStore};
 use super::providers::ProviderClient;
-
-// ============================================
-// Error Types
-// ============================================
-
-#[derive(Debug, Clone)]
-pub struct ChatError(String);
-
-impl ChatError {
-    pub fn new(message: impl Into<String>) -> Self {
-        Self(message.into())
-    }
+use super::retry::RetryPolicy;
+use super::router::{ProviderRoute, ProviderRouter, RouteInfo, Task};
+use super::stream::{self, StreamHandle, StreamSubscription};
+use super::structured::{JsonSchema, ResponseFormat};
+
+/// A complete (non-streaming) response and where it came from
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChatReply {
+    pub content: String,
+    pub route: RouteInfo,
+    /// Follow-up requests made because the reply was cut off
+    pub continuations: u32,
 }
 
-impl std::fmt::Display for ChatError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
-    }
+/// Unified AI client wrapper for Blackbird
+/// Handles provider routing, failover and agent configuration
+pub struct BlackbirdAI {
+    router: ProviderRouter,
+    /// `None` when the model is offered no tools
+    tools: Option<ToolContext>,
+    retry: RetryPolicy,
+    recorder: Option<FixtureStore>,
+    format: ResponseFormat,
+    context_window: Option<usize>,
 }
 
-impl std::error::Error for ChatError {}
-
-impl From<anyhow::Error> for ChatError {
-    fn from(err: anyhow::Error) -> Self {
-        ChatError::new(err.to_string())
+impl BlackbirdAI {
+    /// Create AI client for a specific provider
+    pub fn new(client: ProviderClient) -> Self {
+        Self::with_router(ProviderRouter::single(client))
     }
-}
 
-pub type ChatResult<T> = Result<T, ChatError>;
-
-// ============================================
-// Streaming State Management
-// ============================================
-
-static STREAM_STORE: Lazy<StreamStore> = Lazy::new(StreamStore::default);
-
-struct StreamStore {
-    counter: AtomicU64,
-    entries: Mutex<HashMap<u64, StreamEntry>>,
-}
-
-impl Default for StreamStore {
-    fn default() -> Self {
+    /// Create AI client that fails over between the router's providers
+    pub fn with_router(router: ProviderRouter) -> Self {
         Self {
-            counter: AtomicU64::new(1),
-            entries: Mutex::new(HashMap::new()),
+            router,
+            tools: Some(ToolContext::shared()),
+            retry: RetryPolicy::from_env(),
+            recorder: None,
+            format: ResponseFormat::Text,
+            context_window: None,
         }
     }
-}
 
-#[derive(Default)]
-struct StreamEntry {
-    buffer: String,
-    done: bool,
-}
-
-impl StreamStore {
-    fn create_handle(&self) -> StreamHandle {
-        let id = self.counter.fetch_add(1, Ordering::Relaxed);
-        let mut entries = self.entries.lock().expect("stream store poisoned");
-        entries.insert(id, StreamEntry::default());
-        StreamHandle { id }
-    }
-
-    fn append(&self, id: u64, chunk: &str) {
-        let mut entries = self.entries.lock().expect("stream store poisoned");
-        if let Some(entry) = entries.get_mut(&id) {
-            entry.buffer.push_str(chunk);
+    /// Create AI client from environment configuration
+    pub fn from_env() -> ChatResult<Self> {
+        let mut ai = Self::with_router(ProviderRouter::from_env()?);
+        if let Some((FixtureMode::Record, store)) = FixtureStore::from_env() {
+            ai = ai.with_recorder(store);
         }
+        Ok(ai)
     }
 
-    fn finish(&self, id: u64) {
-        let mut entries = self.entries.lock().expect("stream store poisoned");
-        if let Some(entry) = entries.get_mut(&id) {
-            entry.done = true;
-        }
+    /// Override the retry policy read from the environment
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
     }
 
-    fn fail(&self, id: u64, message: String) {
-        let mut entries = self.entries.lock().expect("stream store poisoned");
-        if let Some(entry) = entries.get_mut(&id) {
-            entry.buffer = message;
-            entry.done = true;
-        }
+    /// Use a specific tool context instead of the shared one
+    pub fn with_tool_context(mut self, tools: ToolContext) -> Self {
+        self.tools = Some(tools);
+        self
     }
 
-    fn snapshot(&self, id: u64) -> ChatResult<(String, bool)> {
-        let entries = self.entries.lock().expect("stream store poisoned");
-        if let Some(entry) = entries.get(&id) {
-            Ok((entry.buffer.clone(), entry.done))
-        } else {
-            Err(ChatError::new("invalid stream id"))
-        }
+    /// Offer the model no tools at all
+    pub fn without_tools(mut self) -> Self {
+        self.tools = None;
+        self
     }
-}
-
-#[derive(Clone)]
-pub struct StreamHandle {
-    id: u64,
-}
 
-impl StreamHandle {
-    pub fn append(&self, piece: &str) {
-        STREAM_STORE.append(self.id, piece);
+    /// Ask for replies in this format. JSON schemas use the provider's native
+    /// structured output where available and prompt instructions elsewhere.
+    pub fn with_response_format(mut self, format: ResponseFormat) -> Self {
+        self.format = format;
+        self
     }
 
-    pub fn finish(&self) {
-        STREAM_STORE.finish(self.id);
+    /// Assume this context window (in tokens) instead of looking up each route's model
+    pub fn with_context_window(mut self, tokens: usize) -> Self {
+        self.context_window = Some(tokens);
+        self
     }
 
-    pub fn fail(&self, err: &str) {
-        STREAM_STORE.fail(self.id, err.to_string());
-    }
-}
-
-/// Unified AI client wrapper for Blackbird
-/// Handles provider auto-detection and agent configuration
-pub struct BlackbirdAI {
-    client: ProviderClient,
-}
-
-impl BlackbirdAI {
-    /// Create AI client from environment configuration
-    pub fn from_env() -> Result<Self> {
-        let client = ProviderClient::from_env()?;
-        Ok(Self { client })
+    /// Save every successful response as a fixture for later replay
+    pub fn with_recorder(mut self, store: FixtureStore) -> Self {
+        self.recorder = Some(store);
+        self
     }
 
     /// Get the system prompt for Blackbird
@@ -148,192 +115,783 @@ When responding:
 
 You have access to tools that allow you to:
 - Perform calculations
-- Search through the user's saved documents
-- List saved documents with filtering
+- Search through the user's saved apps
+- List saved apps with filtering
 - Get current application settings"#
             .to_string()
     }
 
     /// Simple prompt (non-streaming, single-turn)
-    pub async fn prompt(&self, message: &str) -> Result<String> {
-        match &self.client {
+    pub async fn prompt(&self, message: &str) -> ChatResult<String> {
+        Ok(self.chat(Task::Chat, message, Vec::new()).await?.content)
+    }
+
+    /// Chat with conversation history (non-streaming, multi-turn)
+    pub async fn chat(
+        &self,
+        task: Task,
+        message: &str,
+        history: Vec<ChatMessage>,
+    ) -> ChatResult<ChatReply> {
+        let history = &self.compact(task, message, history).await;
+        let ((content, continuations), route) = self
+            .router
+            .run(
+                task,
+                &self.retry,
+                || true,
+                |route, info| async move {
+                    self.generate(route, &info.model, task, message, history, None)
+                        .await
+                },
+            )
+            .await?;
+        Ok(ChatReply {
+            content,
+            route,
+            continuations,
+        })
+    }
+
+    /// Reply to the last of `messages`, with the rest as history
+    pub async fn reply(&self, messages: Vec<ChatMessage>, task: Task) -> ChatResult<ChatReply> {
+        let Some((last_message, history)) = messages.split_last() else {
+            return Err(ChatError::new("No messages provided"));
+        };
+        self.chat(task, &last_message.content, history.to_vec())
+            .await
+    }
+
+    /// Stream a reply to the last of `messages` in the background, returning
+    /// the stream id to subscribe to
+    pub fn start_stream(self, messages: Vec<ChatMessage>, task: Task) -> u64 {
+        spawn_stream(move |handle| async move { self.reply_stream(messages, task, &handle).await })
+    }
+
+    async fn reply_stream(
+        &self,
+        messages: Vec<ChatMessage>,
+        task: Task,
+        handle: &StreamHandle,
+    ) -> ChatResult<RouteInfo> {
+        let Some((last_message, history)) = messages.split_last() else {
+            return Err(ChatError::new("No messages provided"));
+        };
+        self.chat_stream(task, &last_message.content, history.to_vec(), handle)
+            .await
+    }
+
+    /// Chat with conversation history, pushing each chunk into `handle` as it arrives.
+    ///
+    /// Failures are only retried (or failed over to another provider) before the
+    /// first chunk has been emitted, so subscribers never see duplicated text.
+    pub async fn chat_stream(
+        &self,
+        task: Task,
+        message: &str,
+        history: Vec<ChatMessage>,
+        handle: &StreamHandle,
+    ) -> ChatResult<RouteInfo> {
+        let history = &self.compact(task, message, history).await;
+        let ((), route) = self
+            .router
+            .run(
+                task,
+                &self.retry,
+                || !handle.has_content() && !handle.is_cancelled(),
+                |route, info| async move {
+                    handle.set_route(info.clone());
+                    self.generate(route, &info.model, task, message, history, Some(handle))
+                        .await?;
+                    Ok(())
+                },
+            )
+            .await?;
+        Ok(route)
+    }
+
+    /// Request a reply on `route`, then request continuations while it was cut
+    /// off (by the output limit, or an HTML document left unclosed by a build).
+    /// Returns the stitched reply and how many continuations it took.
+    ///
+    /// When streaming, every part is appended to `handle` as it arrives. A
+    /// failed continuation keeps the text produced so far.
+    async fn generate(
+        &self,
+        route: &ProviderRoute,
+        model: &str,
+        task: Task,
+        message: &str,
+        history: &[ChatMessage],
+        handle: Option<&StreamHandle>,
+    ) -> ChatResult<(String, u32)> {
+        let emit = |chunk: &str| {
+            if let Some(handle) = handle
+                && !chunk.is_empty()
+            {
+                handle.append(chunk);
+            }
+        };
+
+        let first = self
+            .call(
+                route,
+                model,
+                message,
+                history.to_vec(),
+                &self.format,
+                handle.map(|_| emit),
+            )
+            .await?;
+        let mut content = first.content;
+        let mut truncated = first.truncated;
+        let mut continuations = 0;
+
+        while continuations < MAX_CONTINUATIONS
+            && continuation::needs_continuation(&content, truncated, task == Task::Build)
+        {
+            continuations += 1;
+            tracing::info!(
+                "Reply from {} was cut off, requesting continuation {}",
+                model,
+                continuations
+            );
+            if let Some(handle) = handle {
+                handle.continued(continuations);
+            }
+
+            let mut followup = history.to_vec();
+            for (role, text) in [(Role::User, message), (Role::Assistant, content.as_str())] {
+                followup.push(ChatMessage {
+                    role,
+                    content: text.to_string(),
+                    created_at: None,
+                    tags: Vec::new(),
+                    tool_call_id: None,
+                });
+            }
+
+            // Continuations extend the reply as plain text; a JSON mode would
+            // make the provider start a new object instead
+            let mut stitcher = Stitcher::new(&content);
+            let mut added = String::new();
+            let sink = {
+                let (stitcher, added) = (&mut stitcher, &mut added);
+                move |chunk: &str| {
+                    let ready = stitcher.push(chunk);
+                    emit(&ready);
+                    added.push_str(&ready);
+                }
+            };
+            let result = self
+                .call(
+                    route,
+                    model,
+                    CONTINUE_PROMPT,
+                    followup,
+                    &ResponseFormat::Text,
+                    handle.map(|_| sink),
+                )
+                .await;
+
+            match result {
+                Ok(next) => {
+                    if handle.is_none() {
+                        added.push_str(&stitcher.push(&next.content));
+                    }
+                    let rest = stitcher.finish();
+                    emit(&rest);
+                    added.push_str(&rest);
+                    content.push_str(&added);
+                    truncated = next.truncated;
+                }
+                Err(err) => {
+                    tracing::warn!("Continuation failed ({}), keeping the partial reply", err);
+                    content.push_str(&added);
+                    break;
+                }
+            }
+        }
+
+        Ok((content, continuations))
+    }
+
+    /// One provider request, streamed to `on_chunk` when one is given
+    async fn call<F>(
+        &self,
+        route: &ProviderRoute,
+        model: &str,
+        message: &str,
+        history: Vec<ChatMessage>,
+        format: &ResponseFormat,
+        on_chunk: Option<F>,
+    ) -> ChatResult<Completion>
+    where
+        F: FnMut(&str) + Send,
+    {
+        let completion = match on_chunk {
+            Some(mut on_chunk) => {
+                self.chat_stream_once(
+                    route,
+                    model.to_string(),
+                    message,
+                    history.clone(),
+                    format,
+                    &mut on_chunk,
+                )
+                .await?
+            }
+            None => {
+                self.chat_once(route, model.to_string(), message, history.clone(), format)
+                    .await?
+            }
+        };
+        self.record(model, message, &history, format, &completion.content);
+        Ok(completion)
+    }
+
+    async fn chat_once(
+        &self,
+        route: &ProviderRoute,
+        model: String,
+        message: &str,
+        history: Vec<ChatMessage>,
+        format: &ResponseFormat,
+    ) -> ChatResult<Completion> {
+        let (preamble, history) = self.split_system(history, format);
+        match &route.client {
             ProviderClient::OpenAI(client) => {
-                let model = env::var("OPENAI_MODEL").unwrap_or_else(|_| "gpt-4o".to_string());
+                let rig_messages = self.convert_to_rig_messages(history);
 
-                let agent = client
-                    .agent(&model)
-                    .preamble(&Self::system_prompt())
-                    .max_tokens(4096)
-                    .temperature(0.7)
-                    .build();
+                let agent = self.build_agent(
+                    with_params(
+                        client.agent(&model).preamble(&preamble),
+                        format.schema().map(JsonSchema::responses_param),
+                    )
+                    .max_tokens(context::MAX_OUTPUT_TOKENS as u64)
+                    .temperature(0.7),
+                );
 
-                Ok(agent.prompt(message).await?)
+                prompt_agent(agent, message, rig_messages).await
             }
             ProviderClient::Anthropic(client) => {
-                let model = env::var("ANTHROPIC_MODEL")
-                    .unwrap_or_else(|_| "claude-3-5-sonnet-20241022".to_string());
+                let rig_messages = self.convert_to_rig_messages(history);
 
-                let agent = client
-                    .agent(&model)
-                    .preamble(&Self::system_prompt())
-                    .max_tokens(4096)
-                    .temperature(0.7)
-                    .build();
+                let agent = self.build_agent(
+                    client
+                        .agent(&model)
+                        .preamble(&preamble)
+                        .max_tokens(context::MAX_OUTPUT_TOKENS as u64)
+                        .temperature(0.7),
+                );
 
-                Ok(agent.prompt(message).await?)
+                prompt_agent(agent, message, rig_messages).await
             }
             ProviderClient::Ollama(client) => {
-                let model = env::var("LLM_MODEL").unwrap_or_else(|_| "llama3.1:latest".to_string());
+                let rig_messages = self.convert_to_rig_messages(history);
 
-                let agent = client
-                    .agent(&model)
-                    .preamble(&Self::system_prompt())
-                    .build();
+                let agent = self.build_agent(client.agent(&model).preamble(&preamble));
 
-                Ok(agent.prompt(message).await?)
+                prompt_agent(agent, message, rig_messages).await
+            }
+            ProviderClient::OpenAICompatible(client) => {
+                let rig_messages = self.convert_to_rig_messages(history);
+
+                let agent = self.build_agent(
+                    with_params(
+                        AgentBuilder::new(client.completion_model(&model)).preamble(&preamble),
+                        format
+                            .schema()
+                            .map(|schema| json!({ "response_format": schema.response_format() })),
+                    )
+                    .max_tokens(context::MAX_OUTPUT_TOKENS as u64)
+                    .temperature(0.7),
+                );
+
+                prompt_agent(agent, message, rig_messages).await
             }
             ProviderClient::Blackbird(client) => {
-                // For Blackbird, we send messages directly
-                let messages = vec![
-                    ChatMessage {
-                        role: crate::types::Role::User,
-                        content: Self::system_prompt(),
-                        created_at: None,
-                        tags: vec![],
-                    },
-                    ChatMessage {
-                        role: crate::types::Role::User,
-                        content: message.to_string(),
-                        created_at: None,
-                        tags: vec![],
-                    },
-                ];
-
-                Ok(client.complete(&messages).await?)
+                // For Blackbird, we build the full message array
+                let messages = Self::blackbird_messages(preamble, message, history);
+                client
+                    .complete(
+                        &model,
+                        &messages,
+                        self.tools.as_ref().map(ToolContext::toolset).as_ref(),
+                        format.schema(),
+                    )
+                    .await
+            }
+            ProviderClient::Mock(client) => {
+                let messages = Self::blackbird_messages(preamble, message, history);
+                client
+                    .complete(
+                        &model,
+                        &messages,
+                        self.tools.as_ref().map(ToolContext::toolset).as_ref(),
+                        format.schema(),
+                    )
+                    .await
             }
         }
     }
 
-    /// Chat with conversation history (non-streaming, multi-turn)
-    pub async fn chat(&self, message: &str, history: Vec<ChatMessage>) -> Result<String> {
-        match &self.client {
+    async fn chat_stream_once(
+        &self,
+        route: &ProviderRoute,
+        model: String,
+        message: &str,
+        history: Vec<ChatMessage>,
+        format: &ResponseFormat,
+        on_chunk: &mut (dyn FnMut(&str) + Send),
+    ) -> ChatResult<Completion> {
+        let (preamble, history) = self.split_system(history, format);
+        match &route.client {
             ProviderClient::OpenAI(client) => {
                 let rig_messages = self.convert_to_rig_messages(history);
-                let model = env::var("OPENAI_MODEL").unwrap_or_else(|_| "gpt-4o".to_string());
 
-                let agent = client
-                    .agent(&model)
-                    .preamble(&Self::system_prompt())
-                    .max_tokens(4096)
-                    .temperature(0.7)
-                    .build();
+                let agent = self.build_agent(
+                    with_params(
+                        client.agent(&model).preamble(&preamble),
+                        format.schema().map(JsonSchema::responses_param),
+                    )
+                    .max_tokens(context::MAX_OUTPUT_TOKENS as u64)
+                    .temperature(0.7),
+                );
 
-                Ok(agent.chat(message, rig_messages).await?)
+                stream_agent(agent, message, rig_messages, on_chunk).await
             }
             ProviderClient::Anthropic(client) => {
                 let rig_messages = self.convert_to_rig_messages(history);
-                let model = env::var("ANTHROPIC_MODEL")
-                    .unwrap_or_else(|_| "claude-3-5-sonnet-20241022".to_string());
 
-                let agent = client
-                    .agent(&model)
-                    .preamble(&Self::system_prompt())
-                    .max_tokens(4096)
-                    .temperature(0.7)
-                    .build();
+                let agent = self.build_agent(
+                    client
+                        .agent(&model)
+                        .preamble(&preamble)
+                        .max_tokens(context::MAX_OUTPUT_TOKENS as u64)
+                        .temperature(0.7),
+                );
 
-                Ok(agent.chat(message, rig_messages).await?)
+                stream_agent(agent, message, rig_messages, on_chunk).await
             }
             ProviderClient::Ollama(client) => {
                 let rig_messages = self.convert_to_rig_messages(history);
-                let model = env::var("LLM_MODEL").unwrap_or_else(|_| "llama3.1:latest".to_string());
 
-                let agent = client
-                    .agent(&model)
-                    .preamble(&Self::system_prompt())
-                    .build();
+                let agent = self.build_agent(client.agent(&model).preamble(&preamble));
 
-                Ok(agent.chat(message, rig_messages).await?)
+                stream_agent(agent, message, rig_messages, on_chunk).await
             }
-            ProviderClient::Blackbird(client) => {
-                // For Blackbird, we build the full message array
-                let mut messages = vec![ChatMessage {
-                    role: crate::types::Role::User,
-                    content: Self::system_prompt(),
-                    created_at: None,
-                    tags: vec![],
-                }];
+            ProviderClient::OpenAICompatible(client) => {
+                let rig_messages = self.convert_to_rig_messages(history);
 
-                // Add history
-                messages.extend(history);
+                let agent = self.build_agent(
+                    with_params(
+                        AgentBuilder::new(client.completion_model(&model)).preamble(&preamble),
+                        format
+                            .schema()
+                            .map(|schema| json!({ "response_format": schema.response_format() })),
+                    )
+                    .max_tokens(context::MAX_OUTPUT_TOKENS as u64)
+                    .temperature(0.7),
+                );
+
+                stream_agent(agent, message, rig_messages, on_chunk).await
+            }
+            ProviderClient::Blackbird(client) => {
+                let messages = Self::blackbird_messages(preamble, message, history);
+                client
+                    .complete_stream(
+                        &model,
+                        &messages,
+                        self.tools.as_ref().map(ToolContext::toolset).as_ref(),
+                        format.schema(),
+                        on_chunk,
+                    )
+                    .await
+            }
+            ProviderClient::Mock(client) => {
+                let messages = Self::blackbird_messages(preamble, message, history);
+                client
+                    .complete_stream(
+                        &model,
+                        &messages,
+                        self.tools.as_ref().map(ToolContext::toolset).as_ref(),
+                        format.schema(),
+                        on_chunk,
+                    )
+                    .await
+            }
+        }
+    }
 
-                // Add new user message
-                messages.push(ChatMessage {
-                    role: crate::types::Role::User,
-                    content: message.to_string(),
-                    created_at: None,
-                    tags: vec![],
-                });
+    /// Save a response as a fixture when recording. Failures are logged rather
+    /// than returned so recording never breaks a working request.
+    fn record(
+        &self,
+        model: &str,
+        message: &str,
+        history: &[ChatMessage],
+        format: &ResponseFormat,
+        response: &str,
+    ) {
+        let Some(store) = &self.recorder else {
+            return;
+        };
+        let (preamble, history) = self.split_system(history.to_vec(), format);
+        let messages = Self::blackbird_messages(preamble, message, history);
+        if let Err(err) = store.save(model, &messages, response) {
+            tracing::warn!("Failed to record fixture: {}", err);
+        }
+    }
 
-                Ok(client.complete(&messages).await?)
+    /// Fit `history` into the context budget for `task`.
+    ///
+    /// App source the model has since rewritten is always replaced with a
+    /// placeholder. If the request is still over budget, turns before the
+    /// latest app source and the last exchange are summarised by the
+    /// extraction model (or listed verbatim if that fails).
+    async fn compact(
+        &self,
+        task: Task,
+        message: &str,
+        mut history: Vec<ChatMessage>,
+    ) -> Vec<ChatMessage> {
+        context::drop_stale_sources(&mut history);
+
+        let budget = self.context_budget(task);
+        let mut used = context::estimate_messages(&history) + context::estimate_tokens(message);
+        if !history.iter().any(|msg| msg.role == Role::System) {
+            used += context::estimate_tokens(&Self::system_prompt());
+        }
+        if used <= budget {
+            return history;
+        }
+        let Some(split) = context::split_for_summary(history.clone()) else {
+            return history;
+        };
+
+        tracing::info!(
+            "Request needs ~{} tokens of {}, summarising {} earlier messages",
+            used,
+            budget,
+            split.older.len()
+        );
+        let summary = match self.summarise(&split.older).await {
+            Ok(summary) => summary,
+            Err(err) => {
+                tracing::warn!("Summarising history failed ({}), listing requests", err);
+                context::fallback_summary(&split.older)
             }
+        };
+
+        // The summary joins the preamble, so keep the default prompt it
+        // would otherwise replace
+        let mut compacted = split.system;
+        if compacted.is_empty() {
+            compacted.push(ChatMessage {
+                role: Role::System,
+                content: Self::system_prompt(),
+                created_at: None,
+                tags: Vec::new(),
+                tool_call_id: None,
+            });
+        }
+        compacted.push(context::summary_message(&summary));
+        compacted.extend(split.recent);
+        compacted
+    }
+
+    /// Tokens available for the request: the smallest window among the
+    /// routes serving `task`, less room for the reply
+    fn context_budget(&self, task: Task) -> usize {
+        let window = self.context_window.unwrap_or_else(|| {
+            self.router
+                .routes()
+                .iter()
+                .map(|route| context::context_window(route.model_for(task)))
+                .min()
+                .unwrap_or(context::DEFAULT_CONTEXT_WINDOW)
+        });
+        window.saturating_sub(context::MAX_OUTPUT_TOKENS)
+    }
+
+    async fn summarise(&self, turns: &[ChatMessage]) -> ChatResult<String> {
+        let transcript = &context::transcript(turns);
+        let prompt = vec![ChatMessage {
+            role: Role::System,
+            content: context::SUMMARY_PROMPT.to_string(),
+            created_at: None,
+            tags: Vec::new(),
+            tool_call_id: None,
+        }];
+        let prompt = &prompt;
+
+        let (summary, _) = self
+            .router
+            .run(
+                Task::Extraction,
+                &self.retry,
+                || true,
+                |route, info| async move {
+                    self.chat_once(
+                        route,
+                        info.model,
+                        transcript,
+                        prompt.clone(),
+                        &ResponseFormat::Text,
+                    )
+                    .await
+                },
+            )
+            .await?;
+        Ok(summary.content)
+    }
+
+    /// Build a Rig agent with this client's tools, if it has any
+    fn build_agent<M: CompletionModel>(&self, builder: AgentBuilder<M>) -> Agent<M> {
+        match &self.tools {
+            Some(tools) => tools.attach(builder).build(),
+            None => builder.build(),
+        }
+    }
+
+    /// Separate system messages from the conversation. Their text becomes the
+    /// preamble, replacing the default system prompt rather than adding to it.
+    /// JSON output instructions are appended when a schema is requested.
+    fn split_system(
+        &self,
+        history: Vec<ChatMessage>,
+        format: &ResponseFormat,
+    ) -> (String, Vec<ChatMessage>) {
+        let (system, rest): (Vec<_>, Vec<_>) = history
+            .into_iter()
+            .partition(|msg| msg.role == Role::System);
+
+        let mut preamble = if system.is_empty() {
+            Self::system_prompt()
+        } else {
+            system
+                .into_iter()
+                .map(|msg| msg.content)
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        };
+        if let Some(schema) = format.schema() {
+            preamble.push_str("\n\n");
+            preamble.push_str(&schema.instructions());
         }
+        (preamble, rest)
+    }
+
+    /// Blackbird has no preamble, so the system prompt leads the message array.
+    /// This is also the canonical request form used by the mock and fixtures.
+    fn blackbird_messages(
+        preamble: String,
+        message: &str,
+        history: Vec<ChatMessage>,
+    ) -> Vec<ChatMessage> {
+        let mut messages = vec![ChatMessage {
+            role: Role::System,
+            content: preamble,
+            created_at: None,
+            tags: vec![],
+            tool_call_id: None,
+        }];
+
+        messages.extend(history);
+
+        messages.push(ChatMessage {
+            role: Role::User,
+            content: message.to_string(),
+            created_at: None,
+            tags: vec![],
+            tool_call_id: None,
+        });
+        messages
     }
 
     /// Convert Blackbird ChatMessage to Rig Message format
     fn convert_to_rig_messages(&self, messages: Vec<ChatMessage>) -> Vec<rig::message::Message> {
         messages
             .into_iter()
-            .map(|msg| match msg.role {
-                crate::types::Role::User => rig::message::Message::user(&msg.content),
-                crate::types::Role::Assistant => rig::message::Message::assistant(&msg.content),
+            .filter_map(|msg| match msg.role {
+                // Carried by the agent preamble instead; see `split_system`
+                Role::System => None,
+                Role::User => Some(rig::message::Message::user(&msg.content)),
+                Role::Assistant => Some(rig::message::Message::assistant(&msg.content)),
+                Role::Tool => Some(match msg.tool_call_id {
+                    Some(id) => rig::message::Message::tool_result(id, msg.content),
+                    // Without a call id providers reject a tool result, so pass it as context
+                    None => rig::message::Message::user(format!("Tool result:\n{}", msg.content)),
+                }),
             })
             .collect()
     }
 }
 
-// ============================================
-// Public API Functions
-// ============================================
+/// Drive a Rig agent's streaming chat, passing text deltas to `on_chunk`.
+/// Returns the full text once the stream ends.
+async fn stream_agent<M>(
+    agent: Agent<M>,
+    message: &str,
+    history: Vec<rig::message::Message>,
+    on_chunk: &mut (dyn FnMut(&str) + Send),
+) -> ChatResult<Completion>
+where
+    M: CompletionModel + 'static,
+    M::StreamingResponse: GetTokenUsage,
+{
+    let mut stream = agent
+        .stream_chat(message, history)
+        .multi_turn(MAX_TOOL_TURNS)
+        .await;
+
+    let mut output = String::new();
+    let mut truncated = false;
+    while let Some(item) = stream.next().await {
+        match item.map_err(|err| ChatError::classify(&err))? {
+            MultiTurnStreamItem::StreamItem(StreamedAssistantContent::Text(text)) => {
+                on_chunk(&text.text);
+                output.push_str(&text.text);
+            }
+            // Each completion in the tool loop ends with its own usage; the
+            // last one is the reply
+            MultiTurnStreamItem::StreamItem(StreamedAssistantContent::Final(response)) => {
+                truncated = hit_output_limit(response.token_usage(), agent.max_tokens);
+            }
+            _ => {}
+        }
+    }
 
-/// Simple chat reply (blocking)
-pub async fn chat_reply(messages: Vec<ChatMessage>) -> ChatResult<String> {
-    let ai = BlackbirdAI::from_env()
-        .map_err(|e| ChatError::new(format!("Failed to initialize AI: {}", e)))?;
+    Ok(Completion::new(output, truncated))
+}
+
+/// Run a Rig agent's prompt, following tool calls for up to [`MAX_TOOL_TURNS`]
+async fn prompt_agent<M>(
+    agent: Agent<M>,
+    message: &str,
+    mut history: Vec<rig::message::Message>,
+) -> ChatResult<Completion>
+where
+    M: CompletionModel + 'static,
+{
+    let last = LastUsage::default();
+    let response = agent
+        .prompt(message)
+        .with_history(&mut history)
+        .multi_turn(MAX_TOOL_TURNS)
+        .extended_details()
+        .with_hook(last.clone())
+        .await?;
+
+    let truncated = hit_output_limit(last.get(), agent.max_tokens);
+    Ok(Completion::new(response.output, truncated))
+}
 
-    if messages.is_empty() {
-        return Err(ChatError::new("No messages provided"));
+/// Usage of the latest completion in a prompt. Rig only reports the total
+/// across tool turns, which says nothing about whether the reply was cut off.
+#[derive(Clone, Default)]
+struct LastUsage(Arc<Mutex<Option<Usage>>>);
+
+impl LastUsage {
+    fn get(&self) -> Option<Usage> {
+        *self.0.lock().expect("usage lock poisoned")
     }
+}
 
-    let last_message = &messages[messages.len() - 1];
-    let history = messages[..messages.len() - 1].to_vec();
+impl<M: CompletionModel> PromptHook<M> for LastUsage {
+    fn on_completion_response(
+        &self,
+        _prompt: &rig::message::Message,
+        response: &CompletionResponse<M::Response>,
+        _cancel_sig: CancelSignal,
+    ) -> impl Future<Output = ()> + Send {
+        *self.0.lock().expect("usage lock poisoned") = Some(response.usage);
+        async {}
+    }
+}
 
-    ai.chat(&last_message.content, history)
-        .await
-        .map_err(|e| ChatError::new(format!("Chat error: {}", e)))
+/// Rig does not expose the provider's finish reason, so a reply is taken to be
+/// cut off when it used the whole output allowance. Without a `max_tokens`
+/// the provider's own limit is unknown, so nothing counts as cut off.
+fn hit_output_limit(usage: Option<Usage>, max_tokens: Option<u64>) -> bool {
+    match (usage, max_tokens) {
+        (Some(usage), Some(max)) => usage.output_tokens >= max,
+        _ => false,
+    }
 }
 
-/// Start streaming chat response
-pub async fn chat_reply_stream_start(messages: Vec<ChatMessage>) -> ChatResult<u64> {
-    let handle = STREAM_STORE.create_handle();
-    let id = handle.id;
+/// Apply provider-specific request parameters, if any
+fn with_params<M: CompletionModel>(
+    builder: AgentBuilder<M>,
+    params: Option<Value>,
+) -> AgentBuilder<M> {
+    match params {
+        Some(params) => builder.additional_params(params),
+        None => builder,
+    }
+}
 
-    tokio::spawn(async move {
-        match chat_reply(messages).await {
-            Ok(response) => {
-                handle.append(&response);
-                handle.finish();
-            }
-            Err(err) => {
-                handle.fail(&err.to_string());
-            }
+/// Run `produce` in a background task feeding a new stream, returning its id
+fn spawn_stream<F, Fut>(produce: F) -> u64
+where
+    F: FnOnce(StreamHandle) -> Fut,
+    Fut: Future<Output = ChatResult<RouteInfo>> + Send + 'static,
+{
+    let handle = stream::create_handle();
+    let id = handle.id();
+
+    let task_handle = handle.clone();
+    let future = produce(handle.clone());
+    let task = tokio::spawn(async move {
+        match future.await {
+            Ok(_) => task_handle.finish(),
+            Err(err) => task_handle.fail(err),
         }
     });
+    handle.attach_abort(task.abort_handle());
+
+    id
+}
+
+// ============================================
+// Public API Functions
+// ============================================
+
+/// Simple chat reply (blocking), routed to the model suited to `task`
+pub async fn chat_reply(messages: Vec<ChatMessage>, task: Task) -> ChatResult<ChatReply> {
+    BlackbirdAI::from_env()?.reply(messages, task).await
+}
 
-    Ok(id)
+/// Start streaming chat response.
+/// Subscribers are told which provider and model serve it via [`StreamEvent::Routed`].
+///
+/// [`StreamEvent::Routed`]: super::StreamEvent::Routed
+pub async fn chat_reply_stream_start(messages: Vec<ChatMessage>, task: Task) -> ChatResult<u64> {
+    Ok(spawn_stream(move |handle| async move {
+        BlackbirdAI::from_env()?
+            .reply_stream(messages, task, &handle)
+            .await
+    }))
+}
+
+/// Subscribe to a streaming chat response.
+/// Content produced before subscribing is delivered as the first delta.
+pub async fn chat_reply_stream_subscribe(id: u64) -> ChatResult<StreamSubscription> {
+    stream::subscribe(id)
 }
 
 /// Poll streaming chat status
+///
+/// Compatibility layer over [`chat_reply_stream_subscribe`]: returns the content
+/// accumulated so far and whether the stream is done. Once `done` has been
+/// returned the stream is released and further polls fail.
 pub async fn chat_reply_stream_poll(id: u64) -> ChatResult<(String, bool)> {
-    STREAM_STORE.snapshot(id)
+    stream::poll(id)
+}
+
+/// Cancel a streaming chat response, aborting the provider request.
+/// Subscribers receive the partial content followed by a cancellation event.
+pub async fn chat_reply_stream_cancel(id: u64) -> ChatResult<()> {
+    stream::cancel(id)
 }
@@ -0,0 +1,245 @@
+use std::env;
+
+use crate::types::{ChatMessage, Role};
+
+/// Context window assumed for models not in [`context_window`]'s table
+pub const DEFAULT_CONTEXT_WINDOW: usize = 32_000;
+
+/// Reply length requested from providers, and left free in the context budget
+pub const MAX_OUTPUT_TOKENS: usize = 4_096;
+
+/// Trailing messages never folded into a summary
+const KEEP_RECENT_MESSAGES: usize = 2;
+
+/// Replaces app source the model has since rewritten
+pub const STALE_SOURCE_PLACEHOLDER: &str =
+    "[Earlier version of the app omitted; the latest version appears later in the conversation]";
+
+/// Sent to the extraction model to summarise turns that no longer fit
+pub const SUMMARY_PROMPT: &str = "Summarise the conversation below for a model that will continue it. \
+List every requirement, preference and decision the user stated, in order, as short bullet points. \
+Do not include code.";
+
+/// Prefix of the system message that carries a summary
+const SUMMARY_HEADING: &str = "Summary of the earlier conversation:";
+
+/// Context window in tokens for a model, from `LLM_CONTEXT_WINDOW` or a table
+/// of known model families
+pub fn context_window(model: &str) -> usize {
+    if let Some(tokens) = env::var("LLM_CONTEXT_WINDOW")
+        .ok()
+        .and_then(|raw| raw.trim().parse().ok())
+    {
+        return tokens;
+    }
+
+    let model = model.to_ascii_lowercase();
+    // Most specific prefixes first
+    const KNOWN: &[(&str, usize)] = &[
+        ("gpt-4.1", 1_047_576),
+        ("gpt-4o", 128_000),
+        ("gpt-4-turbo", 128_000),
+        ("gpt-4", 8_192),
+        ("gpt-3.5", 16_385),
+        ("gpt-oss", 131_072),
+        ("o1", 200_000),
+        ("o3", 200_000),
+        ("o4", 200_000),
+        ("claude", 200_000),
+        ("llama3", 128_000),
+        ("qwen", 32_768),
+        ("mistral", 32_768),
+    ];
+    KNOWN
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, tokens)| *tokens)
+        .unwrap_or(DEFAULT_CONTEXT_WINDOW)
+}
+
+/// Rough token count (about four characters per token)
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Estimated tokens for a message list, including per-message overhead
+pub fn estimate_messages(messages: &[ChatMessage]) -> usize {
+    messages
+        .iter()
+        .map(|msg| estimate_tokens(&msg.content) + 4)
+        .sum()
+}
+
+/// Whether a message carries a full HTML document
+pub fn is_app_source(content: &str) -> bool {
+    let lower = content.to_ascii_lowercase();
+    lower.contains("<!doctype html") || lower.contains("<html")
+}
+
+/// Replace every assistant app source except the latest with a placeholder.
+/// Returns how many were replaced.
+pub fn drop_stale_sources(history: &mut [ChatMessage]) -> usize {
+    let Some(latest) = history
+        .iter()
+        .rposition(|msg| msg.role == Role::Assistant && is_app_source(&msg.content))
+    else {
+        return 0;
+    };
+
+    let mut replaced = 0;
+    for msg in &mut history[..latest] {
+        if msg.role == Role::Assistant && is_app_source(&msg.content) {
+            msg.content = STALE_SOURCE_PLACEHOLDER.to_string();
+            replaced += 1;
+        }
+    }
+    replaced
+}
+
+/// History split for summarisation
+pub struct SummarySplit {
+    /// System messages, kept as they are
+    pub system: Vec<ChatMessage>,
+    /// Turns to fold into a summary
+    pub older: Vec<ChatMessage>,
+    /// The most recent turns and the latest app source, kept verbatim
+    pub recent: Vec<ChatMessage>,
+}
+
+/// Split `history` into turns to summarise and turns to keep, or `None` when
+/// there is nothing old enough to summarise
+pub fn split_for_summary(history: Vec<ChatMessage>) -> Option<SummarySplit> {
+    let (system, turns): (Vec<_>, Vec<_>) = history
+        .into_iter()
+        .partition(|msg| msg.role == Role::System);
+
+    let recent_start = turns.len().saturating_sub(KEEP_RECENT_MESSAGES);
+    let latest_source = turns
+        .iter()
+        .rposition(|msg| msg.role == Role::Assistant && is_app_source(&msg.content));
+    let keep_from = latest_source.map_or(recent_start, |idx| idx.min(recent_start));
+    if keep_from == 0 {
+        return None;
+    }
+
+    let mut older = turns;
+    let recent = older.split_off(keep_from);
+    Some(SummarySplit {
+        system,
+        older,
+        recent,
+    })
+}
+
+/// Plain-text transcript of turns, as given to the summariser
+pub fn transcript(turns: &[ChatMessage]) -> String {
+    turns
+        .iter()
+        .map(|msg| {
+            let speaker = match msg.role {
+                Role::User => "User",
+                Role::Assistant => "Assistant",
+                Role::Tool => "Tool",
+                Role::System => "System",
+            };
+            let content = if is_app_source(&msg.content) {
+                "[app source]"
+            } else {
+                msg.content.as_str()
+            };
+            format!("{}: {}", speaker, content)
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Summary used when the summariser is unavailable: the user's requests, in order
+pub fn fallback_summary(turns: &[ChatMessage]) -> String {
+    turns
+        .iter()
+        .filter(|msg| msg.role == Role::User)
+        .map(|msg| {
+            let line = msg.content.split_whitespace().collect::<Vec<_>>().join(" ");
+            match line.char_indices().nth(200) {
+                Some((cut, _)) => format!("- {}...", &line[..cut]),
+                None => format!("- {}", line),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The system message carrying a summary
+pub fn summary_message(summary: &str) -> ChatMessage {
+    ChatMessage {
+        role: Role::System,
+        content: format!("{}\n{}", SUMMARY_HEADING, summary.trim()),
+        created_at: None,
+        tags: Vec::new(),
+        tool_call_id: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(role: Role, content: &str) -> ChatMessage {
+        ChatMessage {
+            role,
+            content: content.to_string(),
+            created_at: None,
+            tags: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    #[test]
+    fn test_context_window_lookup() {
+        assert_eq!(context_window("gpt-4o-mini"), 128_000);
+        assert_eq!(context_window("gpt-4"), 8_192);
+        assert_eq!(context_window("claude-3-5-haiku-20241022"), 200_000);
+        assert_eq!(context_window("something-new"), DEFAULT_CONTEXT_WINDOW);
+    }
+
+    #[test]
+    fn test_only_latest_source_is_kept() {
+        let mut history = vec![
+            msg(Role::User, "make a timer"),
+            msg(Role::Assistant, "<!DOCTYPE html><html>v1</html>"),
+            msg(Role::User, "make it red"),
+            msg(Role::Assistant, "<html>v2</html>"),
+        ];
+        assert_eq!(drop_stale_sources(&mut history), 1);
+        assert_eq!(history[1].content, STALE_SOURCE_PLACEHOLDER);
+        assert_eq!(history[3].content, "<html>v2</html>");
+    }
+
+    #[test]
+    fn test_split_keeps_latest_source_and_recent_turns() {
+        let history = vec![
+            msg(Role::System, "prompt"),
+            msg(Role::User, "make a timer"),
+            msg(Role::Assistant, "<html>v1</html>"),
+            msg(Role::User, "make it red"),
+            msg(Role::Assistant, "Done."),
+            msg(Role::User, "add sound"),
+        ];
+        let split = split_for_summary(history).unwrap();
+        assert_eq!(split.system.len(), 1);
+        assert_eq!(split.older.len(), 1);
+        assert_eq!(split.recent[0].content, "<html>v1</html>");
+
+        assert!(split_for_summary(vec![msg(Role::User, "hi")]).is_none());
+    }
+
+    #[test]
+    fn test_fallback_summary_lists_requests() {
+        let turns = vec![
+            msg(Role::User, "make a\n timer"),
+            msg(Role::Assistant, "ok"),
+            msg(Role::User, "make it red"),
+        ];
+        assert_eq!(fallback_summary(&turns), "- make a timer\n- make it red");
+    }
+}
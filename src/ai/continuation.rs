@@ -0,0 +1,186 @@
+use super::context;
+use crate::patch;
+
+/// Follow-up requests allowed after a reply is cut off
+pub const MAX_CONTINUATIONS: u32 = 3;
+
+/// Sent as the user turn of a continuation request
+pub const CONTINUE_PROMPT: &str = "Your previous reply was cut off by the output limit. \
+Continue it exactly where it stopped: do not repeat anything already written, \
+do not start over and do not add any commentary or code fences.";
+
+/// Characters of the previous part compared against the start of a continuation
+const OVERLAP_WINDOW: usize = 256;
+
+/// Shorter matches are treated as coincidence rather than repeated text
+const MIN_OVERLAP: usize = 12;
+
+/// A provider reply and whether it stopped at the output token limit
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Completion {
+    pub content: String,
+    pub truncated: bool,
+}
+
+impl Completion {
+    pub fn new(content: impl Into<String>, truncated: bool) -> Self {
+        Self {
+            content: content.into(),
+            truncated,
+        }
+    }
+}
+
+/// Whether `content` opens an HTML document without closing it
+pub fn is_unclosed_document(content: &str) -> bool {
+    context::is_app_source(content) && !content.to_ascii_lowercase().contains("</html>")
+}
+
+/// Whether a reply should be continued. `expects_document` marks requests
+/// that must end with a complete HTML document, so an unclosed one counts as
+/// cut off even when the provider did not report hitting its limit.
+pub fn needs_continuation(content: &str, truncated: bool, expects_document: bool) -> bool {
+    // Edits quote fragments of a document, so only the provider can tell
+    if patch::is_edit_reply(content) {
+        return truncated;
+    }
+    if is_unclosed_document(content) {
+        return truncated || expects_document;
+    }
+    // A finished document is complete even if trailing text hit the limit
+    truncated && !context::is_app_source(content)
+}
+
+/// Joins a continuation onto the text before it.
+///
+/// The start of the continuation is held back until enough of it has arrived
+/// to drop a leading code fence and any text the model repeated from the end
+/// of the previous part. After that, chunks pass straight through.
+pub struct Stitcher {
+    tail: String,
+    pending: String,
+    resolved: bool,
+}
+
+impl Stitcher {
+    pub fn new(previous: &str) -> Self {
+        let start = previous
+            .char_indices()
+            .rev()
+            .nth(OVERLAP_WINDOW - 1)
+            .map_or(0, |(idx, _)| idx);
+        Self {
+            tail: previous[start..].to_string(),
+            pending: String::new(),
+            resolved: false,
+        }
+    }
+
+    /// Feed a chunk of the continuation, returning the text ready to append
+    pub fn push(&mut self, chunk: &str) -> String {
+        if self.resolved {
+            return chunk.to_string();
+        }
+        self.pending.push_str(chunk);
+        if self.pending.chars().count() < OVERLAP_WINDOW {
+            return String::new();
+        }
+        self.resolve()
+    }
+
+    /// The text still held back once the continuation has ended
+    pub fn finish(mut self) -> String {
+        if self.resolved {
+            return String::new();
+        }
+        self.resolve()
+    }
+
+    fn resolve(&mut self) -> String {
+        self.resolved = true;
+        let pending = std::mem::take(&mut self.pending);
+        let text = strip_opening_fence(&pending);
+        text[overlap(&self.tail, text)..].to_string()
+    }
+}
+
+/// Join two parts of a reply in one go
+pub fn stitch(previous: &str, continuation: &str) -> String {
+    let mut stitcher = Stitcher::new(previous);
+    let mut joined = previous.to_string();
+    joined.push_str(&stitcher.push(continuation));
+    joined.push_str(&stitcher.finish());
+    joined
+}
+
+/// Drop a markdown fence the model opened despite being asked not to
+fn strip_opening_fence(text: &str) -> &str {
+    let trimmed = text.trim_start();
+    if !trimmed.starts_with("```") {
+        return text;
+    }
+    match trimmed.find('\n') {
+        Some(end) => &trimmed[end + 1..],
+        None => "",
+    }
+}
+
+/// Length in bytes of the longest prefix of `text` that `tail` ends with
+fn overlap(tail: &str, text: &str) -> usize {
+    (MIN_OVERLAP..=tail.len().min(text.len()))
+        .rev()
+        .find(|&len| text.is_char_boundary(len) && tail.ends_with(&text[..len]))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unclosed_document() {
+        assert!(is_unclosed_document("<!DOCTYPE html><html><body>"));
+        assert!(!is_unclosed_document("<html><body></body></HTML>"));
+        assert!(!is_unclosed_document("Here is a tip."));
+
+        assert!(needs_continuation("<html><body>", false, true));
+        assert!(!needs_continuation("<html><body>", false, false));
+        assert!(needs_continuation("A long answer", true, false));
+        assert!(!needs_continuation("<html></html>[[app_t", true, true));
+        assert!(!needs_continuation(
+            "<<<<<<< SEARCH\n<html>\n=======\n<html lang=\"en\">\n>>>>>>> REPLACE",
+            false,
+            true
+        ));
+    }
+
+    #[test]
+    fn test_stitch_drops_fence_and_repeated_text() {
+        let first = "<html><body><div class=\"timer\">00:00</div><but";
+        assert_eq!(
+            stitch(first, "ton>Start</button></body></html>"),
+            "<html><body><div class=\"timer\">00:00</div><button>Start</button></body></html>"
+        );
+        assert_eq!(
+            stitch(
+                first,
+                "```html\n<div class=\"timer\">00:00</div><button></button>"
+            ),
+            "<html><body><div class=\"timer\">00:00</div><button></button>"
+        );
+        // A short coincidental match is kept
+        assert_eq!(stitch("a < b", "<b>"), "a < b<b>");
+    }
+
+    #[test]
+    fn test_stitcher_holds_back_until_overlap_is_known() {
+        let previous = "x".repeat(300) + "<script>let count = 0;";
+        let mut stitcher = Stitcher::new(&previous);
+        assert_eq!(stitcher.push("<script>let count"), "");
+        let rest = " = 0;\ncount++;".to_string() + &"y".repeat(OVERLAP_WINDOW);
+        let emitted = stitcher.push(&rest);
+        assert!(emitted.starts_with("\ncount++;"));
+        assert_eq!(stitcher.push("z"), "z");
+        assert_eq!(stitcher.finish(), "");
+    }
+}
@@ -0,0 +1,296 @@
+use rig::completion::{CompletionError, PromptError};
+use rig::http_client;
+use rig::tool::ToolSetError;
+use std::time::Duration;
+
+/// Errors surfaced by the AI client, classified so callers can decide
+/// whether to retry, fail over, or show the user a specific message
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ChatError {
+    /// No provider is configured, or its configuration is invalid
+    #[error("Configuration error: {0}")]
+    Config(String),
+
+    /// The provider rejected the credentials (HTTP 401/403)
+    #[error("Authentication failed: {0}")]
+    Auth(String),
+
+    /// The provider asked us to slow down (HTTP 429)
+    #[error("Rate limited{}", retry_after.map(|d| format!(" (retry after {}s)", d.as_secs())).unwrap_or_default())]
+    RateLimited { retry_after: Option<Duration> },
+
+    /// The request did not complete within the configured timeout
+    #[error("Request timed out")]
+    Timeout,
+
+    /// The provider could not be reached
+    #[error("Network error: {0}")]
+    Network(String),
+
+    /// The provider answered with an error. `status` is `None` when the
+    /// provider library did not expose the HTTP status.
+    #[error("Provider error{}: {body}", status.map(|s| format!(" {s}")).unwrap_or_default())]
+    Provider { status: Option<u16>, body: String },
+
+    /// The provider's response could not be understood
+    #[error("Invalid response: {0}")]
+    Parse(String),
+
+    /// Anything else (empty requests, tool loop limits, ...)
+    #[error("{0}")]
+    Other(String),
+}
+
+pub type ChatResult<T> = Result<T, ChatError>;
+
+impl ChatError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self::Other(message.into())
+    }
+
+    /// Classify a non-success HTTP response
+    pub fn from_status(
+        status: u16,
+        body: impl Into<String>,
+        retry_after: Option<Duration>,
+    ) -> Self {
+        match status {
+            401 | 403 => Self::Auth(body.into()),
+            429 => Self::RateLimited { retry_after },
+            408 | 504 => Self::Timeout,
+            _ => Self::Provider {
+                status: Some(status),
+                body: body.into(),
+            },
+        }
+    }
+
+    /// Whether the same request may succeed if sent again
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::RateLimited { .. } | Self::Timeout | Self::Network(_) => true,
+            Self::Provider { status, .. } => status.is_some_and(|s| s >= 500),
+            Self::Config(_) | Self::Auth(_) | Self::Parse(_) | Self::Other(_) => false,
+        }
+    }
+
+    /// Whether a different provider might succeed where this one failed
+    pub fn should_failover(&self) -> bool {
+        matches!(
+            self,
+            Self::Auth(_) | Self::RateLimited { .. } | Self::Timeout | Self::Network(_)
+        )
+    }
+
+    /// Delay requested by the provider before the next attempt
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::RateLimited { retry_after } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Short, non-technical message for display in the UI
+    pub fn user_message(&self) -> &'static str {
+        match self {
+            Self::Config(_) => "No AI provider is configured. Check your settings.",
+            Self::Auth(_) => "The AI provider rejected the API key.",
+            Self::RateLimited { .. } => {
+                "The AI provider is rate limiting requests. Try again shortly."
+            }
+            Self::Timeout => "The AI provider took too long to respond.",
+            Self::Network(_) => "Couldn't reach the AI provider. Check your connection.",
+            Self::Provider { .. } => "The AI provider returned an error. Try again.",
+            Self::Parse(_) => "The AI provider sent a response that couldn't be read.",
+            Self::Other(_) => "Something went wrong. Try again.",
+        }
+    }
+}
+
+/// Parse a `Retry-After` header given in seconds. HTTP-date values are
+/// treated as absent, which falls back to the retry policy's own backoff.
+pub fn parse_retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+impl ChatError {
+    /// Classify an error produced by rig or reqwest by walking its source chain.
+    ///
+    /// Rig's streaming error type is not exported, so streamed failures are
+    /// handled through `dyn Error` rather than a `From` impl.
+    pub fn classify(err: &(dyn std::error::Error + 'static)) -> Self {
+        let mut current = Some(err);
+        while let Some(err) = current {
+            if let Some(err) = err.downcast_ref::<ChatError>() {
+                return err.clone();
+            }
+            if let Some(err) = err.downcast_ref::<reqwest::Error>() {
+                return Self::from_reqwest(err);
+            }
+            if let Some(err) = err.downcast_ref::<http_client::Error>() {
+                match err {
+                    http_client::Error::InvalidStatusCode(status) => {
+                        return Self::from_status(status.as_u16(), status.to_string(), None);
+                    }
+                    http_client::Error::InvalidStatusCodeWithMessage(status, body) => {
+                        return Self::from_status(status.as_u16(), body.clone(), None);
+                    }
+                    http_client::Error::InvalidContentType(_) => {
+                        return Self::Parse(err.to_string());
+                    }
+                    http_client::Error::Instance(inner) => {
+                        if let Some(inner) = inner.downcast_ref::<reqwest::Error>() {
+                            return Self::from_reqwest(inner);
+                        }
+                        return Self::Network(inner.to_string());
+                    }
+                    other => return Self::Network(other.to_string()),
+                }
+            }
+            if let Some(err) = err.downcast_ref::<CompletionError>() {
+                match err {
+                    CompletionError::JsonError(err) => return Self::Parse(err.to_string()),
+                    CompletionError::ResponseError(msg) => return Self::Parse(msg.clone()),
+                    CompletionError::ProviderError(body) => {
+                        return classify_provider_body(body.clone());
+                    }
+                    CompletionError::RequestError(inner) => {
+                        if let Some(inner) = inner.downcast_ref::<reqwest::Error>() {
+                            return Self::from_reqwest(inner);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            if let Some(err) = err.downcast_ref::<ToolSetError>() {
+                return Self::Other(format!("Tool error: {}", err));
+            }
+            if let Some(err) = err.downcast_ref::<PromptError>()
+                && matches!(err, PromptError::MaxDepthError { .. })
+            {
+                return Self::Other(err.to_string());
+            }
+            current = err.source();
+        }
+        Self::Other(err.to_string())
+    }
+
+    fn from_reqwest(err: &reqwest::Error) -> Self {
+        if err.is_timeout() {
+            Self::Timeout
+        } else if let Some(status) = err.status() {
+            Self::from_status(status.as_u16(), err.to_string(), None)
+        } else if err.is_decode() {
+            Self::Parse(err.to_string())
+        } else if err.is_connect() || err.is_request() || err.is_body() {
+            Self::Network(err.to_string())
+        } else {
+            Self::Other(err.to_string())
+        }
+    }
+}
+
+impl From<reqwest::Error> for ChatError {
+    fn from(err: reqwest::Error) -> Self {
+        Self::from_reqwest(&err)
+    }
+}
+
+impl From<CompletionError> for ChatError {
+    fn from(err: CompletionError) -> Self {
+        Self::classify(&err)
+    }
+}
+
+impl From<PromptError> for ChatError {
+    fn from(err: PromptError) -> Self {
+        Self::classify(&err)
+    }
+}
+
+impl From<ToolSetError> for ChatError {
+    fn from(err: ToolSetError) -> Self {
+        Self::classify(&err)
+    }
+}
+
+impl From<anyhow::Error> for ChatError {
+    fn from(err: anyhow::Error) -> Self {
+        Self::classify(err.as_ref())
+    }
+}
+
+/// Rig reports non-streaming HTTP failures as the raw response body without
+/// the status code, so recognise the common auth and rate-limit payloads.
+fn classify_provider_body(body: String) -> ChatError {
+    let lower = body.to_ascii_lowercase();
+    if lower.contains("rate_limit") || lower.contains("rate limit") {
+        ChatError::RateLimited { retry_after: None }
+    } else if lower.contains("invalid_api_key")
+        || lower.contains("authentication_error")
+        || lower.contains("incorrect api key")
+    {
+        ChatError::Auth(body)
+    } else {
+        ChatError::Provider { status: None, body }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status_classification() {
+        assert!(matches!(
+            ChatError::from_status(401, "", None),
+            ChatError::Auth(_)
+        ));
+        assert!(matches!(
+            ChatError::from_status(429, "", Some(Duration::from_secs(3))),
+            ChatError::RateLimited { retry_after: Some(d) } if d.as_secs() == 3
+        ));
+        assert!(matches!(
+            ChatError::from_status(502, "bad gateway", None),
+            ChatError::Provider {
+                status: Some(502),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_retryable_errors() {
+        assert!(ChatError::Timeout.is_retryable());
+        assert!(ChatError::from_status(503, "", None).is_retryable());
+        assert!(!ChatError::from_status(400, "", None).is_retryable());
+        assert!(!ChatError::Auth("nope".into()).is_retryable());
+    }
+
+    #[test]
+    fn test_provider_body_classification() {
+        let rate = r#"{"error":{"type":"rate_limit_exceeded"}}"#.to_string();
+        assert!(matches!(
+            ChatError::from(CompletionError::ProviderError(rate)),
+            ChatError::RateLimited { .. }
+        ));
+        let auth = r#"{"error":{"code":"invalid_api_key"}}"#.to_string();
+        assert!(matches!(
+            ChatError::from(CompletionError::ProviderError(auth)),
+            ChatError::Auth(_)
+        ));
+    }
+
+    #[test]
+    fn test_anyhow_roundtrip_keeps_variant() {
+        let err: anyhow::Error = ChatError::Timeout.into();
+        assert!(matches!(ChatError::from(err), ChatError::Timeout));
+    }
+}
@@ -0,0 +1,165 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::error::{ChatError, ChatResult};
+use crate::types::{ChatMessage, Role};
+
+/// Directory used when `LLM_FIXTURES_DIR` is not set
+const DEFAULT_FIXTURES_DIR: &str = "tests/fixtures/llm";
+
+/// Whether fixtures are being captured from a real provider or served back
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FixtureMode {
+    /// Forward requests to the configured providers and save each reply
+    Record,
+    /// Answer from saved fixtures without contacting any provider
+    Replay,
+}
+
+/// A request/response pair saved by record mode
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fixture {
+    /// Model that produced the response; informational, not part of the match
+    pub model: String,
+    pub messages: Vec<FixtureMessage>,
+    pub response: String,
+}
+
+/// The parts of a `ChatMessage` that identify a request
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FixtureMessage {
+    pub role: Role,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl From<&ChatMessage> for FixtureMessage {
+    fn from(msg: &ChatMessage) -> Self {
+        Self {
+            role: msg.role.clone(),
+            content: msg.content.clone(),
+            tool_call_id: msg.tool_call_id.clone(),
+        }
+    }
+}
+
+/// Directory of fixtures, one JSON file per request.
+///
+/// Requests are matched on their exact message list (system prompt included),
+/// so fixtures go stale when a prompt changes and must be re-recorded.
+#[derive(Clone, Debug)]
+pub struct FixtureStore {
+    dir: PathBuf,
+}
+
+impl FixtureStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Read `LLM_FIXTURES` (`record` or `replay`) and `LLM_FIXTURES_DIR`.
+    /// Returns `None` when fixtures are disabled.
+    pub fn from_env() -> Option<(FixtureMode, Self)> {
+        let mode = match env::var("LLM_FIXTURES")
+            .ok()?
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "record" => FixtureMode::Record,
+            "replay" => FixtureMode::Replay,
+            other => {
+                tracing::warn!("Ignoring unknown LLM_FIXTURES mode '{}'", other);
+                return None;
+            }
+        };
+        let dir = env::var("LLM_FIXTURES_DIR").unwrap_or_else(|_| DEFAULT_FIXTURES_DIR.into());
+        Some((mode, Self::new(dir)))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Stable identifier for a request, used as the fixture file name
+    pub fn key(messages: &[ChatMessage]) -> String {
+        let canonical: Vec<FixtureMessage> = messages.iter().map(FixtureMessage::from).collect();
+        let json = serde_json::to_string(&canonical).unwrap_or_default();
+        format!("{:016x}", fnv1a(json.as_bytes()))
+    }
+
+    fn path_for(&self, messages: &[ChatMessage]) -> PathBuf {
+        self.dir.join(format!("{}.json", Self::key(messages)))
+    }
+
+    /// The saved fixture for this request, if one was recorded
+    pub fn load(&self, messages: &[ChatMessage]) -> ChatResult<Option<Fixture>> {
+        let path = self.path_for(messages);
+        let raw = match fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(ChatError::new(format!("{}: {}", path.display(), err))),
+        };
+        serde_json::from_str(&raw)
+            .map(Some)
+            .map_err(|err| ChatError::Parse(format!("{}: {}", path.display(), err)))
+    }
+
+    /// Save a response for this request, replacing any earlier recording
+    pub fn save(&self, model: &str, messages: &[ChatMessage], response: &str) -> ChatResult<()> {
+        let fixture = Fixture {
+            model: model.to_string(),
+            messages: messages.iter().map(FixtureMessage::from).collect(),
+            response: response.to_string(),
+        };
+        let json = serde_json::to_string_pretty(&fixture)
+            .map_err(|err| ChatError::Parse(err.to_string()))?;
+
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(self.path_for(messages), json))
+            .map_err(|err| ChatError::new(format!("{}: {}", self.dir.display(), err)))
+    }
+}
+
+/// 64-bit FNV-1a; unlike `DefaultHasher` its output is fixed across Rust releases
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: Role, content: &str) -> ChatMessage {
+        ChatMessage {
+            role,
+            content: content.to_string(),
+            created_at: None,
+            tags: vec!["ignored".to_string()],
+            tool_call_id: None,
+        }
+    }
+
+    #[test]
+    fn test_key_ignores_metadata() {
+        let a = vec![message(Role::User, "hi")];
+        let mut b = a.clone();
+        b[0].tags.clear();
+        assert_eq!(FixtureStore::key(&a), FixtureStore::key(&b));
+
+        let c = vec![message(Role::System, "hi")];
+        assert_ne!(FixtureStore::key(&a), FixtureStore::key(&c));
+    }
+
+    #[test]
+    fn test_fnv1a_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
@@ -7,7 +7,15 @@
 /// # Architecture
 ///
 /// - `client` - Main BlackbirdAI client with streaming support
-/// - `providers` - Provider-specific implementations (Blackbird custom, Rig-based)
+/// - `context` - Context window sizes and history compaction
+/// - `continuation` - Detecting cut-off replies and stitching their continuations
+/// - `error` - Typed `ChatError` classification of provider failures
+/// - `fixtures` - Recorded request/response pairs for offline replay
+/// - `retry` - Retry policy with exponential backoff, and HTTP timeouts
+/// - `router` - Provider failover and per-task model selection
+/// - `stream` - Stream store and push-based subscriptions for in-flight responses
+/// - `structured` - JSON schema output mode and tolerant JSON extraction
+/// - `providers` - Provider-specific implementations (Blackbird custom, Rig-based, mock)
 ///
 /// # Usage
 ///
@@ -21,10 +29,30 @@
 /// # }
 /// ```
 mod client;
+mod context;
+mod continuation;
+mod error;
+mod fixtures;
 mod providers;
+mod retry;
+mod router;
+mod stream;
+mod structured;
 
 // Re-export main types
 pub use client::{
-    BlackbirdAI, ChatError, ChatResult, StreamHandle, chat_reply, chat_reply_stream_poll,
-    chat_reply_stream_start,
+    BlackbirdAI, ChatReply, chat_reply, chat_reply_stream_cancel, chat_reply_stream_poll,
+    chat_reply_stream_start, chat_reply_stream_subscribe,
 };
+pub use context::{estimate_messages, estimate_tokens};
+pub use continuation::{Completion, MAX_CONTINUATIONS, stitch};
+pub use error::{ChatError, ChatResult};
+pub use fixtures::{Fixture, FixtureMessage, FixtureMode, FixtureStore};
+pub use providers::{
+    BlackbirdClient, MockClient, MockRequest, MockResponse, MockToolCall, OpenAICompatibleClient,
+    ProviderClient,
+};
+pub use retry::{RetryPolicy, Timeouts};
+pub use router::{ProviderRoute, ProviderRouter, RouteInfo, Task};
+pub use stream::{StreamEvent, StreamHandle, StreamSubscription};
+pub use structured::{JsonSchema, ResponseFormat, extract_json};
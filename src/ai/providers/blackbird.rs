@@ -1,6 +1,14 @@
-use crate::types::ChatMessage;
-use anyhow::Result;
-use serde::Deserialize;
+use super::sse::SseDecoder;
+use crate::ai::continuation::Completion;
+use crate::ai::error::{ChatError, ChatResult, parse_retry_after};
+use crate::ai::retry::Timeouts;
+use crate::ai::structured::JsonSchema;
+use crate::tools::MAX_TOOL_TURNS;
+use crate::types::{ChatMessage, Role};
+use futures::StreamExt;
+use rig::completion::ToolDefinition;
+use rig::tool::ToolSet;
+use serde::{Deserialize, Serialize};
 
 /// Custom client for Blackbird API endpoint
 pub struct BlackbirdClient {
@@ -14,12 +22,17 @@ pub struct BlackbirdClient {
 // Blackbird API response types
 #[derive(Deserialize)]
 struct BBMessage {
-    content: String,
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<BBToolCall>,
 }
 
 #[derive(Deserialize)]
 struct BBChoice {
     message: Option<BBMessage>,
+    #[serde(default)]
+    finish_reason: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -32,19 +45,129 @@ struct BBResponseContentOnly {
     content: String,
 }
 
-#[derive(serde::Serialize)]
+// Blackbird streaming chunk types
+#[derive(Deserialize)]
+struct BBDelta {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<BBToolCallDelta>,
+}
+
+#[derive(Deserialize)]
+struct BBStreamChoice {
+    delta: Option<BBDelta>,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BBStreamChunk {
+    choices: Vec<BBStreamChoice>,
+}
+
+#[derive(Deserialize)]
+struct BBToolCallDelta {
+    #[serde(default)]
+    index: usize,
+    id: Option<String>,
+    function: Option<BBFunctionDelta>,
+}
+
+#[derive(Deserialize)]
+struct BBFunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+// OpenAI-style tool calling types
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct BBToolCall {
+    id: String,
+    #[serde(rename = "type", default = "function_type")]
+    kind: String,
+    function: BBFunctionCall,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct BBFunctionCall {
+    name: String,
+    /// JSON-encoded arguments, as sent by the model
+    arguments: String,
+}
+
+fn function_type() -> String {
+    "function".to_string()
+}
+
+#[derive(Serialize)]
+struct BBTool {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: ToolDefinition,
+}
+
+/// A message as sent on the wire, which unlike `ChatMessage` can carry tool calls
+#[derive(Serialize)]
+struct BBWireMessage {
+    role: &'static str,
+    content: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<BBToolCall>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+impl From<&ChatMessage> for BBWireMessage {
+    fn from(msg: &ChatMessage) -> Self {
+        let (role, content) = match (&msg.role, &msg.tool_call_id) {
+            (Role::System, _) => ("system", msg.content.clone()),
+            (Role::User, _) => ("user", msg.content.clone()),
+            (Role::Assistant, _) => ("assistant", msg.content.clone()),
+            (Role::Tool, Some(_)) => ("tool", msg.content.clone()),
+            // A tool message must answer a call id, so pass orphaned results as context
+            (Role::Tool, None) => ("user", format!("Tool result:\n{}", msg.content)),
+        };
+        Self {
+            role,
+            content: Some(content),
+            tool_calls: Vec::new(),
+            tool_call_id: msg.tool_call_id.clone().filter(|_| role == "tool"),
+        }
+    }
+}
+
+#[derive(Serialize)]
 struct BlackbirdRequest<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
     tier: Option<&'a str>,
     #[serde(skip_serializing_if = "Option::is_none")]
     model: Option<&'a str>,
-    messages: &'a [ChatMessage],
+    messages: &'a [BBWireMessage],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    tools: &'a [BBTool],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
+}
+
+/// One model turn: text plus any tool calls it requested
+#[derive(Default)]
+struct Turn {
+    content: String,
+    tool_calls: Vec<BBToolCall>,
+    /// The model stopped at the output token limit (`finish_reason: "length"`)
+    truncated: bool,
+}
+
+fn is_length_limit(finish_reason: Option<&str>) -> bool {
+    finish_reason == Some("length")
 }
 
 impl BlackbirdClient {
     pub fn new(endpoint: String, tier: String, model: String, api_key: Option<String>) -> Self {
         Self {
-            client: reqwest::Client::new(),
+            client: Timeouts::from_env().http_client(),
             endpoint,
             tier,
             model,
@@ -52,11 +175,143 @@ impl BlackbirdClient {
         }
     }
 
-    pub async fn complete(&self, messages: &[ChatMessage]) -> Result<String> {
+    /// Model requested when the caller does not pick one
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Use a preconfigured HTTP client (e.g. with custom timeouts)
+    pub fn with_http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Complete a conversation. When `tools` are given, tool calls requested by
+    /// the model are executed and their results sent back until it answers in text.
+    /// `schema` requests structured output via `response_format`.
+    pub async fn complete(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        tools: Option<&ToolSet>,
+        schema: Option<&JsonSchema>,
+    ) -> ChatResult<Completion> {
+        self.run(model, messages, tools, schema, false, |_| {})
+            .await
+    }
+
+    /// Stream a completion, calling `on_chunk` with each piece of text as it arrives.
+    /// Returns the full response once the stream ends.
+    ///
+    /// Endpoints that ignore `stream: true` and answer with a plain JSON body are
+    /// handled as a single chunk.
+    pub async fn complete_stream<F>(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        tools: Option<&ToolSet>,
+        schema: Option<&JsonSchema>,
+        on_chunk: F,
+    ) -> ChatResult<Completion>
+    where
+        F: FnMut(&str),
+    {
+        self.run(model, messages, tools, schema, true, on_chunk)
+            .await
+    }
+
+    async fn run<F>(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        tools: Option<&ToolSet>,
+        schema: Option<&JsonSchema>,
+        stream: bool,
+        mut on_chunk: F,
+    ) -> ChatResult<Completion>
+    where
+        F: FnMut(&str),
+    {
+        let definitions = match tools {
+            Some(tools) => tools
+                .get_tool_definitions()
+                .await?
+                .into_iter()
+                .map(|function| BBTool {
+                    kind: "function",
+                    function,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let response_format = schema.map(JsonSchema::response_format);
+        let mut wire: Vec<BBWireMessage> = messages.iter().map(BBWireMessage::from).collect();
+        let mut output = String::new();
+
+        for _ in 0..=MAX_TOOL_TURNS {
+            let turn = self
+                .send_turn(
+                    model,
+                    &wire,
+                    &definitions,
+                    response_format.as_ref(),
+                    stream,
+                    &mut on_chunk,
+                )
+                .await?;
+            output.push_str(&turn.content);
+
+            let Some(tools) = tools.filter(|_| !turn.tool_calls.is_empty()) else {
+                return Ok(Completion::new(output, turn.truncated));
+            };
+
+            let calls = turn.tool_calls.clone();
+            wire.push(BBWireMessage {
+                role: "assistant",
+                content: (!turn.content.is_empty()).then_some(turn.content),
+                tool_calls: turn.tool_calls,
+                tool_call_id: None,
+            });
+            for call in calls {
+                let result = tools
+                    .call(&call.function.name, call.function.arguments)
+                    .await
+                    .unwrap_or_else(|err| format!("Error: {}", err));
+                wire.push(BBWireMessage {
+                    role: "tool",
+                    content: Some(result),
+                    tool_calls: Vec::new(),
+                    tool_call_id: Some(call.id),
+                });
+            }
+        }
+
+        Err(ChatError::new(format!(
+            "Blackbird tool loop exceeded {} turns",
+            MAX_TOOL_TURNS
+        )))
+    }
+
+    async fn send_turn<F>(
+        &self,
+        model: &str,
+        messages: &[BBWireMessage],
+        tools: &[BBTool],
+        response_format: Option<&serde_json::Value>,
+        stream: bool,
+        on_chunk: &mut F,
+    ) -> ChatResult<Turn>
+    where
+        F: FnMut(&str),
+    {
         let mut request = self.client.post(&self.endpoint).json(&BlackbirdRequest {
             tier: Some(&self.tier),
-            model: Some(&self.model),
+            model: Some(model),
             messages,
+            tools,
+            stream: stream.then_some(true),
+            response_format: response_format.cloned(),
         });
 
         if let Some(key) = &self.api_key {
@@ -65,26 +320,225 @@ impl BlackbirdClient {
 
         let response = request.send().await?;
         let status = response.status();
-        let body = response.text().await?;
 
         if !status.is_success() {
-            return Err(anyhow::anyhow!("Blackbird API error {}: {}", status, body));
+            let retry_after = parse_retry_after(response.headers());
+            let body = response.text().await.unwrap_or_default();
+            return Err(ChatError::from_status(status.as_u16(), body, retry_after));
+        }
+
+        let is_event_stream = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/event-stream"));
+
+        if !is_event_stream {
+            let turn = parse_completion_body(response.text().await?);
+            if !turn.content.is_empty() {
+                on_chunk(&turn.content);
+            }
+            return Ok(turn);
         }
 
-        // Try OpenAI-shaped response first
-        if let Ok(parsed) = serde_json::from_str::<BBResponseOpenAIShape>(&body)
-            && let Some(choice) = parsed.choices.into_iter().next()
-            && let Some(msg) = choice.message
+        let mut decoder = SseDecoder::default();
+        let mut turn = Turn::default();
+        let mut body = response.bytes_stream();
+
+        'read: while let Some(bytes) = body.next().await {
+            for data in decoder.push(&bytes?) {
+                if data == "[DONE]" {
+                    break 'read;
+                }
+                apply_stream_chunk(&data, &mut turn, on_chunk);
+            }
+        }
+        if let Some(data) = decoder.finish()
+            && data != "[DONE]"
         {
-            return Ok(msg.content);
+            apply_stream_chunk(&data, &mut turn, on_chunk);
+        }
+
+        Ok(turn)
+    }
+}
+
+fn parse_completion_body(body: String) -> Turn {
+    // Try OpenAI-shaped response first
+    if let Ok(parsed) = serde_json::from_str::<BBResponseOpenAIShape>(&body)
+        && let Some(choice) = parsed.choices.into_iter().next()
+        && let Some(msg) = choice.message
+    {
+        return Turn {
+            content: msg.content.unwrap_or_default(),
+            tool_calls: msg.tool_calls,
+            truncated: is_length_limit(choice.finish_reason.as_deref()),
+        };
+    }
+
+    // Try content-only response
+    if let Ok(parsed) = serde_json::from_str::<BBResponseContentOnly>(&body) {
+        return Turn {
+            content: parsed.content,
+            ..Turn::default()
+        };
+    }
+
+    // Fallback to raw body
+    Turn {
+        content: body,
+        ..Turn::default()
+    }
+}
+
+/// Fold one SSE data payload into the turn, forwarding any text to `on_chunk`
+fn apply_stream_chunk<F>(data: &str, turn: &mut Turn, on_chunk: &mut F)
+where
+    F: FnMut(&str),
+{
+    // OpenAI-shaped chunk: choices[0].delta
+    if let Ok(chunk) = serde_json::from_str::<BBStreamChunk>(data) {
+        let Some(choice) = chunk.choices.into_iter().next() else {
+            return;
+        };
+        if is_length_limit(choice.finish_reason.as_deref()) {
+            turn.truncated = true;
         }
+        let Some(delta) = choice.delta else {
+            return;
+        };
+        if let Some(content) = delta.content.filter(|c| !c.is_empty()) {
+            on_chunk(&content);
+            turn.content.push_str(&content);
+        }
+        for call in delta.tool_calls {
+            if turn.tool_calls.len() <= call.index {
+                turn.tool_calls
+                    .resize_with(call.index + 1, BBToolCall::default);
+            }
+            let slot = &mut turn.tool_calls[call.index];
+            slot.kind = function_type();
+            if let Some(id) = call.id {
+                slot.id = id;
+            }
+            if let Some(function) = call.function {
+                if let Some(name) = function.name {
+                    slot.function.name.push_str(&name);
+                }
+                if let Some(arguments) = function.arguments {
+                    slot.function.arguments.push_str(&arguments);
+                }
+            }
+        }
+        return;
+    }
+
+    // Content-only chunk
+    if let Ok(chunk) = serde_json::from_str::<BBResponseContentOnly>(data)
+        && !chunk.content.is_empty()
+    {
+        on_chunk(&chunk.content);
+        turn.content.push_str(&chunk.content);
+    }
+}
 
-        // Try content-only response
-        if let Ok(parsed) = serde_json::from_str::<BBResponseContentOnly>(&body) {
-            return Ok(parsed.content);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(chunks: &[&str]) -> (Turn, String) {
+        let mut turn = Turn::default();
+        let mut streamed = String::new();
+        for data in chunks {
+            apply_stream_chunk(data, &mut turn, &mut |c: &str| streamed.push_str(c));
         }
+        (turn, streamed)
+    }
+
+    #[test]
+    fn test_stream_chunks_openai_shape() {
+        let (turn, streamed) = collect(&[
+            r#"{"choices":[{"delta":{"role":"assistant"}}]}"#,
+            r#"{"choices":[{"delta":{"content":"Hel"}}]}"#,
+            r#"{"choices":[{"delta":{"content":"lo"}}]}"#,
+        ]);
+        assert_eq!(streamed, "Hello");
+        assert_eq!(turn.content, "Hello");
+        assert!(turn.tool_calls.is_empty());
+    }
+
+    #[test]
+    fn test_stream_chunks_content_only() {
+        let (turn, streamed) = collect(&[r#"{"content":"lo"}"#, "not json"]);
+        assert_eq!(streamed, "lo");
+        assert_eq!(turn.content, "lo");
+    }
+
+    #[test]
+    fn test_stream_tool_call_deltas_are_accumulated() {
+        let (turn, streamed) = collect(&[
+            r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"calculate","arguments":""}}]}}]}"#,
+            r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"expression\":"}}]}}]}"#,
+            r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"2+2\"}"}}]}}]}"#,
+        ]);
+        assert!(streamed.is_empty());
+        assert_eq!(turn.tool_calls.len(), 1);
+        assert_eq!(turn.tool_calls[0].id, "call_1");
+        assert_eq!(turn.tool_calls[0].function.name, "calculate");
+        assert_eq!(
+            turn.tool_calls[0].function.arguments,
+            r#"{"expression":"2+2"}"#
+        );
+    }
+
+    #[test]
+    fn test_length_finish_reason_marks_truncation() {
+        let (turn, _) = collect(&[
+            r#"{"choices":[{"delta":{"content":"<html><bo"}}]}"#,
+            r#"{"choices":[{"delta":{},"finish_reason":"length"}]}"#,
+        ]);
+        assert!(turn.truncated);
+
+        let body = r#"{"choices":[{"message":{"content":"<html>"},"finish_reason":"length"}]}"#;
+        assert!(parse_completion_body(body.to_string()).truncated);
+        let body = r#"{"choices":[{"message":{"content":"hi"},"finish_reason":"stop"}]}"#;
+        assert!(!parse_completion_body(body.to_string()).truncated);
+    }
+
+    #[test]
+    fn test_wire_roles() {
+        let message = |role, tool_call_id: Option<&str>| ChatMessage {
+            role,
+            content: "x".to_string(),
+            created_at: None,
+            tags: Vec::new(),
+            tool_call_id: tool_call_id.map(str::to_string),
+        };
+
+        let system = BBWireMessage::from(&message(Role::System, None));
+        assert_eq!(system.role, "system");
+
+        let tool = BBWireMessage::from(&message(Role::Tool, Some("call_1")));
+        assert_eq!(tool.role, "tool");
+        assert_eq!(tool.tool_call_id.as_deref(), Some("call_1"));
+
+        let orphan = BBWireMessage::from(&message(Role::Tool, None));
+        assert_eq!(orphan.role, "user");
+        assert!(orphan.tool_call_id.is_none());
+    }
+
+    #[test]
+    fn test_parse_completion_body_fallbacks() {
+        let openai = r#"{"choices":[{"message":{"content":"hi"}}]}"#.to_string();
+        assert_eq!(parse_completion_body(openai).content, "hi");
+        assert_eq!(parse_completion_body("plain".to_string()).content, "plain");
+    }
 
-        // Fallback to raw body
-        Ok(body)
+    #[test]
+    fn test_parse_completion_body_tool_calls() {
+        let body = r#"{"choices":[{"message":{"content":null,"tool_calls":[{"id":"call_1","type":"function","function":{"name":"get_setting","arguments":"{\"setting\":\"theme\"}"}}]}}]}"#;
+        let turn = parse_completion_body(body.to_string());
+        assert!(turn.content.is_empty());
+        assert_eq!(turn.tool_calls[0].function.name, "get_setting");
     }
 }
@@ -0,0 +1,328 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::ai::continuation::Completion;
+use crate::ai::error::{ChatError, ChatResult};
+use crate::ai::fixtures::FixtureStore;
+use crate::ai::structured::JsonSchema;
+use crate::tools::MAX_TOOL_TURNS;
+use crate::types::{ChatMessage, Role};
+use rig::tool::ToolSet;
+
+/// One scripted model turn
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockResponse {
+    /// Answer with this text
+    Text(String),
+    /// Answer with this text and report that the output limit cut it off
+    Truncated(String),
+    /// Request tool calls; their results are sent back and the next
+    /// scripted response answers them
+    ToolCalls(Vec<MockToolCall>),
+    /// Fail before producing any output
+    Error(ChatError),
+    /// Stream `partial`, then fail with `error`
+    Interrupted { partial: String, error: ChatError },
+}
+
+impl MockResponse {
+    pub fn text(content: impl Into<String>) -> Self {
+        Self::Text(content.into())
+    }
+
+    pub fn tool_call(name: impl Into<String>, arguments: serde_json::Value) -> Self {
+        Self::ToolCalls(vec![MockToolCall {
+            name: name.into(),
+            arguments: arguments.to_string(),
+        }])
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockToolCall {
+    pub name: String,
+    /// JSON-encoded arguments, as a model would send them
+    pub arguments: String,
+}
+
+/// A request the mock received, one per model turn
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    /// Schema requested for structured output, if any
+    pub schema: Option<JsonSchema>,
+}
+
+#[derive(Default)]
+struct MockState {
+    script: Mutex<VecDeque<MockResponse>>,
+    requests: Mutex<Vec<MockRequest>>,
+}
+
+/// Deterministic provider for tests and offline runs.
+///
+/// Answers from a script of [`MockResponse`]s, or from recorded fixtures in
+/// replay mode. Clones share the script and request log, so a test can keep a
+/// clone to inspect what the client sent.
+#[derive(Clone)]
+pub struct MockClient {
+    state: Arc<MockState>,
+    fixtures: Option<FixtureStore>,
+    model: String,
+    chunk_size: usize,
+    chunk_delay: Duration,
+}
+
+impl Default for MockClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockClient {
+    /// Empty script; requests fail until responses are pushed
+    pub fn new() -> Self {
+        Self {
+            state: Arc::default(),
+            fixtures: None,
+            model: "mock".to_string(),
+            chunk_size: usize::MAX,
+            chunk_delay: Duration::ZERO,
+        }
+    }
+
+    /// Answer every request from the fixtures recorded in `store`
+    pub fn replay(store: FixtureStore) -> Self {
+        Self {
+            fixtures: Some(store),
+            model: "replay".to_string(),
+            ..Self::new()
+        }
+    }
+
+    pub fn with_responses(self, responses: impl IntoIterator<Item = MockResponse>) -> Self {
+        for response in responses {
+            self.push(response);
+        }
+        self
+    }
+
+    /// Stream text in pieces of `size` characters, waiting `delay` before each
+    pub fn with_chunking(mut self, size: usize, delay: Duration) -> Self {
+        self.chunk_size = size.max(1);
+        self.chunk_delay = delay;
+        self
+    }
+
+    /// Add a response to the end of the script
+    pub fn push(&self, response: MockResponse) {
+        self.state
+            .script
+            .lock()
+            .expect("mock script poisoned")
+            .push_back(response);
+    }
+
+    /// Every request received so far, oldest first
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state
+            .requests
+            .lock()
+            .expect("mock requests poisoned")
+            .clone()
+    }
+
+    /// Scripted responses not yet consumed
+    pub fn remaining(&self) -> usize {
+        self.state
+            .script
+            .lock()
+            .expect("mock script poisoned")
+            .len()
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub async fn complete(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        tools: Option<&ToolSet>,
+        schema: Option<&JsonSchema>,
+    ) -> ChatResult<Completion> {
+        self.complete_stream(model, messages, tools, schema, |_| {})
+            .await
+    }
+
+    /// Run the conversation, calling `on_chunk` as text is "generated".
+    /// Tool calls are executed against `tools` like a real provider would.
+    pub async fn complete_stream<F>(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        tools: Option<&ToolSet>,
+        schema: Option<&JsonSchema>,
+        mut on_chunk: F,
+    ) -> ChatResult<Completion>
+    where
+        F: FnMut(&str),
+    {
+        let mut conversation = messages.to_vec();
+        let mut output = String::new();
+
+        for turn in 0..=MAX_TOOL_TURNS {
+            match self.next_response(model, &conversation, schema)? {
+                MockResponse::Text(content) => {
+                    self.emit(&content, &mut on_chunk).await;
+                    output.push_str(&content);
+                    return Ok(Completion::new(output, false));
+                }
+                MockResponse::Truncated(content) => {
+                    self.emit(&content, &mut on_chunk).await;
+                    output.push_str(&content);
+                    return Ok(Completion::new(output, true));
+                }
+                MockResponse::Error(err) => return Err(err),
+                MockResponse::Interrupted { partial, error } => {
+                    self.emit(&partial, &mut on_chunk).await;
+                    return Err(error);
+                }
+                MockResponse::ToolCalls(calls) => {
+                    let Some(tools) = tools else {
+                        return Err(ChatError::new("Mock tool call without tools"));
+                    };
+                    for (index, call) in calls.into_iter().enumerate() {
+                        let result = tools
+                            .call(&call.name, call.arguments)
+                            .await
+                            .unwrap_or_else(|err| format!("Error: {}", err));
+                        conversation.push(ChatMessage {
+                            role: Role::Tool,
+                            content: result,
+                            created_at: None,
+                            tags: Vec::new(),
+                            tool_call_id: Some(format!("call_{}_{}", turn, index)),
+                        });
+                    }
+                }
+            }
+        }
+
+        Err(ChatError::new(format!(
+            "Mock tool loop exceeded {} turns",
+            MAX_TOOL_TURNS
+        )))
+    }
+
+    fn next_response(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        schema: Option<&JsonSchema>,
+    ) -> ChatResult<MockResponse> {
+        self.state
+            .requests
+            .lock()
+            .expect("mock requests poisoned")
+            .push(MockRequest {
+                model: model.to_string(),
+                messages: messages.to_vec(),
+                schema: schema.cloned(),
+            });
+
+        if let Some(store) = &self.fixtures {
+            return match store.load(messages)? {
+                Some(fixture) => Ok(MockResponse::Text(fixture.response)),
+                None => Err(ChatError::Config(format!(
+                    "No fixture {} in {}; record it with LLM_FIXTURES=record",
+                    FixtureStore::key(messages),
+                    store.dir().display()
+                ))),
+            };
+        }
+
+        self.state
+            .script
+            .lock()
+            .expect("mock script poisoned")
+            .pop_front()
+            .ok_or_else(|| ChatError::new("Mock script exhausted"))
+    }
+
+    async fn emit<F>(&self, text: &str, on_chunk: &mut F)
+    where
+        F: FnMut(&str),
+    {
+        let chars: Vec<char> = text.chars().collect();
+        for piece in chars.chunks(self.chunk_size) {
+            if !self.chunk_delay.is_zero() {
+                tokio::time::sleep(self.chunk_delay).await;
+            }
+            on_chunk(&piece.iter().collect::<String>());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(content: &str) -> Vec<ChatMessage> {
+        vec![ChatMessage {
+            role: Role::User,
+            content: content.to_string(),
+            created_at: None,
+            tags: Vec::new(),
+            tool_call_id: None,
+        }]
+    }
+
+    #[tokio::test]
+    async fn test_streams_in_chunks() {
+        let mock = MockClient::new()
+            .with_responses([MockResponse::text("abcdefg")])
+            .with_chunking(3, Duration::ZERO);
+
+        let mut chunks = Vec::new();
+        let full = mock
+            .complete_stream("m", &user("hi"), None, None, |chunk| {
+                chunks.push(chunk.to_string())
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(full.content, "abcdefg");
+        assert_eq!(chunks, ["abc", "def", "g"]);
+        assert_eq!(mock.requests()[0].model, "m");
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_emits_partial_then_fails() {
+        let mock = MockClient::new().with_responses([MockResponse::Interrupted {
+            partial: "half".into(),
+            error: ChatError::Network("reset".into()),
+        }]);
+
+        let mut seen = String::new();
+        let err = mock
+            .complete_stream("m", &user("hi"), None, None, |chunk| seen.push_str(chunk))
+            .await
+            .unwrap_err();
+
+        assert_eq!(seen, "half");
+        assert_eq!(err, ChatError::Network("reset".into()));
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_script_is_an_error() {
+        let err = MockClient::new()
+            .complete("m", &user("hi"), None, None)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("exhausted"));
+    }
+}
@@ -1,10 +1,17 @@
 pub mod blackbird;
+pub mod mock;
+pub mod openai_compatible;
+mod sse;
 
-use anyhow::Result;
+use super::error::{ChatError, ChatResult};
+use super::fixtures::{FixtureMode, FixtureStore};
+use super::retry::Timeouts;
 use rig::providers;
 use std::env;
 
 pub use blackbird::BlackbirdClient;
+pub use mock::{MockClient, MockRequest, MockResponse, MockToolCall};
+pub use openai_compatible::OpenAICompatibleClient;
 
 /// Enum to hold different provider clients
 pub enum ProviderClient {
@@ -12,34 +19,76 @@ pub enum ProviderClient {
     Anthropic(providers::anthropic::Client),
     Ollama(providers::ollama::Client),
     Blackbird(BlackbirdClient),
+    OpenAICompatible(OpenAICompatibleClient),
+    /// Scripted or replayed responses, for tests and offline runs
+    Mock(MockClient),
 }
 
 impl ProviderClient {
     /// Auto-detect and configure provider from environment variables
-    pub fn from_env() -> Result<Self> {
+    pub fn from_env() -> ChatResult<Self> {
+        Ok(Self::all_from_env()?.remove(0))
+    }
+
+    /// Every provider configured in the environment, highest priority first
+    pub fn all_from_env() -> ChatResult<Vec<Self>> {
         // Priority order:
-        // 1. BLACKBIRD_ENDPOINT → Blackbird API
-        // 2. OPENAI_API_KEY → OpenAI
-        // 3. ANTHROPIC_API_KEY → Claude
-        // 4. LLM_USE_OLLAMA=true → Ollama
+        // 1. LLM_ENDPOINT → OpenAI-compatible server
+        // 2. BLACKBIRD_ENDPOINT → Blackbird API
+        // 3. OPENAI_API_KEY → OpenAI
+        // 4. ANTHROPIC_API_KEY → Claude
+        // 5. LLM_USE_OLLAMA=true → Ollama
+        //
+        // LLM_FIXTURES=replay bypasses all of them and answers from recorded fixtures
+        if let Some((FixtureMode::Replay, store)) = FixtureStore::from_env() {
+            return Ok(vec![Self::Mock(MockClient::replay(store))]);
+        }
+
+        let timeouts = Timeouts::from_env();
+        let http = timeouts.http_client();
+        let mut clients = Vec::new();
+
+        // An explicit endpoint wins over the bundled Blackbird default
+        if let Ok(endpoint) = env::var("LLM_ENDPOINT") {
+            let api_key = env::var("LLM_API_KEY").ok();
+            let model = env::var("LLM_MODEL").unwrap_or_else(|_| "default".to_string());
+            let headers = env::var("LLM_EXTRA_HEADERS")
+                .map(|raw| openai_compatible::parse_headers(&raw))
+                .unwrap_or_default();
+
+            clients.push(Self::OpenAICompatible(OpenAICompatibleClient::new(
+                &endpoint,
+                api_key.as_deref(),
+                model,
+                &headers,
+                timeouts,
+            )?));
+        }
 
-        // Check for Blackbird endpoint first
         if let Ok(endpoint) = env::var("BLACKBIRD_ENDPOINT") {
             let tier = env::var("BLACKBIRD_TIER").unwrap_or_else(|_| "ultra".to_string());
             let model = env::var("BLACKBIRD_MODEL").unwrap_or_else(|_| "gpt-oss-120b".to_string());
             let api_key = env::var("BLACKBIRD_API_KEY").ok();
 
-            return Ok(Self::Blackbird(BlackbirdClient::new(
-                endpoint, tier, model, api_key,
-            )));
+            clients.push(Self::Blackbird(
+                BlackbirdClient::new(endpoint, tier, model, api_key).with_http_client(http.clone()),
+            ));
         }
 
         if let Ok(key) = env::var("OPENAI_API_KEY") {
-            return Ok(Self::OpenAI(providers::openai::Client::new(&key)));
+            clients.push(Self::OpenAI(
+                providers::openai::Client::builder(&key)
+                    .with_client(http.clone())
+                    .build(),
+            ));
         }
 
         if let Ok(key) = env::var("ANTHROPIC_API_KEY") {
-            return Ok(Self::Anthropic(providers::anthropic::Client::new(&key)));
+            let client = providers::anthropic::Client::builder(&key)
+                .with_client(http.clone())
+                .build()
+                .map_err(|e| ChatError::Config(e.to_string()))?;
+            clients.push(Self::Anthropic(client));
         }
 
         let use_ollama = env::var("LLM_USE_OLLAMA")
@@ -49,11 +98,62 @@ impl ProviderClient {
         if matches!(use_ollama.as_str(), "1" | "true" | "yes" | "on") {
             // Ollama endpoint is configured via OLLAMA_HOST environment variable
             // The Rig client reads this automatically (defaults to http://localhost:11434)
-            return Ok(Self::Ollama(providers::ollama::Client::new()));
+            clients.push(Self::Ollama(
+                providers::ollama::Client::builder()
+                    .with_client(http)
+                    .build(),
+            ));
         }
 
-        Err(anyhow::anyhow!(
-            "No AI provider configured. Set BLACKBIRD_ENDPOINT, OPENAI_API_KEY, ANTHROPIC_API_KEY, or LLM_USE_OLLAMA=true"
-        ))
+        if clients.is_empty() {
+            return Err(ChatError::Config(
+                "No AI provider configured. Set LLM_ENDPOINT, BLACKBIRD_ENDPOINT, OPENAI_API_KEY, ANTHROPIC_API_KEY, or LLM_USE_OLLAMA=true".to_string(),
+            ));
+        }
+        Ok(clients)
+    }
+
+    /// Display name reported alongside responses
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::OpenAI(_) => "OpenAI",
+            Self::Anthropic(_) => "Anthropic",
+            Self::Ollama(_) => "Ollama",
+            Self::Blackbird(_) => "Blackbird",
+            Self::OpenAICompatible(_) => "OpenAI-compatible",
+            Self::Mock(_) => "Mock",
+        }
+    }
+
+    /// `(strong, cheap)` models from the environment. Providers without a
+    /// cheap model configured use the strong model for everything.
+    pub fn default_models(&self) -> (String, String) {
+        let (strong, cheap_var, cheap_default) = match self {
+            Self::OpenAI(_) => (
+                env::var("OPENAI_MODEL").unwrap_or_else(|_| "gpt-4o".to_string()),
+                "OPENAI_CHEAP_MODEL",
+                Some("gpt-4o-mini"),
+            ),
+            Self::Anthropic(_) => (
+                env::var("ANTHROPIC_MODEL")
+                    .unwrap_or_else(|_| "claude-3-5-sonnet-20241022".to_string()),
+                "ANTHROPIC_CHEAP_MODEL",
+                Some("claude-3-5-haiku-20241022"),
+            ),
+            Self::Ollama(_) => (
+                env::var("LLM_MODEL").unwrap_or_else(|_| "llama3.1:latest".to_string()),
+                "LLM_CHEAP_MODEL",
+                None,
+            ),
+            Self::Blackbird(client) => (client.model().to_string(), "BLACKBIRD_CHEAP_MODEL", None),
+            Self::OpenAICompatible(client) => (client.model().to_string(), "LLM_CHEAP_MODEL", None),
+            Self::Mock(client) => return (client.model().to_string(), client.model().to_string()),
+        };
+
+        let cheap = env::var(cheap_var)
+            .ok()
+            .or_else(|| cheap_default.map(str::to_string))
+            .unwrap_or_else(|| strong.clone());
+        (strong, cheap)
     }
 }
@@ -0,0 +1,109 @@
+use crate::ai::error::{ChatError, ChatResult};
+use crate::ai::retry::Timeouts;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use rig::client::CompletionClient;
+use rig::providers::openai;
+
+/// Client for any server speaking the OpenAI chat completions API
+/// (vLLM, llama.cpp server, LM Studio, LiteLLM and similar gateways)
+pub struct OpenAICompatibleClient {
+    client: openai::Client,
+    base_url: String,
+    model: String,
+}
+
+impl OpenAICompatibleClient {
+    /// `base_url` is the API root, e.g. `http://localhost:8000/v1`.
+    /// `headers` are sent with every request in addition to the bearer key.
+    pub fn new(
+        base_url: &str,
+        api_key: Option<&str>,
+        model: String,
+        headers: &[(String, String)],
+        timeouts: Timeouts,
+    ) -> ChatResult<Self> {
+        let mut header_map = HeaderMap::new();
+        for (name, value) in headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| ChatError::Config(format!("Invalid header name {}: {}", name, e)))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|e| ChatError::Config(format!("Invalid value for {}: {}", name, e)))?;
+            header_map.insert(name, value);
+        }
+
+        let http = reqwest::Client::builder()
+            .connect_timeout(timeouts.connect)
+            .read_timeout(timeouts.read)
+            .default_headers(header_map)
+            .build()
+            .map_err(|e| ChatError::Config(e.to_string()))?;
+
+        let base_url = base_url.trim_end_matches('/').to_string();
+        // Local servers usually accept any key; rig always sends one
+        let client = openai::Client::builder(api_key.unwrap_or_default())
+            .base_url(&base_url)
+            .with_client(http)
+            .build();
+
+        Ok(Self {
+            client,
+            base_url,
+            model,
+        })
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Model requested when the caller does not pick one
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Completion model using the chat completions endpoint, which
+    /// compatible servers implement (unlike OpenAI's newer Responses API)
+    pub fn completion_model(&self, model: &str) -> openai::CompletionModel {
+        self.client.completion_model(model).completions_api()
+    }
+}
+
+/// Parse `LLM_EXTRA_HEADERS`, a comma-separated list of `Name=Value` pairs
+pub fn parse_headers(raw: &str) -> Vec<(String, String)> {
+    raw.split(',')
+        .filter_map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            let name = name.trim();
+            (!name.is_empty()).then(|| (name.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_headers() {
+        let headers = parse_headers("X-Org=acme, X-Route = gpu-1,,broken");
+        assert_eq!(
+            headers,
+            vec![
+                ("X-Org".to_string(), "acme".to_string()),
+                ("X-Route".to_string(), "gpu-1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_header_is_config_error() {
+        let result = OpenAICompatibleClient::new(
+            "http://localhost:8000/v1/",
+            None,
+            "local".to_string(),
+            &[("bad header".to_string(), "x".to_string())],
+            Timeouts::default(),
+        );
+        assert!(matches!(result, Err(ChatError::Config(_))));
+    }
+}
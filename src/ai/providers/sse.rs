@@ -0,0 +1,95 @@
+/// Incremental decoder for `text/event-stream` response bodies.
+///
+/// Bytes are fed in as they arrive from the network; complete events are
+/// returned as their joined `data:` payloads. Partial lines (including UTF-8
+/// sequences split across chunks) are buffered until the next push.
+#[derive(Default)]
+pub struct SseDecoder {
+    pending: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    /// Feed raw bytes and return the data payloads of every event completed by them
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(bytes);
+
+        let mut events = Vec::new();
+        while let Some(pos) = self.pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(self.data.join("\n"));
+                    self.data.clear();
+                }
+                continue;
+            }
+
+            if let Some(value) = line.strip_prefix("data:") {
+                self.data
+                    .push(value.strip_prefix(' ').unwrap_or(value).to_string());
+            }
+            // Comments (`:`), `event:`, `id:` and `retry:` fields are not needed
+        }
+        events
+    }
+
+    /// Flush an event left unterminated when the stream closed
+    pub fn finish(&mut self) -> Option<String> {
+        if !self.pending.is_empty() {
+            // Terminate the dangling line so it is parsed like any other field
+            let mut tail = std::mem::take(&mut self.pending);
+            tail.push(b'\n');
+            if let Some(event) = self.push(&tail).pop() {
+                return Some(event);
+            }
+        }
+        if self.data.is_empty() {
+            None
+        } else {
+            let event = self.data.join("\n");
+            self.data.clear();
+            Some(event)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decodes_events_split_across_chunks() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.push(b"data: {\"a\"").is_empty());
+        let events = decoder.push(b":1}\n\ndata: [DONE]\n\n");
+        assert_eq!(events, vec!["{\"a\":1}".to_string(), "[DONE]".to_string()]);
+    }
+
+    #[test]
+    fn test_ignores_comments_and_crlf() {
+        let mut decoder = SseDecoder::default();
+        let events = decoder.push(b": keep-alive\r\n\r\nevent: delta\r\ndata: hi\r\n\r\n");
+        assert_eq!(events, vec!["hi".to_string()]);
+    }
+
+    #[test]
+    fn test_multibyte_split_is_preserved() {
+        let mut decoder = SseDecoder::default();
+        let bytes = "data: héllo\n\n".as_bytes();
+        let (first, second) = bytes.split_at(8);
+        assert!(decoder.push(first).is_empty());
+        assert_eq!(decoder.push(second), vec!["héllo".to_string()]);
+    }
+
+    #[test]
+    fn test_finish_flushes_unterminated_event() {
+        let mut decoder = SseDecoder::default();
+        assert!(decoder.push(b"data: tail").is_empty());
+        assert_eq!(decoder.finish(), Some("tail".to_string()));
+        assert_eq!(decoder.finish(), None);
+    }
+}
@@ -0,0 +1,247 @@
+use std::env;
+use std::future::Future;
+use std::time::Duration;
+
+use super::error::{ChatError, ChatResult};
+
+/// How failed provider requests are retried.
+///
+/// Retryable errors (see [`ChatError::is_retryable`]) are retried with
+/// exponential backoff: `base_delay * 2^attempt`, capped at `max_delay`.
+/// A `Retry-After` from the provider replaces the computed delay; if it is
+/// longer than `max_delay` the error is returned instead of waiting.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Read `LLM_MAX_RETRIES`, `LLM_RETRY_BASE_MS` and `LLM_RETRY_MAX_MS`,
+    /// falling back to the defaults for unset or invalid values
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            max_retries: env_parse("LLM_MAX_RETRIES").unwrap_or(default.max_retries),
+            base_delay: env_parse("LLM_RETRY_BASE_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(default.base_delay),
+            max_delay: env_parse("LLM_RETRY_MAX_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(default.max_delay),
+        }
+    }
+
+    /// Delay before retry number `attempt` (0-based), or `None` to give up
+    pub fn delay_for(&self, attempt: u32, err: &ChatError) -> Option<Duration> {
+        if attempt >= self.max_retries || !err.is_retryable() {
+            return None;
+        }
+
+        match err.retry_after() {
+            Some(wait) if wait > self.max_delay => None,
+            Some(wait) => Some(wait),
+            None => {
+                let factor = 2u32.saturating_pow(attempt);
+                Some(self.base_delay.saturating_mul(factor).min(self.max_delay))
+            }
+        }
+    }
+
+    /// Run `op` until it succeeds, fails with a non-retryable error, or the
+    /// retry budget is spent
+    pub async fn run<T, F, Fut>(&self, op: F) -> ChatResult<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = ChatResult<T>>,
+    {
+        self.run_while(|| true, op).await
+    }
+
+    /// Like [`run`](Self::run), but only retries while `can_retry` holds.
+    /// Streaming callers use this to stop retrying once output has been emitted.
+    pub async fn run_while<T, C, F, Fut>(&self, mut can_retry: C, mut op: F) -> ChatResult<T>
+    where
+        C: FnMut() -> bool,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = ChatResult<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let err = match op().await {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+
+            let Some(delay) = self.delay_for(attempt, &err).filter(|_| can_retry()) else {
+                return Err(err);
+            };
+
+            tracing::warn!(
+                "AI request failed ({}), retrying in {:?} (attempt {}/{})",
+                err,
+                delay,
+                attempt + 1,
+                self.max_retries
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Connect and read timeouts applied to every provider HTTP client
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Timeouts {
+    pub connect: Duration,
+    /// Maximum gap between bytes of a response, so long streams are not cut off
+    pub read: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(10),
+            read: Duration::from_secs(120),
+        }
+    }
+}
+
+impl Timeouts {
+    /// Read `LLM_CONNECT_TIMEOUT_SECS` and `LLM_READ_TIMEOUT_SECS`
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            connect: env_parse("LLM_CONNECT_TIMEOUT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(default.connect),
+            read: env_parse("LLM_READ_TIMEOUT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(default.read),
+        }
+    }
+
+    /// Build an HTTP client with these timeouts
+    pub fn http_client(&self) -> reqwest::Client {
+        reqwest::Client::builder()
+            .connect_timeout(self.connect)
+            .read_timeout(self.read)
+            .build()
+            .unwrap_or_else(|err| {
+                tracing::error!("Failed to build HTTP client, using defaults: {}", err);
+                reqwest::Client::new()
+            })
+    }
+}
+
+fn env_parse<T: std::str::FromStr>(key: &str) -> Option<T> {
+    env::var(key).ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn fast_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(50),
+        }
+    }
+
+    #[test]
+    fn test_backoff_is_exponential_and_capped() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+        };
+        let err = ChatError::Timeout;
+        assert_eq!(policy.delay_for(0, &err), Some(Duration::from_millis(100)));
+        assert_eq!(policy.delay_for(1, &err), Some(Duration::from_millis(200)));
+        assert_eq!(policy.delay_for(3, &err), Some(Duration::from_millis(800)));
+        assert_eq!(policy.delay_for(4, &err), Some(Duration::from_millis(1000)));
+        assert_eq!(policy.delay_for(10, &err), None);
+    }
+
+    #[test]
+    fn test_retry_after_overrides_backoff() {
+        let policy = RetryPolicy::default();
+        let soon = ChatError::RateLimited {
+            retry_after: Some(Duration::from_secs(2)),
+        };
+        assert_eq!(policy.delay_for(0, &soon), Some(Duration::from_secs(2)));
+
+        let too_long = ChatError::RateLimited {
+            retry_after: Some(Duration::from_secs(3600)),
+        };
+        assert_eq!(policy.delay_for(0, &too_long), None);
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_on_non_retryable_error() {
+        let calls = AtomicU32::new(0);
+        let result: ChatResult<()> = fast_policy(3)
+            .run(|| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(ChatError::Auth("bad key".into()))
+            })
+            .await;
+        assert!(matches!(result, Err(ChatError::Auth(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_run_retries_until_success() {
+        let calls = AtomicU32::new(0);
+        let result = fast_policy(3)
+            .run(|| async {
+                if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(ChatError::Network("reset".into()))
+                } else {
+                    Ok("done")
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), "done");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_run_while_respects_guard() {
+        let calls = AtomicU32::new(0);
+        let result: ChatResult<()> = fast_policy(3)
+            .run_while(
+                || false,
+                || async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Err(ChatError::Timeout)
+                },
+            )
+            .await;
+        assert!(matches!(result, Err(ChatError::Timeout)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
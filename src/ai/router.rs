@@ -0,0 +1,237 @@
+use std::fmt;
+use std::future::Future;
+
+use super::error::{ChatError, ChatResult};
+use super::providers::ProviderClient;
+use super::retry::RetryPolicy;
+
+/// What a request is for, which decides the model it is sent to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Task {
+    /// Conversational replies
+    #[default]
+    Chat,
+    /// App builds driven by the app builder prompt
+    Build,
+    /// Short title/tag extraction, where a cheap model is good enough
+    Extraction,
+}
+
+/// The provider and model that served a response
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RouteInfo {
+    pub provider: String,
+    pub model: String,
+}
+
+impl fmt::Display for RouteInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} · {}", self.provider, self.model)
+    }
+}
+
+/// A configured provider with the models used for each task
+pub struct ProviderRoute {
+    pub client: ProviderClient,
+    /// Used for builds and chat
+    pub strong_model: String,
+    /// Used for extraction
+    pub cheap_model: String,
+}
+
+impl ProviderRoute {
+    /// Route with the models configured in the environment for this provider
+    pub fn new(client: ProviderClient) -> Self {
+        let (strong_model, cheap_model) = client.default_models();
+        Self {
+            client,
+            strong_model,
+            cheap_model,
+        }
+    }
+
+    pub fn with_models(mut self, strong: impl Into<String>, cheap: impl Into<String>) -> Self {
+        self.strong_model = strong.into();
+        self.cheap_model = cheap.into();
+        self
+    }
+
+    pub fn model_for(&self, task: Task) -> &str {
+        match task {
+            Task::Chat | Task::Build => &self.strong_model,
+            Task::Extraction => &self.cheap_model,
+        }
+    }
+
+    pub fn info(&self, task: Task) -> RouteInfo {
+        RouteInfo {
+            provider: self.client.name().to_string(),
+            model: self.model_for(task).to_string(),
+        }
+    }
+}
+
+/// Ordered list of providers, tried in turn until one answers.
+///
+/// Each provider gets the full retry policy first; the router only moves on
+/// when the error is one another provider could avoid (see
+/// [`ChatError::should_failover`]).
+pub struct ProviderRouter {
+    routes: Vec<ProviderRoute>,
+}
+
+impl ProviderRouter {
+    pub fn new(routes: Vec<ProviderRoute>) -> ChatResult<Self> {
+        if routes.is_empty() {
+            return Err(ChatError::Config("No AI providers to route between".into()));
+        }
+        Ok(Self { routes })
+    }
+
+    /// Router over a single provider, with no failover
+    pub fn single(client: ProviderClient) -> Self {
+        Self {
+            routes: vec![ProviderRoute::new(client)],
+        }
+    }
+
+    /// Every provider configured in the environment, in detection priority order
+    pub fn from_env() -> ChatResult<Self> {
+        Self::new(
+            ProviderClient::all_from_env()?
+                .into_iter()
+                .map(ProviderRoute::new)
+                .collect(),
+        )
+    }
+
+    pub fn routes(&self) -> &[ProviderRoute] {
+        &self.routes
+    }
+
+    /// Run `op` against each provider in order until one succeeds.
+    ///
+    /// `can_continue` is checked before every retry and failover, so callers
+    /// can stop once partial output has been shown or the request cancelled.
+    pub async fn run<'a, T, C, F, Fut>(
+        &'a self,
+        task: Task,
+        retry: &RetryPolicy,
+        mut can_continue: C,
+        mut op: F,
+    ) -> ChatResult<(T, RouteInfo)>
+    where
+        C: FnMut() -> bool,
+        F: FnMut(&'a ProviderRoute, RouteInfo) -> Fut,
+        Fut: Future<Output = ChatResult<T>>,
+    {
+        let mut last_error = None;
+        for (index, route) in self.routes.iter().enumerate() {
+            if index > 0 && !can_continue() {
+                break;
+            }
+
+            let info = route.info(task);
+            let result = retry
+                .run_while(&mut can_continue, || op(route, info.clone()))
+                .await;
+
+            match result {
+                Ok(value) => return Ok((value, info)),
+                Err(err) if err.should_failover() => {
+                    tracing::warn!("{} failed ({}), trying next provider", info, err);
+                    last_error = Some(err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| ChatError::Config("No AI providers available".into())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::providers::BlackbirdClient;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn route(name: &str) -> ProviderRoute {
+        let client = BlackbirdClient::new(
+            format!("http://{}.invalid", name),
+            "tier".to_string(),
+            name.to_string(),
+            None,
+        );
+        ProviderRoute::new(ProviderClient::Blackbird(client)).with_models(name, "cheap")
+    }
+
+    fn router() -> ProviderRouter {
+        ProviderRouter::new(vec![route("primary"), route("backup")]).unwrap()
+    }
+
+    #[test]
+    fn test_task_selects_model() {
+        let route = route("primary");
+        assert_eq!(route.model_for(Task::Build), "primary");
+        assert_eq!(route.model_for(Task::Chat), "primary");
+        assert_eq!(route.model_for(Task::Extraction), "cheap");
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_on_rate_limit() {
+        let (value, info) = router()
+            .run(
+                Task::Build,
+                &RetryPolicy::none(),
+                || true,
+                |_, info| async move {
+                    if info.model == "primary" {
+                        Err(ChatError::RateLimited { retry_after: None })
+                    } else {
+                        Ok("ok")
+                    }
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(value, "ok");
+        assert_eq!(info.model, "backup");
+    }
+
+    #[tokio::test]
+    async fn test_does_not_fail_over_on_bad_request() {
+        let calls = AtomicUsize::new(0);
+        let result: ChatResult<((), RouteInfo)> = router()
+            .run(
+                Task::Chat,
+                &RetryPolicy::none(),
+                || true,
+                |_, _| async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Err(ChatError::from_status(400, "bad request", None))
+                },
+            )
+            .await;
+        assert!(matches!(result, Err(ChatError::Provider { .. })));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stops_when_caller_refuses() {
+        let calls = AtomicUsize::new(0);
+        let result: ChatResult<((), RouteInfo)> = router()
+            .run(
+                Task::Chat,
+                &RetryPolicy::none(),
+                || false,
+                |_, _| async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Err(ChatError::Network("down".into()))
+                },
+            )
+            .await;
+        assert!(matches!(result, Err(ChatError::Network(_))));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,556 @@
+use super::error::{ChatError, ChatResult};
+use super::router::RouteInfo;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender, error::TryRecvError};
+use tokio::task::AbortHandle;
+
+/// Streams still running after this long are aborted and evicted
+const STREAM_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// How long a finished stream nobody has read yet is kept for late subscribers
+const FINISHED_RETENTION: Duration = Duration::from_secs(30);
+
+// ============================================
+// Stream Events
+// ============================================
+
+/// An update pushed to stream subscribers
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamEvent {
+    /// The provider and model now serving the response. Sent again if the
+    /// request fails over to another provider before producing output.
+    Routed(RouteInfo),
+    /// Newly generated text, to be appended to what was received before
+    Delta(String),
+    /// The reply was cut off and continuation request `n` is under way. Its
+    /// text keeps arriving as deltas that extend the same reply.
+    Continued(u32),
+    /// The provider finished the response
+    Finished,
+    /// The stream was cancelled; the deltas received so far are the partial response
+    Cancelled,
+    /// The provider request failed
+    Failed(ChatError),
+}
+
+/// Receiving end of a stream subscription
+pub struct StreamSubscription {
+    receiver: UnboundedReceiver<StreamEvent>,
+    pending: Option<StreamEvent>,
+}
+
+impl StreamSubscription {
+    /// Wait for the next event, or `None` once the stream is gone.
+    ///
+    /// Deltas that are already queued are merged so a slow consumer catches up
+    /// in a single update.
+    pub async fn recv(&mut self) -> Option<StreamEvent> {
+        let event = match self.pending.take() {
+            Some(event) => event,
+            None => self.receiver.recv().await?,
+        };
+
+        let StreamEvent::Delta(mut text) = event else {
+            return Some(event);
+        };
+        while let Ok(next) = self.receiver.try_recv() {
+            match next {
+                StreamEvent::Delta(more) => text.push_str(&more),
+                other => {
+                    self.pending = Some(other);
+                    break;
+                }
+            }
+        }
+        Some(StreamEvent::Delta(text))
+    }
+}
+
+// ============================================
+// Stream Store
+// ============================================
+
+static STREAM_STORE: Lazy<StreamStore> = Lazy::new(StreamStore::default);
+
+struct StreamStore {
+    counter: AtomicU64,
+    entries: Mutex<HashMap<u64, StreamEntry>>,
+}
+
+impl Default for StreamStore {
+    fn default() -> Self {
+        Self {
+            counter: AtomicU64::new(1),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+struct StreamEntry {
+    buffer: String,
+    route: Option<RouteInfo>,
+    continuations: u32,
+    outcome: Option<StreamEvent>,
+    abort: Option<AbortHandle>,
+    subscribers: Vec<UnboundedSender<StreamEvent>>,
+    created_at: Instant,
+    finished_at: Option<Instant>,
+}
+
+impl StreamEntry {
+    fn new() -> Self {
+        Self {
+            buffer: String::new(),
+            route: None,
+            continuations: 0,
+            outcome: None,
+            abort: None,
+            subscribers: Vec::new(),
+            created_at: Instant::now(),
+            finished_at: None,
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.outcome.is_some()
+    }
+
+    /// Send an event to every live subscriber, dropping closed ones.
+    /// Returns whether anybody received it.
+    fn broadcast(&mut self, event: &StreamEvent) -> bool {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        !self.subscribers.is_empty()
+    }
+}
+
+impl StreamStore {
+    fn create_handle(&self) -> StreamHandle {
+        let id = self.counter.fetch_add(1, Ordering::Relaxed);
+        let mut entries = self.entries.lock().expect("stream store poisoned");
+        Self::evict_expired(&mut entries, STREAM_TTL, FINISHED_RETENTION);
+        entries.insert(id, StreamEntry::new());
+        StreamHandle { id }
+    }
+
+    fn append(&self, id: u64, chunk: &str) {
+        let mut entries = self.entries.lock().expect("stream store poisoned");
+        if let Some(entry) = entries.get_mut(&id)
+            && !entry.is_done()
+        {
+            entry.buffer.push_str(chunk);
+            entry.broadcast(&StreamEvent::Delta(chunk.to_string()));
+        }
+    }
+
+    /// Record the final outcome. Entries whose result reached a subscriber are
+    /// evicted right away; the rest are kept briefly for late subscribers.
+    fn complete(&self, id: u64, outcome: StreamEvent) {
+        let mut entries = self.entries.lock().expect("stream store poisoned");
+        let Some(entry) = entries.get_mut(&id) else {
+            return;
+        };
+        if entry.is_done() {
+            return;
+        }
+
+        if let Some(abort) = entry.abort.take()
+            && outcome == StreamEvent::Cancelled
+        {
+            abort.abort();
+        }
+        let delivered = entry.broadcast(&outcome);
+        entry.subscribers.clear();
+        entry.outcome = Some(outcome);
+        entry.finished_at = Some(Instant::now());
+
+        if delivered {
+            entries.remove(&id);
+        }
+    }
+
+    fn attach_abort(&self, id: u64, abort: AbortHandle) {
+        let mut entries = self.entries.lock().expect("stream store poisoned");
+        match entries.get_mut(&id) {
+            Some(entry) if !entry.is_done() => entry.abort = Some(abort),
+            Some(entry) if entry.outcome == Some(StreamEvent::Cancelled) => {
+                // Cancelled before the task was registered; stop it right away
+                abort.abort();
+            }
+            _ => {}
+        }
+    }
+
+    /// Abort the producing task and mark the stream cancelled, keeping partial content
+    fn cancel(&self, id: u64) -> ChatResult<()> {
+        if !self
+            .entries
+            .lock()
+            .expect("stream store poisoned")
+            .contains_key(&id)
+        {
+            return Err(ChatError::new("invalid stream id"));
+        }
+        self.complete(id, StreamEvent::Cancelled);
+        Ok(())
+    }
+
+    fn is_cancelled(&self, id: u64) -> bool {
+        let entries = self.entries.lock().expect("stream store poisoned");
+        entries
+            .get(&id)
+            .is_some_and(|entry| entry.outcome == Some(StreamEvent::Cancelled))
+    }
+
+    fn set_route(&self, id: u64, route: RouteInfo) {
+        let mut entries = self.entries.lock().expect("stream store poisoned");
+        if let Some(entry) = entries.get_mut(&id)
+            && !entry.is_done()
+        {
+            entry.broadcast(&StreamEvent::Routed(route.clone()));
+            entry.route = Some(route);
+        }
+    }
+
+    fn continued(&self, id: u64, part: u32) {
+        let mut entries = self.entries.lock().expect("stream store poisoned");
+        if let Some(entry) = entries.get_mut(&id)
+            && !entry.is_done()
+        {
+            entry.broadcast(&StreamEvent::Continued(part));
+            entry.continuations = part;
+        }
+    }
+
+    fn has_content(&self, id: u64) -> bool {
+        let entries = self.entries.lock().expect("stream store poisoned");
+        entries
+            .get(&id)
+            .is_some_and(|entry| !entry.buffer.is_empty())
+    }
+
+    /// Subscribe to a stream. Content produced so far is replayed as one delta.
+    fn subscribe(&self, id: u64) -> ChatResult<StreamSubscription> {
+        let mut entries = self.entries.lock().expect("stream store poisoned");
+        Self::evict_expired(&mut entries, STREAM_TTL, FINISHED_RETENTION);
+        let entry = entries
+            .get_mut(&id)
+            .ok_or_else(|| ChatError::new("invalid stream id"))?;
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        if let Some(route) = &entry.route {
+            let _ = sender.send(StreamEvent::Routed(route.clone()));
+        }
+        if !entry.buffer.is_empty() {
+            let _ = sender.send(StreamEvent::Delta(entry.buffer.clone()));
+        }
+        if entry.continuations > 0 {
+            let _ = sender.send(StreamEvent::Continued(entry.continuations));
+        }
+        match entry.outcome.clone() {
+            Some(outcome) => {
+                let _ = sender.send(outcome);
+                entries.remove(&id);
+            }
+            None => entry.subscribers.push(sender),
+        }
+
+        Ok(StreamSubscription {
+            receiver,
+            pending: None,
+        })
+    }
+
+    fn evict_expired(entries: &mut HashMap<u64, StreamEntry>, ttl: Duration, retention: Duration) {
+        entries.retain(|_, entry| {
+            if let Some(finished_at) = entry.finished_at {
+                return finished_at.elapsed() < retention;
+            }
+            if entry.created_at.elapsed() < ttl {
+                return true;
+            }
+            if let Some(abort) = entry.abort.take() {
+                abort.abort();
+            }
+            entry.broadcast(&StreamEvent::Failed(ChatError::Timeout));
+            false
+        });
+    }
+}
+
+#[derive(Clone)]
+pub struct StreamHandle {
+    id: u64,
+}
+
+impl StreamHandle {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn append(&self, piece: &str) {
+        STREAM_STORE.append(self.id, piece);
+    }
+
+    pub fn finish(&self) {
+        STREAM_STORE.complete(self.id, StreamEvent::Finished);
+    }
+
+    pub fn fail(&self, err: ChatError) {
+        STREAM_STORE.complete(self.id, StreamEvent::Failed(err));
+    }
+
+    /// Register the task producing this stream so it can be aborted on cancel
+    pub fn attach_abort(&self, abort: AbortHandle) {
+        STREAM_STORE.attach_abort(self.id, abort);
+    }
+
+    pub fn cancel(&self) {
+        let _ = STREAM_STORE.cancel(self.id);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        STREAM_STORE.is_cancelled(self.id)
+    }
+
+    /// Record which provider and model are serving this stream
+    pub fn set_route(&self, route: RouteInfo) {
+        STREAM_STORE.set_route(self.id, route);
+    }
+
+    /// Tell subscribers the reply was cut off and continuation `part` has started
+    pub fn continued(&self, part: u32) {
+        STREAM_STORE.continued(self.id, part);
+    }
+
+    /// Whether any text has been appended yet
+    pub fn has_content(&self) -> bool {
+        STREAM_STORE.has_content(self.id)
+    }
+}
+
+pub(super) fn create_handle() -> StreamHandle {
+    STREAM_STORE.create_handle()
+}
+
+pub(super) fn subscribe(id: u64) -> ChatResult<StreamSubscription> {
+    STREAM_STORE.subscribe(id)
+}
+
+pub(super) fn cancel(id: u64) -> ChatResult<()> {
+    POLL_CURSORS
+        .lock()
+        .expect("poll cursors poisoned")
+        .remove(&id);
+    STREAM_STORE.cancel(id)
+}
+
+// ============================================
+// Polling Compatibility Layer
+// ============================================
+
+static POLL_CURSORS: Lazy<Mutex<HashMap<u64, PollCursor>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A subscription drained on each poll into an accumulated buffer
+struct PollCursor {
+    subscription: StreamSubscription,
+    buffer: String,
+    last_polled: Instant,
+}
+
+/// Snapshot of a stream as `(content, done)`, built on top of a subscription.
+/// The cursor is dropped once `done` has been reported.
+pub(super) fn poll(id: u64) -> ChatResult<(String, bool)> {
+    let mut cursors = POLL_CURSORS.lock().expect("poll cursors poisoned");
+    cursors.retain(|_, cursor| cursor.last_polled.elapsed() < STREAM_TTL);
+
+    let cursor = match cursors.entry(id) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => entry.insert(PollCursor {
+            subscription: STREAM_STORE.subscribe(id)?,
+            buffer: String::new(),
+            last_polled: Instant::now(),
+        }),
+    };
+    cursor.last_polled = Instant::now();
+
+    let mut done = false;
+    loop {
+        match cursor.subscription.receiver.try_recv() {
+            Ok(StreamEvent::Delta(chunk)) => cursor.buffer.push_str(&chunk),
+            Ok(StreamEvent::Routed(_) | StreamEvent::Continued(_)) => {}
+            Ok(StreamEvent::Finished | StreamEvent::Cancelled) => done = true,
+            Ok(StreamEvent::Failed(err)) => {
+                cursor.buffer = err.to_string();
+                done = true;
+            }
+            Err(TryRecvError::Empty) => break,
+            Err(TryRecvError::Disconnected) => {
+                done = true;
+                break;
+            }
+        }
+    }
+
+    if done {
+        let cursor = cursors.remove(&id).expect("cursor present");
+        return Ok((cursor.buffer, true));
+    }
+    Ok((cursor.buffer.clone(), false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscriber_receives_deltas_then_outcome() {
+        let store = StreamStore::default();
+        let handle = store.create_handle();
+        store.append(handle.id, "<html>");
+
+        let mut subscription = store.subscribe(handle.id).unwrap();
+        store.append(handle.id, "<body>");
+        store.append(handle.id, "</body>");
+        store.complete(handle.id, StreamEvent::Finished);
+
+        assert_eq!(
+            subscription.recv().await,
+            Some(StreamEvent::Delta("<html><body></body>".to_string()))
+        );
+        assert_eq!(subscription.recv().await, Some(StreamEvent::Finished));
+        assert_eq!(subscription.recv().await, None);
+    }
+
+    #[test]
+    fn test_delivered_stream_is_evicted() {
+        let store = StreamStore::default();
+        let handle = store.create_handle();
+        let _subscription = store.subscribe(handle.id).unwrap();
+
+        store.complete(handle.id, StreamEvent::Finished);
+        assert!(store.entries.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_route_is_replayed_before_content() {
+        let store = StreamStore::default();
+        let handle = store.create_handle();
+        let route = RouteInfo {
+            provider: "OpenAI".to_string(),
+            model: "gpt-4o".to_string(),
+        };
+        store.set_route(handle.id, route.clone());
+        store.append(handle.id, "Hi");
+
+        let mut subscription = store.subscribe(handle.id).unwrap();
+        assert_eq!(subscription.recv().await, Some(StreamEvent::Routed(route)));
+        assert_eq!(
+            subscription.recv().await,
+            Some(StreamEvent::Delta("Hi".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_continuation_is_replayed_after_content() {
+        let store = StreamStore::default();
+        let handle = store.create_handle();
+        store.append(handle.id, "<html>");
+        store.continued(handle.id, 1);
+        store.append(handle.id, "<body>");
+
+        let mut subscription = store.subscribe(handle.id).unwrap();
+        assert_eq!(
+            subscription.recv().await,
+            Some(StreamEvent::Delta("<html><body>".to_string()))
+        );
+        assert_eq!(subscription.recv().await, Some(StreamEvent::Continued(1)));
+    }
+
+    #[tokio::test]
+    async fn test_late_subscriber_gets_result_then_evicts() {
+        let store = StreamStore::default();
+        let handle = store.create_handle();
+        store.complete(handle.id, StreamEvent::Failed(ChatError::new("boom")));
+
+        let mut subscription = store.subscribe(handle.id).unwrap();
+        assert_eq!(
+            subscription.recv().await,
+            Some(StreamEvent::Failed(ChatError::new("boom")))
+        );
+        assert!(store.subscribe(handle.id).is_err());
+    }
+
+    #[test]
+    fn test_expired_entries_are_swept() {
+        let store = StreamStore::default();
+        let running = store.create_handle();
+        let finished = store.create_handle();
+        store.complete(finished.id, StreamEvent::Finished);
+
+        let mut entries = store.entries.lock().unwrap();
+        StreamStore::evict_expired(&mut entries, STREAM_TTL, Duration::ZERO);
+        assert!(entries.contains_key(&running.id));
+        assert!(!entries.contains_key(&finished.id));
+
+        StreamStore::evict_expired(&mut entries, Duration::ZERO, Duration::ZERO);
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn test_cancel_keeps_partial_content() {
+        let store = StreamStore::default();
+        let handle = store.create_handle();
+        store.append(handle.id, "<html>partial");
+
+        store.cancel(handle.id).unwrap();
+        store.append(handle.id, " late chunk");
+        store.complete(handle.id, StreamEvent::Failed(ChatError::new("aborted")));
+
+        assert!(store.is_cancelled(handle.id));
+        let entries = store.entries.lock().unwrap();
+        assert_eq!(entries[&handle.id].buffer, "<html>partial");
+    }
+
+    #[test]
+    fn test_cancel_after_finish_is_noop() {
+        let store = StreamStore::default();
+        let handle = store.create_handle();
+        store.complete(handle.id, StreamEvent::Finished);
+
+        store.cancel(handle.id).unwrap();
+        assert!(!store.is_cancelled(handle.id));
+        assert!(store.cancel(u64::MAX).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_aborts_attached_task() {
+        let store = StreamStore::default();
+        let handle = store.create_handle();
+        let task = tokio::spawn(async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        });
+        store.attach_abort(handle.id, task.abort_handle());
+
+        store.cancel(handle.id).unwrap();
+        assert!(task.await.unwrap_err().is_cancelled());
+    }
+
+    #[test]
+    fn test_poll_accumulates_and_releases() {
+        let handle = create_handle();
+        handle.append("Hello");
+        assert_eq!(poll(handle.id).unwrap(), ("Hello".to_string(), false));
+
+        handle.append(", world");
+        handle.finish();
+        assert_eq!(poll(handle.id).unwrap(), ("Hello, world".to_string(), true));
+        assert!(poll(handle.id).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use serde_json::{Value, json};
+
+/// How the model is asked to shape its reply
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ResponseFormat {
+    /// Free text, parsed by the caller
+    #[default]
+    Text,
+    /// A single JSON object matching the schema
+    Json(JsonSchema),
+}
+
+/// A named JSON schema for structured output
+#[derive(Clone, Debug, PartialEq)]
+pub struct JsonSchema {
+    pub name: String,
+    pub schema: Value,
+}
+
+impl JsonSchema {
+    pub fn new(name: impl Into<String>, schema: Value) -> Self {
+        Self {
+            name: name.into(),
+            schema,
+        }
+    }
+
+    /// Added to the system prompt so providers without a native JSON mode
+    /// (Anthropic, Ollama) still answer in the right shape
+    pub fn instructions(&self) -> String {
+        format!(
+            "Reply with a single JSON object and nothing else: no markdown fences, no text before or after it. \
+The object must match this JSON schema:\n{}",
+            self.schema
+        )
+    }
+
+    /// The `response_format` value for OpenAI chat completions and compatible APIs
+    pub fn response_format(&self) -> Value {
+        json!({
+            "type": "json_schema",
+            "json_schema": {
+                "name": self.name,
+                "schema": self.schema,
+                "strict": true,
+            }
+        })
+    }
+
+    /// `text.format` for the OpenAI Responses API
+    pub fn responses_param(&self) -> Value {
+        json!({
+            "text": {
+                "format": {
+                    "type": "json_schema",
+                    "name": self.name,
+                    "schema": self.schema,
+                    "strict": true,
+                }
+            }
+        })
+    }
+}
+
+impl ResponseFormat {
+    pub fn schema(&self) -> Option<&JsonSchema> {
+        match self {
+            Self::Text => None,
+            Self::Json(schema) => Some(schema),
+        }
+    }
+}
+
+/// The JSON object in a reply, tolerating markdown fences or stray text
+/// around it from models that ignore the format
+pub fn extract_json(content: &str) -> Option<Value> {
+    let trimmed = content.trim();
+    if let Ok(value @ Value::Object(_)) = serde_json::from_str(trimmed) {
+        return Some(value);
+    }
+
+    let start = trimmed.find('{')?;
+    let end = trimmed.rfind('}')?;
+    match serde_json::from_str(trimmed.get(start..=end)?) {
+        Ok(value @ Value::Object(_)) => Some(value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_json_variants() {
+        assert_eq!(extract_json(r#"{"a":1}"#), Some(json!({"a": 1})));
+        assert_eq!(
+            extract_json("```json\n{\"a\": \"}\"}\n```"),
+            Some(json!({"a": "}"}))
+        );
+        assert_eq!(extract_json("<html></html>"), None);
+        assert_eq!(extract_json("[1, 2]"), None);
+    }
+
+    #[test]
+    fn test_provider_params() {
+        let schema = JsonSchema::new("app", json!({"type": "object"}));
+        assert_eq!(schema.response_format()["json_schema"]["name"], "app");
+        assert_eq!(
+            schema.responses_param()["text"]["format"]["type"],
+            "json_schema"
+        );
+    }
+}
@@ -1,264 +1,1163 @@
 //! Blackbird Bridge - Enables iframe apps to use persistent storage
 //!
 //! This module provides:
-//! - App-specific isolated storage via localStorage
+//! - App-specific isolated storage, kept by the host in a [`storage`] backend
+//!   and limited by a per-app quota
+//! - Notice of storage changes, for every instance of an app
+//! - The message protocol between app iframes and the host window
+//! - Model calls for apps, under a per-app quota
 
+use crate::storage::{self, StorageError, StorageResult, StorageUsage};
+use crate::types::{ChatMessage, Role};
 use once_cell::sync::Lazy;
-use std::collections::HashMap;
-use std::sync::Mutex;
-
-#[cfg(not(target_arch = "wasm32"))]
-use std::{fs, path::PathBuf};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 
 // ============================================
-// Storage Backend (for native platforms)
+// Storage
 // ============================================
 
-/// In-memory storage for WASM, file-based for native
-#[allow(dead_code)]
-static APP_STORAGE: Lazy<Mutex<HashMap<String, HashMap<String, String>>>> =
+/// Get a value from app-specific storage
+pub fn storage_get(app_id: &str, key: &str) -> Option<String> {
+    storage::backend().get(app_id, key).unwrap_or_else(|err| {
+        tracing::warn!("Failed to read {} for app {}: {}", key, app_id, err);
+        None
+    })
+}
+
+/// Set a value in app-specific storage, within the app's [`StorageQuota`]
+pub fn storage_set(app_id: &str, key: &str, value: &str) -> StorageResult<()> {
+    let writes = app_writes(app_id);
+    let mut usage = writes.lock().map_err(|_| StorageError::Unavailable)?;
+    let backend = storage::backend();
+    let old = backend.get(app_id, key)?;
+    if old.as_deref() == Some(value) {
+        return Ok(());
+    }
+    let current = match *usage {
+        Some(current) => current,
+        None => backend.usage(app_id)?,
+    };
+    let after = APP_STORAGE_QUOTA.check(current, key, old.as_deref(), value)?;
+    // Unknown if the write fails part way
+    *usage = None;
+    backend.set(app_id, key, value)?;
+    *usage = Some(after);
+    drop(usage);
+    publish_change(app_id, Some(key), Some(value));
+    Ok(())
+}
+
+/// Delete a value from app-specific storage
+pub fn storage_delete(app_id: &str, key: &str) -> StorageResult<()> {
+    let writes = app_writes(app_id);
+    let mut usage = writes.lock().map_err(|_| StorageError::Unavailable)?;
+    *usage = None;
+    storage::backend().delete(app_id, key)?;
+    drop(usage);
+    publish_change(app_id, Some(key), None);
+    Ok(())
+}
+
+/// List all keys in app-specific storage
+pub fn storage_keys(app_id: &str) -> Vec<String> {
+    storage::backend().keys(app_id).unwrap_or_else(|err| {
+        tracing::warn!("Failed to list keys for app {}: {}", app_id, err);
+        Vec::new()
+    })
+}
+
+/// Clear all storage for an app
+pub fn storage_clear(app_id: &str) -> StorageResult<()> {
+    let writes = app_writes(app_id);
+    let mut usage = writes.lock().map_err(|_| StorageError::Unavailable)?;
+    *usage = None;
+    storage::backend().clear(app_id)?;
+    *usage = Some(StorageUsage::default());
+    drop(usage);
+    publish_change(app_id, None, None);
+    Ok(())
+}
+
+/// Copy all of one app's storage into another's, replacing keys both have,
+/// as long as the result fits the [`StorageQuota`]. Returns the number of keys
+/// copied.
+pub fn storage_copy(from: &str, to: &str) -> StorageResult<usize> {
+    let writes = app_writes(to);
+    let mut usage = writes.lock().map_err(|_| StorageError::Unavailable)?;
+    let backend = storage::backend();
+    let stored = storage_entries(backend.as_ref(), from)?;
+    let entries: Vec<(&str, &str)> = stored
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect();
+    let keys: Vec<&str> = entries.iter().map(|(key, _)| *key).collect();
+    let old = backend.get_many(to, &keys)?;
+    let current = match *usage {
+        Some(current) => current,
+        None => backend.usage(to)?,
+    };
+    let after = APP_STORAGE_QUOTA.check_many(current, &entries, &old)?;
+    *usage = None;
+    backend.set_many(to, &entries)?;
+    *usage = Some(after);
+    drop(usage);
+    for (key, value) in &entries {
+        publish_change(to, Some(key), Some(value));
+    }
+    Ok(entries.len())
+}
+
+/// Move all of one app's storage into another's, leaving the first empty.
+/// Returns the number of keys moved.
+pub fn storage_move(from: &str, to: &str) -> StorageResult<usize> {
+    let moved = storage_copy(from, to)?;
+    storage_clear(from)?;
+    Ok(moved)
+}
+
+/// The usage of each app written through the bridge, measured on its first
+/// write and kept current after, so checking a write against the quota does
+/// not scan the app's storage. Each entry's lock is held from the check until
+/// the write lands, so writes to one app cannot race past the quota together.
+/// `None` until measured, and again whenever a write's effect is not known.
+static APP_WRITES: Lazy<Mutex<HashMap<String, AppWrites>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
-/// Get the storage directory for a specific app
-#[cfg(not(target_arch = "wasm32"))]
-fn get_app_storage_dir(app_id: &str) -> PathBuf {
-    let safe_id = sanitize_app_id(app_id);
+type AppWrites = Arc<Mutex<Option<StorageUsage>>>;
+
+fn app_writes(app_id: &str) -> AppWrites {
+    let mut writes = APP_WRITES.lock().unwrap_or_else(PoisonError::into_inner);
+    writes.entry(app_id.to_string()).or_default().clone()
+}
+
+/// Every key an app stores, with its value
+fn storage_entries(
+    backend: &dyn storage::StorageBackend,
+    app_id: &str,
+) -> StorageResult<Vec<(String, String)>> {
+    let keys = backend.keys(app_id)?;
+    let key_refs: Vec<&str> = keys.iter().map(String::as_str).collect();
+    let values = backend.get_many(app_id, &key_refs)?;
+    Ok(keys
+        .into_iter()
+        .zip(values)
+        .filter_map(|(key, value)| Some((key, value?)))
+        .collect())
+}
+
+/// How much an app is storing
+pub fn storage_usage(app_id: &str) -> StorageUsage {
+    storage::backend().usage(app_id).unwrap_or_else(|err| {
+        tracing::warn!("Failed to measure storage for app {}: {}", app_id, err);
+        StorageUsage::default()
+    })
+}
+
+/// How much each app may store
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StorageQuota {
+    /// Bytes of keys and values, as counted by [`StorageUsage`]
+    pub max_bytes: u64,
+    pub max_keys: usize,
+}
 
-    if let Some(data_dir) = dirs::data_local_dir() {
-        return data_dir.join("blackbird").join("app_data").join(safe_id);
+impl Default for StorageQuota {
+    fn default() -> Self {
+        Self {
+            max_bytes: 10 * 1024 * 1024,
+            max_keys: 1000,
+        }
+    }
+}
+
+impl StorageQuota {
+    /// Read `APP_STORAGE_MAX_BYTES` and `APP_STORAGE_MAX_KEYS`, falling back
+    /// to the defaults for unset or invalid values
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            max_bytes: env_limit("APP_STORAGE_MAX_BYTES").unwrap_or(default.max_bytes),
+            max_keys: env_limit("APP_STORAGE_MAX_KEYS").unwrap_or(default.max_keys),
+        }
     }
 
-    PathBuf::from("cache").join("app_data").join(safe_id)
+    /// Check that setting `key` from `old` to `value` keeps an app now using
+    /// `usage` within the quota, and return its usage after the write. Writes
+    /// that do not grow the app's usage are allowed even past the quota, so an
+    /// app over it can still shrink.
+    pub fn check(
+        &self,
+        usage: StorageUsage,
+        key: &str,
+        old: Option<&str>,
+        value: &str,
+    ) -> StorageResult<StorageUsage> {
+        let old_bytes = old.map_or(0, |old| key.len() + old.len()) as u64;
+        let new_bytes = (key.len() + value.len()) as u64;
+        if new_bytes > old_bytes
+            && usage.bytes.saturating_sub(old_bytes) + new_bytes > self.max_bytes
+        {
+            return Err(StorageError::TooManyBytes(self.max_bytes));
+        }
+        if old.is_none() && usage.keys >= self.max_keys {
+            return Err(StorageError::TooManyKeys(self.max_keys));
+        }
+        Ok(StorageUsage {
+            bytes: usage.bytes.saturating_sub(old_bytes) + new_bytes,
+            keys: usage.keys + usize::from(old.is_none()),
+        })
+    }
+
+    /// [`Self::check`] for setting several keys at once, given the value each
+    /// had before. The batch is judged as a whole: it is refused only if the
+    /// app ends up past the quota and bigger than it was.
+    pub fn check_many(
+        &self,
+        usage: StorageUsage,
+        entries: &[(&str, &str)],
+        old: &[Option<String>],
+    ) -> StorageResult<StorageUsage> {
+        let mut after = usage;
+        for ((key, value), old) in entries.iter().zip(old) {
+            let old_bytes = old.as_ref().map_or(0, |old| key.len() + old.len()) as u64;
+            after.bytes = after.bytes.saturating_sub(old_bytes) + (key.len() + value.len()) as u64;
+            after.keys += usize::from(old.is_none());
+        }
+        if after.bytes > usage.bytes && after.bytes > self.max_bytes {
+            return Err(StorageError::TooManyBytes(self.max_bytes));
+        }
+        if after.keys > usage.keys && after.keys > self.max_keys {
+            return Err(StorageError::TooManyKeys(self.max_keys));
+        }
+        Ok(after)
+    }
 }
 
-/// Sanitize app ID for filesystem use
-fn sanitize_app_id(app_id: &str) -> String {
-    app_id
-        .chars()
-        .map(|c| {
-            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
-                c
-            } else {
-                '_'
+/// Storage quota applied to every app
+pub static APP_STORAGE_QUOTA: Lazy<StorageQuota> = Lazy::new(StorageQuota::from_env);
+
+/// Starts the id of every Workbench preview namespace
+pub const PREVIEW_APP_PREFIX: &str = "preview-";
+
+/// Clear every preview namespace except `keep`, the one the Workbench is
+/// using. Previews belong to a Workbench session, so the rest are left over
+/// from earlier ones. Returns the number of namespaces cleared.
+pub fn collect_preview_storage(keep: &str) -> usize {
+    let backend = storage::backend();
+    let app_ids = backend.app_ids().unwrap_or_else(|err| {
+        tracing::warn!("Failed to list app storage: {}", err);
+        Vec::new()
+    });
+    app_ids
+        .iter()
+        .filter(|app_id| app_id.starts_with(PREVIEW_APP_PREFIX) && *app_id != keep)
+        .filter(|app_id| match storage_clear(app_id) {
+            Ok(()) => true,
+            Err(err) => {
+                tracing::warn!("Failed to clear preview storage {}: {}", app_id, err);
+                false
             }
         })
-        .collect()
+        .count()
 }
 
-/// Get a value from app-specific storage
-#[cfg(not(target_arch = "wasm32"))]
-pub fn storage_get(app_id: &str, key: &str) -> Option<String> {
-    let storage_dir = get_app_storage_dir(app_id);
-    let file_path = storage_dir.join(format!("{}.json", sanitize_key(key)));
-    fs::read_to_string(file_path).ok()
+/// A change made through [`storage_set`], [`storage_delete`] or
+/// [`storage_clear`], by an app or by Rust code such as a tool
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct StorageChange {
+    pub app_id: String,
+    /// The key that changed, or `None` when all of the app's storage was cleared
+    pub key: Option<String>,
+    /// The new value, or `None` when the key was deleted
+    pub value: Option<String>,
 }
 
-#[cfg(target_arch = "wasm32")]
-pub fn storage_get(app_id: &str, key: &str) -> Option<String> {
-    let storage = APP_STORAGE.lock().ok()?;
-    storage.get(app_id)?.get(key).cloned()
+static STORAGE_SUBSCRIBERS: Lazy<Mutex<Vec<UnboundedSender<StorageChange>>>> =
+    Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Receive every storage change made from now on, for all apps
+pub fn subscribe_storage_changes() -> UnboundedReceiver<StorageChange> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    if let Ok(mut subscribers) = STORAGE_SUBSCRIBERS.lock() {
+        subscribers.push(sender);
+    }
+    receiver
 }
 
-/// Set a value in app-specific storage
-#[cfg(not(target_arch = "wasm32"))]
-pub fn storage_set(app_id: &str, key: &str, value: &str) -> Result<(), String> {
-    let storage_dir = get_app_storage_dir(app_id);
-    fs::create_dir_all(&storage_dir)
-        .map_err(|e| format!("Failed to create storage directory: {}", e))?;
-    let file_path = storage_dir.join(format!("{}.json", sanitize_key(key)));
-    fs::write(file_path, value).map_err(|e| format!("Failed to write to storage: {}", e))
+/// Send a change to every live subscriber, dropping closed ones
+fn publish_change(app_id: &str, key: Option<&str>, value: Option<&str>) {
+    let Ok(mut subscribers) = STORAGE_SUBSCRIBERS.lock() else {
+        return;
+    };
+    let change = StorageChange {
+        app_id: app_id.to_string(),
+        key: key.map(str::to_string),
+        value: value.map(str::to_string),
+    };
+    subscribers.retain(|subscriber| subscriber.send(change.clone()).is_ok());
 }
 
-#[cfg(target_arch = "wasm32")]
-pub fn storage_set(app_id: &str, key: &str, value: &str) -> Result<(), String> {
-    let mut storage = APP_STORAGE.lock().map_err(|e| e.to_string())?;
-    let app_storage = storage.entry(app_id.to_string()).or_default();
-    app_storage.insert(key.to_string(), value.to_string());
-    Ok(())
+/// A numeric limit from the environment, if set and valid
+fn env_limit<T: std::str::FromStr>(key: &str) -> Option<T> {
+    std::env::var(key).ok()?.trim().parse().ok()
 }
 
-/// Delete a value from app-specific storage
-#[cfg(not(target_arch = "wasm32"))]
-pub fn storage_delete(app_id: &str, key: &str) -> Result<(), String> {
-    let storage_dir = get_app_storage_dir(app_id);
-    let file_path = storage_dir.join(format!("{}.json", sanitize_key(key)));
-    if file_path.exists() {
-        fs::remove_file(file_path).map_err(|e| format!("Failed to delete from storage: {}", e))?;
+// ============================================
+// Host Messages
+// ============================================
+
+/// Evaluated once in the host window. Forwards SDK messages from app iframes
+/// to Rust, taking the app id from the `data-app-id` attribute of the iframe
+/// the message came from rather than trusting the message itself.
+///
+/// Messages with an `id` expect an answer. Each is given a host-wide
+/// `request` number, and the [`HostReply`] that Rust sends back for it is
+/// posted to the iframe that asked, after any [`HostChunk`]s.
+///
+/// A `{ change }` sent from Rust holds a [`StorageChange`], which is posted to
+/// every iframe showing that app.
+pub const HOST_LISTENER_SCRIPT: &str = r#"
+const pending = new Map();
+let nextRequest = 0;
+
+window.addEventListener('message', (event) => {
+    const data = event.data;
+    if (!data || data.__blackbird !== 1) return;
+    const frame = Array.from(document.querySelectorAll('iframe[data-app-id]'))
+        .find((frame) => frame.contentWindow === event.source);
+    if (!frame) return;
+    const message = Object.assign({}, data, { app_id: frame.dataset.appId });
+    if (data.id !== undefined) {
+        message.request = ++nextRequest;
+        pending.set(message.request, { source: event.source, id: data.id });
     }
-    Ok(())
+    dioxus.send(message);
+});
+
+(async () => {
+    while (true) {
+        const reply = await dioxus.recv();
+        if (reply.change) {
+            const change = reply.change;
+            document.querySelectorAll('iframe[data-app-id]').forEach((frame) => {
+                if (frame.dataset.appId !== change.app_id || !frame.contentWindow) return;
+                frame.contentWindow.postMessage({
+                    __blackbird: 1,
+                    type: 'change',
+                    key: change.key,
+                    value: change.value
+                }, '*');
+            });
+            continue;
+        }
+        const asker = pending.get(reply.request);
+        if (!asker) continue;
+        if (reply.chunk !== undefined) {
+            asker.source.postMessage({ __blackbird: 1, type: 'chunk', id: asker.id, chunk: reply.chunk }, '*');
+            continue;
+        }
+        pending.delete(reply.request);
+        asker.source.postMessage({
+            __blackbird: 1,
+            type: 'reply',
+            id: asker.id,
+            value: reply.value,
+            error: reply.error
+        }, '*');
+    }
+})();
+"#;
+
+/// Console entries kept per app; older ones are dropped
+pub const MAX_CONSOLE_ENTRIES: usize = 200;
+
+/// A message from an app iframe, as delivered by [`HOST_LISTENER_SCRIPT`]
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct AppMessage {
+    pub app_id: String,
+    #[serde(flatten)]
+    pub event: AppEvent,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AppEvent {
+    /// The SDK started, so the app was (re)loaded
+    Loaded,
+    /// `console.*` output, an uncaught error, an unhandled promise rejection
+    /// or a resource that failed to load
+    Console(ConsoleEntry),
+    /// A `blackbird.storage` call, answered with a [`HostReply`]
+    Storage(StorageRequest),
+    /// A `blackbird.ai` call
+    Ai(AiRequest),
 }
 
-#[cfg(target_arch = "wasm32")]
-pub fn storage_delete(app_id: &str, key: &str) -> Result<(), String> {
-    let mut storage = APP_STORAGE.lock().map_err(|e| e.to_string())?;
-    if let Some(app_storage) = storage.get_mut(app_id) {
-        app_storage.remove(key);
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct StorageRequest {
+    /// Numbered by [`HOST_LISTENER_SCRIPT`]; echoed in the reply
+    pub request: u64,
+    #[serde(flatten)]
+    pub op: StorageOp,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum StorageOp {
+    Get { key: String },
+    Set { key: String, value: String },
+    Delete { key: String },
+    Keys,
+    Clear,
+}
+
+/// The answer to a request from an app, sent back to [`HOST_LISTENER_SCRIPT`]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HostReply {
+    pub request: u64,
+    pub value: serde_json::Value,
+    pub error: Option<String>,
+}
+
+impl HostReply {
+    pub fn new(request: u64, result: Result<serde_json::Value, String>) -> Self {
+        match result {
+            Ok(value) => Self {
+                request,
+                value,
+                error: None,
+            },
+            Err(error) => Self {
+                request,
+                value: serde_json::Value::Null,
+                error: Some(error),
+            },
+        }
     }
-    Ok(())
 }
 
-/// List all keys in app-specific storage
-#[cfg(not(target_arch = "wasm32"))]
-pub fn storage_keys(app_id: &str) -> Vec<String> {
-    let storage_dir = get_app_storage_dir(app_id);
-    if !storage_dir.exists() {
-        return Vec::new();
+/// Run a storage call from an app against its own namespace
+pub fn handle_storage(app_id: &str, op: StorageOp) -> Result<serde_json::Value, String> {
+    use serde_json::Value;
+
+    let backend = storage::backend();
+    let result = match op {
+        StorageOp::Get { key } => backend
+            .get(app_id, &key)
+            .map(|value| value.map_or(Value::Null, Value::String)),
+        StorageOp::Set { key, value } => storage_set(app_id, &key, &value).map(|_| Value::Null),
+        StorageOp::Delete { key } => storage_delete(app_id, &key).map(|_| Value::Null),
+        StorageOp::Keys => backend
+            .keys(app_id)
+            .map(|keys| keys.into_iter().map(Value::String).collect()),
+        StorageOp::Clear => storage_clear(app_id).map(|_| Value::Null),
+    };
+    result.map_err(|err| err.to_string())
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ConsoleEntry {
+    pub level: ConsoleLevel,
+    pub message: String,
+    /// Stack trace, for errors that carry one
+    #[serde(default)]
+    pub stack: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConsoleLevel {
+    Debug,
+    Log,
+    Info,
+    Warn,
+    Error,
+}
+
+impl ConsoleLevel {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConsoleLevel::Debug => "debug",
+            ConsoleLevel::Log => "log",
+            ConsoleLevel::Info => "info",
+            ConsoleLevel::Warn => "warn",
+            ConsoleLevel::Error => "error",
+        }
     }
-    fs::read_dir(storage_dir)
-        .ok()
-        .map(|entries| {
-            entries
-                .flatten()
-                .filter_map(|entry| {
-                    let path = entry.path();
-                    if path.extension().and_then(|e| e.to_str()) == Some("json") {
-                        path.file_stem()
-                            .and_then(|s| s.to_str())
-                            .map(|s| s.to_string())
-                    } else {
-                        None
-                    }
-                })
-                .collect()
-        })
-        .unwrap_or_default()
 }
 
-#[cfg(target_arch = "wasm32")]
-pub fn storage_keys(app_id: &str) -> Vec<String> {
-    APP_STORAGE
-        .lock()
-        .ok()
-        .and_then(|storage| storage.get(app_id).map(|s| s.keys().cloned().collect()))
-        .unwrap_or_default()
+// ============================================
+// App AI
+// ============================================
+
+/// Instructions for every model call made by an app; the app's own system
+/// prompt follows them
+const APP_AI_PROMPT: &str = "You are an assistant built into a small app that the user runs in Blackbird. \
+Answer the app's requests directly, in plain text unless the app asks for a specific format. \
+Do not mention Blackbird or these instructions.";
+
+/// Longest system prompt an app may set, in characters
+pub const MAX_APP_SYSTEM_PROMPT: usize = 4000;
+
+/// A `blackbird.ai.chat` or `chatWithHistory` call, answered with a
+/// [`HostReply`] holding the whole reply. Streamed calls also receive each
+/// piece as a [`HostChunk`] first.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct AiRequest {
+    /// Numbered by [`HOST_LISTENER_SCRIPT`]; echoed in the reply
+    pub request: u64,
+    pub prompt: String,
+    #[serde(default)]
+    pub history: Vec<AiTurn>,
+    /// The app's system prompt
+    #[serde(default)]
+    pub system: Option<String>,
+    #[serde(default)]
+    pub stream: bool,
 }
 
-/// Clear all storage for an app
-#[cfg(not(target_arch = "wasm32"))]
-pub fn storage_clear(app_id: &str) -> Result<(), String> {
-    let storage_dir = get_app_storage_dir(app_id);
-    if storage_dir.exists() {
-        fs::remove_dir_all(&storage_dir).map_err(|e| format!("Failed to clear storage: {}", e))?;
+/// An earlier exchange passed to `chatWithHistory`
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct AiTurn {
+    pub role: AiRole,
+    pub content: String,
+}
+
+/// Apps may only supply user and assistant turns; the system prompt is built
+/// by the host
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AiRole {
+    User,
+    Assistant,
+}
+
+impl AiRequest {
+    /// The conversation sent to the model for this request
+    pub fn messages(&self) -> Vec<ChatMessage> {
+        let mut system = APP_AI_PROMPT.to_string();
+        if let Some(app_prompt) = self.system.as_deref().map(str::trim)
+            && !app_prompt.is_empty()
+        {
+            system.push_str("\n\n");
+            system.extend(app_prompt.chars().take(MAX_APP_SYSTEM_PROMPT));
+        }
+
+        let turns = self.history.iter().map(|turn| {
+            let role = match turn.role {
+                AiRole::User => Role::User,
+                AiRole::Assistant => Role::Assistant,
+            };
+            (role, turn.content.clone())
+        });
+        [(Role::System, system)]
+            .into_iter()
+            .chain(turns)
+            .chain([(Role::User, self.prompt.clone())])
+            .map(|(role, content)| ChatMessage {
+                role,
+                content,
+                created_at: None,
+                tags: Vec::new(),
+                tool_call_id: None,
+            })
+            .collect()
     }
-    Ok(())
 }
 
-#[cfg(target_arch = "wasm32")]
-pub fn storage_clear(app_id: &str) -> Result<(), String> {
-    let mut storage = APP_STORAGE.lock().map_err(|e| e.to_string())?;
-    storage.remove(app_id);
-    Ok(())
+/// A piece of a streamed reply, sent back to [`HOST_LISTENER_SCRIPT`] before
+/// the final [`HostReply`]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HostChunk {
+    pub request: u64,
+    pub chunk: String,
 }
 
-/// Sanitize storage key for filesystem use
-fn sanitize_key(key: &str) -> String {
-    key.chars()
-        .map(|c| {
-            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
-                c
-            } else {
-                '_'
-            }
-        })
-        .take(64)
-        .collect()
+/// How much each app may use the model
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AiQuota {
+    /// Requests started in any rolling minute
+    pub requests_per_minute: usize,
+    /// Estimated prompt and reply tokens in any rolling day
+    pub tokens_per_day: usize,
+}
+
+impl Default for AiQuota {
+    fn default() -> Self {
+        Self {
+            requests_per_minute: 10,
+            tokens_per_day: 200_000,
+        }
+    }
+}
+
+impl AiQuota {
+    /// Read `APP_AI_REQUESTS_PER_MINUTE` and `APP_AI_TOKENS_PER_DAY`, falling
+    /// back to the defaults for unset or invalid values
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            requests_per_minute: env_limit("APP_AI_REQUESTS_PER_MINUTE")
+                .unwrap_or(default.requests_per_minute),
+            tokens_per_day: env_limit("APP_AI_TOKENS_PER_DAY").unwrap_or(default.tokens_per_day),
+        }
+    }
+}
+
+/// Why an app's AI request was refused
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum QuotaError {
+    #[error("Too many AI requests from this app. Try again in {0} seconds.")]
+    TooManyRequests(u64),
+
+    #[error("This app has used its AI allowance of {0} tokens for today.")]
+    TokensUsedUp(usize),
+}
+
+const MINUTE: Duration = Duration::from_secs(60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Model use of each app against an [`AiQuota`]
+#[derive(Debug)]
+pub struct AiUsage {
+    quota: AiQuota,
+    apps: HashMap<String, AppAiUsage>,
+}
+
+#[derive(Debug)]
+struct AppAiUsage {
+    /// Start times of requests in the last minute
+    recent: VecDeque<Instant>,
+    /// Tokens used since `day_start`
+    tokens: usize,
+    day_start: Instant,
+}
+
+impl AiUsage {
+    pub fn new(quota: AiQuota) -> Self {
+        Self {
+            quota,
+            apps: HashMap::new(),
+        }
+    }
+
+    /// Admit a request whose prompt is about `tokens` long, counting it
+    /// against the app's quota
+    pub fn begin(&mut self, app_id: &str, tokens: usize, now: Instant) -> Result<(), QuotaError> {
+        let quota = self.quota;
+        let usage = self.app(app_id, now);
+
+        while usage
+            .recent
+            .front()
+            .is_some_and(|start| now.duration_since(*start) >= MINUTE)
+        {
+            usage.recent.pop_front();
+        }
+        if usage.recent.len() >= quota.requests_per_minute {
+            let wait = usage
+                .recent
+                .front()
+                .map_or(MINUTE, |start| MINUTE - now.duration_since(*start));
+            return Err(QuotaError::TooManyRequests(wait.as_secs().max(1)));
+        }
+        if usage.tokens + tokens > quota.tokens_per_day {
+            return Err(QuotaError::TokensUsedUp(quota.tokens_per_day));
+        }
+
+        usage.recent.push_back(now);
+        usage.tokens += tokens;
+        Ok(())
+    }
+
+    /// Count the `tokens` of a reply against the app's quota
+    pub fn record(&mut self, app_id: &str, tokens: usize, now: Instant) {
+        let usage = self.app(app_id, now);
+        usage.tokens += tokens;
+    }
+
+    /// The app's usage, starting a new day once the last one is over
+    fn app(&mut self, app_id: &str, now: Instant) -> &mut AppAiUsage {
+        let usage = self
+            .apps
+            .entry(app_id.to_string())
+            .or_insert_with(|| AppAiUsage {
+                recent: VecDeque::new(),
+                tokens: 0,
+                day_start: now,
+            });
+        if now.duration_since(usage.day_start) >= DAY {
+            usage.tokens = 0;
+            usage.day_start = now;
+        }
+        usage
+    }
 }
 
+/// Model use of every app since Blackbird started
+pub static APP_AI_USAGE: Lazy<Mutex<AiUsage>> =
+    Lazy::new(|| Mutex::new(AiUsage::new(AiQuota::from_env())));
+
 // ============================================
 // SDK JavaScript Code
 // ============================================
 
+/// `<meta>` name recording the SDK an app was written for
+pub const SDK_VERSION_META: &str = "blackbird-sdk";
+
+/// [`SDK_VERSION_META`] of apps written when storage calls returned values
+/// rather than promises. They get [`get_sync_storage_sdk_script`].
+pub const SYNC_STORAGE_SDK_VERSION: &str = "1";
+
+/// `<meta>` name recording the file path an app was known by before it had an
+/// id. SDKs of that time kept its storage in localStorage under the path.
+pub const LEGACY_ID_META: &str = "blackbird-legacy-id";
+
 /// The Blackbird SDK that gets injected into every app
-/// Currently only provides localStorage-based storage
+/// Provides storage kept by the host and forwards console output and errors
+/// to the host
 pub fn get_sdk_script(app_id: &str) -> String {
+    sdk_script(app_id, "null", "null")
+}
+
+/// The SDK for apps marked [`SYNC_STORAGE_SDK_VERSION`]. Their storage calls
+/// return at once, reading a cache primed with the app's stored values; writes
+/// reach the host in the background. Values the app kept in localStorage under
+/// `legacy_id`, its [`LEGACY_ID_META`], move to its id.
+pub fn get_sync_storage_sdk_script(app_id: &str, legacy_id: Option<&str>) -> String {
+    let entries = storage_entries(storage::backend().as_ref(), app_id).unwrap_or_else(|err| {
+        tracing::warn!("Failed to read storage for app {}: {}", app_id, err);
+        Vec::new()
+    });
+    let primed: serde_json::Map<String, serde_json::Value> = entries
+        .into_iter()
+        .map(|(key, value)| (key, value.into()))
+        .collect();
+    // `<` is escaped so stored text cannot close the script element
+    let primed = serde_json::Value::Object(primed)
+        .to_string()
+        .replace('<', "\\u003c");
+    let legacy_prefix = match legacy_id.and_then(legacy_storage_prefix) {
+        Some(prefix) => serde_json::Value::from(prefix)
+            .to_string()
+            .replace('<', "\\u003c"),
+        None => "null".to_string(),
+    };
+    sdk_script(app_id, &primed, &legacy_prefix)
+}
+
+/// The localStorage key prefix SDKs before app ids used for the app at
+/// `path`. They wrote the path into a single-quoted string in strict-mode
+/// JavaScript, so its backslashes were read as escapes, as they are here.
+/// None if that script could not have run.
+fn legacy_storage_prefix(path: &str) -> Option<String> {
+    let mut prefix = String::from("blackbird_app_");
+    let mut chars = path.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\'' | '\n' | '\r' => return None,
+            '\\' => {}
+            ch => {
+                prefix.push(ch);
+                continue;
+            }
+        }
+        match chars.next()? {
+            'n' => prefix.push('\n'),
+            't' => prefix.push('\t'),
+            'r' => prefix.push('\r'),
+            'b' => prefix.push('\u{8}'),
+            'f' => prefix.push('\u{c}'),
+            'v' => prefix.push('\u{b}'),
+            '0' if !chars.peek().is_some_and(char::is_ascii_digit) => prefix.push('\0'),
+            // Octal escapes are not allowed in strict mode
+            '0'..='9' => return None,
+            'x' => prefix.push(hex_char(&mut chars, 2)?),
+            'u' if chars.peek() == Some(&'{') => {
+                chars.next();
+                let digits: String = chars.by_ref().take_while(|&ch| ch != '}').collect();
+                let code = u32::from_str_radix(&digits, 16).ok()?;
+                prefix.push(char::from_u32(code)?);
+            }
+            'u' => prefix.push(hex_char(&mut chars, 4)?),
+            // A backslash before a line break continues the string
+            '\r' => {
+                chars.next_if_eq(&'\n');
+            }
+            '\n' | '\u{2028}' | '\u{2029}' => {}
+            ch => prefix.push(ch),
+        }
+    }
+    prefix.push('_');
+    Some(prefix)
+}
+
+/// The character written as the next `len` hex digits of an escape
+fn hex_char(chars: &mut impl Iterator<Item = char>, len: usize) -> Option<char> {
+    let digits: String = chars.take(len).collect();
+    if digits.len() != len {
+        return None;
+    }
+    u32::from_str_radix(&digits, 16)
+        .ok()
+        .and_then(char::from_u32)
+}
+
+fn sdk_script(app_id: &str, primed: &str, legacy_prefix: &str) -> String {
     format!(
         r#"<script>
 (function() {{
     'use strict';
 
-    const APP_ID = '{}';
+    const APP_ID = '{app_id}';
     const STORAGE_PREFIX = 'blackbird_app_' + APP_ID + '_';
+    const MAX_MESSAGE_LENGTH = 4000;
+    const REQUEST_TIMEOUT_MS = 10000;
+    const AI_TIMEOUT_MS = 120000;
+    const HOSTED = window.parent !== window;
+    // Stored values for apps using synchronous storage calls, otherwise null
+    const PRIMED = {primed};
+    // Where SDKs before app ids kept this app's values, if it is that old
+    const LEGACY_PREFIX = {legacy_prefix};
+
+    // Host messaging - the host identifies the app by its iframe
+    function post(message) {{
+        try {{
+            if (HOSTED) {{
+                window.parent.postMessage(Object.assign({{ __blackbird: 1 }}, message), '*');
+            }}
+        }} catch (e) {{
+            // The host is gone or refuses messages; the app keeps running
+        }}
+    }}
+
+    // Requests answered by the host, matched to their replies by id
+    const pending = new Map();
+    let nextId = 0;
+
+    // Requests fail if the host goes quiet for `timeout`; each chunk of a
+    // streamed reply restarts the wait and is passed to `onChunk`
+    function request(message, timeout, onChunk) {{
+        return new Promise((resolve, reject) => {{
+            const id = ++nextId;
+            const waiting = {{ resolve, reject, onChunk, timer: null }};
+            waiting.wait = () => {{
+                clearTimeout(waiting.timer);
+                waiting.timer = setTimeout(() => {{
+                    pending.delete(id);
+                    reject(new Error('Blackbird host did not answer ' + message.type + ' request'));
+                }}, timeout || REQUEST_TIMEOUT_MS);
+            }};
+            waiting.wait();
+            pending.set(id, waiting);
+            post(Object.assign({{ id: id }}, message));
+        }});
+    }}
+
+    window.addEventListener('message', (event) => {{
+        const data = event.data;
+        if (event.source !== window.parent || !data || data.__blackbird !== 1) return;
+        if (data.type === 'change') {{
+            notifyChange(data.key, data.value);
+            return;
+        }}
+        const waiting = pending.get(data.id);
+        if (!waiting) return;
+        if (data.type === 'chunk') {{
+            waiting.wait();
+            if (waiting.onChunk) {{
+                try {{
+                    waiting.onChunk(data.chunk);
+                }} catch (e) {{
+                    console.error('[Blackbird] onChunk callback error:', e);
+                }}
+            }}
+            return;
+        }}
+        if (data.type !== 'reply') return;
+        pending.delete(data.id);
+        clearTimeout(waiting.timer);
+        if (data.error) {{
+            waiting.reject(new Error(data.error));
+        }} else {{
+            waiting.resolve(data.value);
+        }}
+    }});
+
+    function describe(value) {{
+        if (value instanceof Error) return value.message || String(value);
+        if (typeof value === 'string') return value;
+        try {{
+            return JSON.stringify(value);
+        }} catch {{
+            return String(value);
+        }}
+    }}
+
+    function report(level, message, stack) {{
+        post({{
+            type: 'console',
+            level: level,
+            message: String(message).slice(0, MAX_MESSAGE_LENGTH),
+            stack: stack ? String(stack).slice(0, MAX_MESSAGE_LENGTH) : null
+        }});
+    }}
+
+    // Console output, forwarded after the browser's own console
+    ['debug', 'log', 'info', 'warn', 'error'].forEach((level) => {{
+        const original = console[level].bind(console);
+        console[level] = (...args) => {{
+            original(...args);
+            const error = args.find((arg) => arg instanceof Error);
+            report(level, args.map(describe).join(' '), error && error.stack);
+        }};
+    }});
+
+    // Uncaught errors, and resources (such as CDN scripts) that fail to load.
+    // Load failures do not bubble, so listen in the capture phase.
+    window.addEventListener('error', (event) => {{
+        const target = event.target;
+        if (target && target !== window && (target.src || target.href)) {{
+            report('error', 'Failed to load ' + target.tagName.toLowerCase() + ': ' + (target.src || target.href));
+            return;
+        }}
+        const where = event.lineno ? ' (line ' + event.lineno + ', column ' + event.colno + ')' : '';
+        report('error', (event.message || 'Uncaught error') + where, event.error && event.error.stack);
+    }}, true);
+
+    window.addEventListener('unhandledrejection', (event) => {{
+        const reason = event.reason;
+        report('error', 'Unhandled promise rejection: ' + describe(reason), reason && reason.stack);
+    }});
+
+    post({{ type: 'loaded' }});
+
+    // Storage calls go to the host. Outside Blackbird (an exported app opened
+    // in a browser) they fall back to localStorage.
+    function localKeys(prefix) {{
+        prefix = prefix || STORAGE_PREFIX;
+        const keys = [];
+        for (let i = 0; i < localStorage.length; i++) {{
+            const key = localStorage.key(i);
+            if (key && key.startsWith(prefix)) {{
+                keys.push(key.slice(prefix.length));
+            }}
+        }}
+        return keys;
+    }}
+
+    // Values are stored as JSON where they can be
+    function parseStored(raw) {{
+        if (raw === null || raw === undefined) return null;
+        try {{
+            return JSON.parse(raw);
+        }} catch {{
+            return raw;
+        }}
+    }}
+
+    // onChange callbacks, as [key or null for every key, callback]
+    const changeListeners = [];
+
+    // The primed cache, kept current with changes from other instances.
+    // Keys this instance is still writing keep the value it wrote.
+    const cache = new Map(Object.entries(PRIMED || {{}}));
+    const writing = new Map();
+
+    function notifyChange(key, raw) {{
+        if (!writing.get(key) && !writing.get(null)) {{
+            if (key === null) {{
+                cache.clear();
+            }} else if (raw === null || raw === undefined) {{
+                cache.delete(key);
+            }} else {{
+                cache.set(key, raw);
+            }}
+        }}
+        const change = {{ key: key, value: parseStored(raw) }};
+        changeListeners.slice().forEach(([filter, callback]) => {{
+            if (filter !== null && key !== null && filter !== key) return;
+            try {{
+                callback(change);
+            }} catch (e) {{
+                console.error('[Blackbird] onChange callback error:', e);
+            }}
+        }});
+    }}
+
+    // Outside Blackbird, other tabs report changes through storage events
+    window.addEventListener('storage', (event) => {{
+        if (HOSTED) return;
+        if (event.key === null) {{
+            notifyChange(null, null);
+        }} else if (event.key.startsWith(STORAGE_PREFIX)) {{
+            notifyChange(event.key.slice(STORAGE_PREFIX.length), event.newValue);
+        }}
+    }});
+
+    // Quota errors from the host get the name browsers give them, so apps can
+    // check `e.name === 'QuotaExceededError'` with or without the host
+    async function storageCall(op, key, value) {{
+        if (HOSTED) {{
+            return request({{ type: 'storage', op: op, key: key, value: value }}).catch((e) => {{
+                if (e.message.startsWith('Storage quota exceeded')) e.name = 'QuotaExceededError';
+                throw e;
+            }});
+        }}
+        switch (op) {{
+            case 'get': return localStorage.getItem(STORAGE_PREFIX + key);
+            case 'set': localStorage.setItem(STORAGE_PREFIX + key, value); notifyChange(key, value); return null;
+            case 'delete': localStorage.removeItem(STORAGE_PREFIX + key); notifyChange(key, null); return null;
+            case 'keys': return localKeys();
+            case 'clear': localKeys().forEach((k) => localStorage.removeItem(STORAGE_PREFIX + k)); notifyChange(null, null); return null;
+        }}
+    }}
+
+    // Values saved in this webview's localStorage by earlier versions of the
+    // SDK, under the app's file path before it had an id, move to the host
+    // the first time the app runs
+    const localPrefixes = [LEGACY_PREFIX, STORAGE_PREFIX].filter((prefix) => prefix !== null);
+    if (PRIMED !== null) {{
+        try {{
+            localPrefixes.forEach((prefix) => {{
+                localKeys(prefix).forEach((key) => cache.set(key, localStorage.getItem(prefix + key)));
+            }});
+        }} catch (e) {{
+            // localStorage unavailable
+        }}
+    }}
+    const migrated = (async () => {{
+        if (!HOSTED) return;
+        try {{
+            for (const prefix of localPrefixes) {{
+                for (const key of localKeys(prefix)) {{
+                    await request({{ type: 'storage', op: 'set', key: key, value: localStorage.getItem(prefix + key) }});
+                    localStorage.removeItem(prefix + key);
+                }}
+            }}
+        }} catch (e) {{
+            // localStorage unavailable or the host is not answering
+        }}
+    }})();
+
+    let systemPrompt = null;
+
+    function aiCall(prompt, history, options) {{
+        if (!HOSTED) {{
+            return Promise.reject(new Error('blackbird.ai is only available inside Blackbird'));
+        }}
+        options = options || {{}};
+        const turns = (Array.isArray(history) ? history : []).map((turn) => ({{
+            role: turn.role === 'assistant' ? 'assistant' : 'user',
+            content: String(turn.content)
+        }}));
+        const onChunk = typeof options.onChunk === 'function' ? options.onChunk : null;
+        return request({{
+            type: 'ai',
+            prompt: String(prompt),
+            history: turns,
+            system: options.system != null ? String(options.system) : systemPrompt,
+            stream: !!onChunk
+        }}, AI_TIMEOUT_MS, onChunk);
+    }}
 
     // Blackbird API
     window.blackbird = {{
-        // Storage API - persistent, app-isolated storage via localStorage
+        // Storage API - persistent, app-isolated storage kept by the host.
+        // Every call returns a promise.
         storage: {{
-            get(key) {{
+            async get(key) {{
+                await migrated;
                 try {{
-                    const raw = localStorage.getItem(STORAGE_PREFIX + key);
-                    if (raw === null) return null;
-                    try {{
-                        return JSON.parse(raw);
-                    }} catch {{
-                        return raw;
-                    }}
+                    return parseStored(await storageCall('get', String(key)));
                 }} catch (e) {{
                     console.error('[Blackbird] Storage get error:', e);
                     return null;
                 }}
             }},
 
-            set(key, value) {{
+            async set(key, value) {{
+                await migrated;
                 try {{
                     const serialized = typeof value === 'string' ? value : JSON.stringify(value);
-                    localStorage.setItem(STORAGE_PREFIX + key, serialized);
+                    await storageCall('set', String(key), serialized);
                 }} catch (e) {{
                     console.error('[Blackbird] Storage set error:', e);
                     throw e;
                 }}
             }},
 
-            delete(key) {{
+            async delete(key) {{
+                await migrated;
                 try {{
-                    localStorage.removeItem(STORAGE_PREFIX + key);
+                    await storageCall('delete', String(key));
                 }} catch (e) {{
                     console.error('[Blackbird] Storage delete error:', e);
                 }}
             }},
 
-            keys() {{
+            async keys() {{
+                await migrated;
                 try {{
-                    const keys = [];
-                    for (let i = 0; i < localStorage.length; i++) {{
-                        const key = localStorage.key(i);
-                        if (key && key.startsWith(STORAGE_PREFIX)) {{
-                            keys.push(key.slice(STORAGE_PREFIX.length));
-                        }}
-                    }}
-                    return keys;
+                    return await storageCall('keys');
                 }} catch (e) {{
                     console.error('[Blackbird] Storage keys error:', e);
                     return [];
                 }}
             }},
 
-            clear() {{
+            async clear() {{
+                await migrated;
                 try {{
-                    const keysToRemove = [];
-                    for (let i = 0; i < localStorage.length; i++) {{
-                        const key = localStorage.key(i);
-                        if (key && key.startsWith(STORAGE_PREFIX)) {{
-                            keysToRemove.push(key);
-                        }}
-                    }}
-                    keysToRemove.forEach(key => localStorage.removeItem(key));
+                    await storageCall('clear');
                 }} catch (e) {{
                     console.error('[Blackbird] Storage clear error:', e);
                 }}
+            }},
+
+            // Call `callback({{ key, value }})` whenever this app's storage
+            // changes, in any open instance of the app or from the host. Given
+            // a key, only changes to that key are reported, along with clear(),
+            // which reports a null key. Deleted keys report a null value.
+            // Returns a function that stops the calls.
+            onChange(key, callback) {{
+                if (typeof key === 'function') {{
+                    callback = key;
+                    key = null;
+                }}
+                if (typeof callback !== 'function') {{
+                    throw new TypeError('blackbird.storage.onChange needs a callback');
+                }}
+                const listener = [key == null ? null : String(key), callback];
+                changeListeners.push(listener);
+                return () => {{
+                    const index = changeListeners.indexOf(listener);
+                    if (index !== -1) changeListeners.splice(index, 1);
+                }};
             }}
         }},
 
-        // AI API - not yet available
-        // Will be implemented in a future update
+        // AI API - model calls made by the host, so apps never hold a key.
+        // chat(prompt) and chatWithHistory(prompt, history) resolve to the
+        // whole reply. Both take an optional last argument:
+        //   {{ system: 'You are a chef', onChunk: (text) => ... }}
+        // `system` overrides setSystemPrompt() for one call; `onChunk`
+        // streams the reply as it is written.
         ai: {{
-            async chat(prompt) {{
-                console.warn('[Blackbird] AI API not yet available');
-                return 'AI API coming soon!';
+            setSystemPrompt(prompt) {{
+                systemPrompt = prompt == null ? null : String(prompt);
+            }},
+
+            async chat(prompt, options) {{
+                return aiCall(prompt, [], options);
             }},
 
-            async chatWithHistory(prompt, history) {{
-                console.warn('[Blackbird] AI API not yet available');
-                return 'AI API coming soon!';
+            async chatWithHistory(prompt, history, options) {{
+                return aiCall(prompt, history, options);
             }}
         }},
 
@@ -268,12 +1167,51 @@ pub fn get_sdk_script(app_id: &str) -> String {
         }}
     }};
 
+    // Apps written before storage calls returned promises read the primed
+    // cache, and their writes go to the host in order in the background
+    if (PRIMED !== null) {{
+        let queue = migrated;
+        function write(op, key, value) {{
+            writing.set(key, (writing.get(key) || 0) + 1);
+            queue = queue.then(() => storageCall(op, key, value)).catch((e) => {{
+                console.error('[Blackbird] Storage ' + op + ' error:', e);
+            }}).finally(() => {{
+                writing.set(key, writing.get(key) - 1);
+            }});
+        }}
+
+        Object.assign(window.blackbird.storage, {{
+            get(key) {{
+                return parseStored(cache.get(String(key)));
+            }},
+
+            set(key, value) {{
+                const serialized = typeof value === 'string' ? value : JSON.stringify(value);
+                cache.set(String(key), serialized);
+                write('set', String(key), serialized);
+            }},
+
+            delete(key) {{
+                cache.delete(String(key));
+                write('delete', String(key));
+            }},
+
+            keys() {{
+                return Array.from(cache.keys());
+            }},
+
+            clear() {{
+                cache.clear();
+                write('clear', null);
+            }}
+        }});
+    }}
+
     // Signal SDK is ready
     window.dispatchEvent(new Event('blackbird:ready'));
     console.log('[Blackbird] SDK loaded for app:', APP_ID);
 }})();
-</script>"#,
-        app_id
+</script>"#
     )
 }
 
@@ -282,15 +1220,52 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_sanitize_app_id() {
-        assert_eq!(sanitize_app_id("my-app"), "my-app");
-        assert_eq!(sanitize_app_id("my app!@#"), "my_app___");
-        assert_eq!(sanitize_app_id("/path/to/file.html"), "_path_to_file_html");
+    fn test_ai_rate_limit() {
+        let mut usage = AiUsage::new(AiQuota {
+            requests_per_minute: 2,
+            tokens_per_day: 1000,
+        });
+        let start = Instant::now();
+
+        assert!(usage.begin("a", 10, start).is_ok());
+        assert!(
+            usage
+                .begin("a", 10, start + Duration::from_secs(20))
+                .is_ok()
+        );
+        assert_eq!(
+            usage.begin("a", 10, start + Duration::from_secs(30)),
+            Err(QuotaError::TooManyRequests(30))
+        );
+        // Other apps have their own allowance
+        assert!(
+            usage
+                .begin("b", 10, start + Duration::from_secs(30))
+                .is_ok()
+        );
+        // The first request leaves the window after a minute
+        assert!(
+            usage
+                .begin("a", 10, start + Duration::from_secs(60))
+                .is_ok()
+        );
     }
 
     #[test]
-    fn test_sanitize_key() {
-        assert_eq!(sanitize_key("todos"), "todos");
-        assert_eq!(sanitize_key("user:preferences"), "user_preferences");
+    fn test_ai_token_quota() {
+        let mut usage = AiUsage::new(AiQuota {
+            requests_per_minute: 100,
+            tokens_per_day: 1000,
+        });
+        let start = Instant::now();
+
+        assert!(usage.begin("a", 300, start).is_ok());
+        usage.record("a", 600, start);
+        assert_eq!(
+            usage.begin("a", 200, start),
+            Err(QuotaError::TokensUsedUp(1000))
+        );
+        assert!(usage.begin("a", 100, start).is_ok());
+        assert!(usage.begin("a", 200, start + DAY).is_ok());
     }
 }
@@ -0,0 +1,796 @@
+//! Tolerant HTML scanning for generated app documents
+//!
+//! Model output is often unfinished or sloppy, so nothing here expects a
+//! well-formed document. Titles, meta tags and scripts are read, and the
+//! theme and SDK injected, with lol_html's HTML5 tokenizer, so comments,
+//! attribute values, tag case and `<script>` contents are handled the way a
+//! browser would.
+//!
+//! `complete_partial` has a small scanner of its own: it needs the byte offset
+//! where an unfinished document stops, which a rewriter does not report.
+
+use crate::bridge;
+use lol_html::html_content::ContentType;
+use lol_html::{
+    ElementContentHandlers, RewriteStrSettings, Selector, element, end, end_tag, rewrite_str, text,
+};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::ops::Range;
+use std::rc::Rc;
+
+/// Elements that never have a closing tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements whose content is text up to their closing tag, never markup
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style", "textarea", "title"];
+
+/// A start or end tag found in a document
+struct Tag {
+    /// Lowercased element name
+    name: String,
+    closing: bool,
+    self_closing: bool,
+    /// Byte range of the tag itself
+    span: Range<usize>,
+    /// For raw text elements: the byte range of the contents, and whether
+    /// the closing tag was found
+    text: Option<(Range<usize>, bool)>,
+}
+
+/// Walks the tags of a document in order
+struct Scanner<'a> {
+    html: &'a str,
+    pos: usize,
+    /// Where an unfinished tag or comment starts, once the scanner reaches one
+    incomplete: Option<usize>,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(html: &'a str) -> Self {
+        Self {
+            html,
+            pos: 0,
+            incomplete: None,
+        }
+    }
+
+    /// Parse the start or end tag at `start`, or `None` if it is unfinished
+    fn tag(&self, start: usize, closing: bool) -> Option<Tag> {
+        let bytes = self.html.as_bytes();
+        let name_start = start + if closing { 2 } else { 1 };
+        let mut i = name_start;
+        while i < bytes.len() && !is_tag_delimiter(bytes[i]) {
+            i += 1;
+        }
+        let name = self.html[name_start..i].to_ascii_lowercase();
+        let mut self_closing = false;
+
+        loop {
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            match bytes.get(i)? {
+                b'>' => break,
+                b'/' => {
+                    i += 1;
+                    self_closing = bytes.get(i) == Some(&b'>');
+                    continue;
+                }
+                _ => {}
+            }
+
+            // An attribute, whose value may contain `>` when quoted
+            i += 1;
+            while i < bytes.len() && !is_tag_delimiter(bytes[i]) && bytes[i] != b'=' {
+                i += 1;
+            }
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            if bytes.get(i) != Some(&b'=') {
+                continue;
+            }
+            i += 1;
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            match bytes.get(i)? {
+                &quote @ (b'"' | b'\'') => {
+                    let len = bytes[i + 1..].iter().position(|&b| b == quote)?;
+                    i += len + 2;
+                }
+                _ => {
+                    while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                        i += 1;
+                    }
+                }
+            }
+        }
+
+        Some(Tag {
+            name,
+            closing,
+            self_closing,
+            span: start..i + 1,
+            text: None,
+        })
+    }
+}
+
+impl Iterator for Scanner<'_> {
+    type Item = Tag;
+
+    fn next(&mut self) -> Option<Tag> {
+        let bytes = self.html.as_bytes();
+        while self.incomplete.is_none() {
+            let start = self.pos + self.html[self.pos..].find('<')?;
+            let rest = &bytes[start..];
+
+            if rest.starts_with(b"<!--") {
+                match self.html[start + 4..].find("-->") {
+                    Some(end) => self.pos = start + 4 + end + 3,
+                    None => self.incomplete = Some(start),
+                }
+                continue;
+            }
+            if rest.starts_with(b"<!") || rest.starts_with(b"<?") || rest.starts_with(b"</>") {
+                // A doctype or other declaration
+                match self.html[start..].find('>') {
+                    Some(end) => self.pos = start + end + 1,
+                    None => self.incomplete = Some(start),
+                }
+                continue;
+            }
+
+            let closing = rest.get(1) == Some(&b'/');
+            let name_at = if closing { 2 } else { 1 };
+            match rest.get(name_at) {
+                Some(b) if b.is_ascii_alphabetic() => {}
+                Some(_) => {
+                    // A stray `<` in text
+                    self.pos = start + 1;
+                    continue;
+                }
+                None => {
+                    self.incomplete = Some(start);
+                    continue;
+                }
+            }
+
+            let Some(mut tag) = self.tag(start, closing) else {
+                self.incomplete = Some(start);
+                continue;
+            };
+            self.pos = tag.span.end;
+
+            if !closing && !tag.self_closing && RAW_TEXT_ELEMENTS.contains(&tag.name.as_str()) {
+                let text_start = tag.span.end;
+                match find_end_tag(self.html, text_start, &tag.name) {
+                    Some(text_end) => {
+                        tag.text = Some((text_start..text_end, true));
+                        // Leave the closing tag to be read next
+                        self.pos = text_end;
+                    }
+                    None => {
+                        // The rest of the document is this element's text
+                        tag.text = Some((text_start..self.html.len(), false));
+                        self.pos = self.html.len();
+                    }
+                }
+            }
+            return Some(tag);
+        }
+        None
+    }
+}
+
+/// Bytes that end a tag or attribute name
+fn is_tag_delimiter(b: u8) -> bool {
+    b.is_ascii_whitespace() || b == b'>' || b == b'/'
+}
+
+/// Offset of the `</name` that closes a raw text element opened before `from`
+fn find_end_tag(html: &str, from: usize, name: &str) -> Option<usize> {
+    let bytes = html.as_bytes();
+    let mut pos = from;
+    while let Some(rel) = html[pos..].find("</") {
+        let at = pos + rel;
+        let name_end = at + 2 + name.len();
+        if bytes.len() >= name_end
+            && bytes[at + 2..name_end].eq_ignore_ascii_case(name.as_bytes())
+            && bytes.get(name_end).is_none_or(|&b| is_tag_delimiter(b))
+        {
+            return Some(at);
+        }
+        pos = at + 2;
+    }
+    None
+}
+
+/// Make a partially streamed document renderable.
+///
+/// A trailing incomplete tag or comment is dropped, an unfinished `<script>`
+/// is held back whole (running half a script only produces errors), and every
+/// element still open is closed.
+pub fn complete_partial(partial: &str) -> String {
+    let mut open: Vec<String> = Vec::new();
+    let mut cut = partial.len();
+    let mut scanner = Scanner::new(partial);
+
+    for tag in scanner.by_ref() {
+        if tag.closing {
+            if let Some(idx) = open.iter().rposition(|open| *open == tag.name) {
+                open.truncate(idx);
+            }
+            continue;
+        }
+        match tag.text {
+            Some((_, false)) if tag.name == "script" => cut = tag.span.start,
+            Some((_, false)) => open.push(tag.name),
+            // Its closing tag is read next
+            Some((_, true)) => {}
+            None if tag.self_closing || VOID_ELEMENTS.contains(&tag.name.as_str()) => {}
+            None => open.push(tag.name),
+        }
+    }
+    if let Some(incomplete) = scanner.incomplete {
+        cut = cut.min(incomplete);
+    }
+
+    let mut completed = partial[..cut].to_string();
+    for name in open.iter().rev() {
+        completed.push_str("</");
+        completed.push_str(name);
+        completed.push('>');
+    }
+    completed
+}
+
+/// Whether `content` is HTML, rather than text such as markdown that may hold
+/// some: it opens with a tag, comment or doctype
+pub fn is_html(content: &str) -> bool {
+    content
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .starts_with('<')
+}
+
+/// Progress through the first HTML `<title>`
+#[derive(Clone, Copy, PartialEq)]
+enum TitleState {
+    Before,
+    Reading,
+    Done,
+}
+
+/// The document title, from the first `<title>` outside inline SVG
+pub fn title(html: &str) -> Option<String> {
+    let state = Cell::new(TitleState::Before);
+    let text = RefCell::new(String::new());
+    // Handlers run in order, so an SVG `<title>` is flagged before the next
+    // handler sees it
+    let in_svg = Cell::new(false);
+
+    rewrite(
+        html,
+        vec![
+            element!("svg title", |_| {
+                in_svg.set(true);
+                Ok(())
+            }),
+            element!("title", |_| {
+                match state.get() {
+                    _ if in_svg.replace(false) => {}
+                    TitleState::Before => state.set(TitleState::Reading),
+                    // The first title was empty
+                    TitleState::Reading => state.set(TitleState::Done),
+                    _ => {}
+                }
+                Ok(())
+            }),
+            text!("title", |chunk| {
+                if state.get() == TitleState::Reading {
+                    text.borrow_mut().push_str(chunk.as_str());
+                    if chunk.last_in_text_node() {
+                        state.set(TitleState::Done);
+                    }
+                }
+                Ok(())
+            }),
+        ],
+    )?;
+
+    non_empty(&text.into_inner())
+}
+
+/// The `content` of the first `<meta>` whose `name` or `property` is `name`
+pub fn meta(html: &str, name: &str) -> Option<String> {
+    let content = RefCell::new(None::<String>);
+
+    rewrite(
+        html,
+        vec![element!("meta", |el| {
+            let matches = ["name", "property"]
+                .into_iter()
+                .filter_map(|attr| el.get_attribute(attr))
+                .any(|value| decode_entities(&value).trim().eq_ignore_ascii_case(name));
+            if matches && content.borrow().is_none() {
+                content.replace(el.get_attribute("content"));
+            }
+            Ok(())
+        })],
+    )?;
+
+    non_empty(&content.into_inner()?)
+}
+
+/// Set the `content` of the `<meta>` named `name`, adding one to the first
+/// `<head>` (or a new one at the start of `<html>`, or the end of the
+/// document) if there is none
+pub fn set_meta(html: &str, name: &str, content: &str) -> String {
+    let named = |value: Option<String>| {
+        value.is_some_and(|value| decode_entities(&value).trim().eq_ignore_ascii_case(name))
+    };
+
+    let exists = Cell::new(false);
+    let _ = rewrite(
+        html,
+        vec![element!("meta", |el| {
+            if named(el.get_attribute("name")) {
+                exists.set(true);
+            }
+            Ok(())
+        })],
+    );
+    if exists.get() {
+        let updated = Cell::new(false);
+        return rewrite(
+            html,
+            vec![element!("meta", |el| {
+                if named(el.get_attribute("name")) && !updated.replace(true) {
+                    el.set_attribute("content", content)?;
+                }
+                Ok(())
+            })],
+        )
+        .unwrap_or_else(|| html.to_string());
+    }
+
+    let tag = format!(
+        "<meta name=\"{}\" content=\"{}\">",
+        escape_attribute(name),
+        escape_attribute(content)
+    );
+    let Some(target) = ["head", "html"]
+        .into_iter()
+        .find(|selector| has_element(html, selector))
+    else {
+        return format!("{}{}", html, tag);
+    };
+    let tag = if target == "html" {
+        format!("<head>{}</head>", tag)
+    } else {
+        tag
+    };
+    let placed = Cell::new(false);
+    rewrite(
+        html,
+        vec![element!(target, |el| {
+            if !placed.replace(true) {
+                el.prepend(&tag, ContentType::Html);
+            }
+            Ok(())
+        })],
+    )
+    .unwrap_or_else(|| html.to_string())
+}
+
+/// The `src` of every `<script>` loaded from another origin, such as a CDN
+pub fn external_scripts(html: &str) -> Vec<String> {
+    let sources = RefCell::new(Vec::new());
+
+    let _ = rewrite(
+        html,
+        vec![element!("script[src]", |el| {
+            let src = decode_entities(&el.get_attribute("src").unwrap_or_default());
+            let src = src.trim();
+            let lower = src.to_ascii_lowercase();
+            if lower.starts_with("https://")
+                || lower.starts_with("http://")
+                || lower.starts_with("//")
+            {
+                sources.borrow_mut().push(src.to_string());
+            }
+            Ok(())
+        })],
+    );
+
+    sources.into_inner()
+}
+
+/// Script `type`s that hold JavaScript; anything else (JSON, templates,
+/// shaders, JSX) is data to the browser
+const JS_SCRIPT_TYPES: &[&str] = &[
+    "",
+    "text/javascript",
+    "application/javascript",
+    "application/x-javascript",
+    "text/ecmascript",
+    "application/ecmascript",
+    "module",
+];
+
+/// JavaScript written inline in a document
+#[derive(Clone, Debug, PartialEq)]
+pub struct InlineScript {
+    pub code: String,
+    /// Where the code starts in the document, counting from 1
+    pub line: usize,
+    pub column: usize,
+    /// A `type="module"` script, which follows module rules
+    pub module: bool,
+}
+
+/// Every inline JavaScript `<script>`, in document order
+pub fn inline_scripts(html: &str) -> Vec<InlineScript> {
+    // Code and start offset of each script, filled in as its text arrives
+    let scripts = RefCell::new(Vec::<(String, Option<usize>, bool)>::new());
+    let reading = Cell::new(false);
+
+    let _ = rewrite(
+        html,
+        vec![
+            element!("script", |el| {
+                let kind = el.get_attribute("type").unwrap_or_default();
+                let kind = kind.trim().to_ascii_lowercase();
+                let is_js = !el.has_attribute("src") && JS_SCRIPT_TYPES.contains(&kind.as_str());
+                if is_js {
+                    scripts
+                        .borrow_mut()
+                        .push((String::new(), None, kind == "module"));
+                }
+                reading.set(is_js);
+                Ok(())
+            }),
+            text!("script", |chunk| {
+                if reading.get()
+                    && let Some((code, start, _)) = scripts.borrow_mut().last_mut()
+                {
+                    start.get_or_insert(chunk.source_location().bytes().start);
+                    code.push_str(chunk.as_str());
+                }
+                Ok(())
+            }),
+        ],
+    );
+
+    scripts
+        .into_inner()
+        .into_iter()
+        .map(|(code, start, module)| {
+            let before = &html[..start.unwrap_or(0).min(html.len())];
+            let line_start = before.rfind('\n').map_or(0, |nl| nl + 1);
+            InlineScript {
+                code,
+                line: before.matches('\n').count() + 1,
+                column: before[line_start..].chars().count() + 1,
+                module,
+            }
+        })
+        .collect()
+}
+
+/// Inject the theme CSS at the start of `<head>` and the Blackbird SDK
+/// before `</body>`. Apps marked with an older [`bridge::SDK_VERSION_META`]
+/// get the SDK they were written for.
+pub fn inject_theme_and_sdk(html: &str, theme_css: &str, app_id: &str) -> String {
+    let style = format!("<style>{}</style>", theme_css);
+    let sdk = match meta(html, bridge::SDK_VERSION_META) {
+        Some(version) if version == bridge::SYNC_STORAGE_SDK_VERSION => {
+            bridge::get_sync_storage_sdk_script(
+                app_id,
+                meta(html, bridge::LEGACY_ID_META).as_deref(),
+            )
+        }
+        _ => bridge::get_sdk_script(app_id),
+    };
+
+    // The theme goes into the first `<head>`, or a new one at the start of
+    // `<html>`, or failing both before the first element
+    let target = ["head", "html", "*"]
+        .into_iter()
+        .find(|selector| has_element(html, selector))
+        .unwrap_or("*");
+    let style = if target == "html" {
+        format!("<head>{}</head>", style)
+    } else {
+        style
+    };
+    let styled = Cell::new(false);
+    // End tag handlers outlive this call, so they share the flag by `Rc`
+    let sdk_placed = Rc::new(Cell::new(false));
+
+    let mut handlers = vec![element!(target, |el| {
+        if !styled.replace(true) {
+            if target == "*" {
+                el.before(&style, ContentType::Html);
+            } else {
+                el.prepend(&style, ContentType::Html);
+            }
+        }
+        Ok(())
+    })];
+    for container in ["body", "html"] {
+        let sdk = sdk.clone();
+        let sdk_placed = sdk_placed.clone();
+        handlers.push(element!(container, move |el| {
+            let sdk = sdk.clone();
+            let sdk_placed = sdk_placed.clone();
+            // Only fails for void elements, which these are not
+            let _ = el.on_end_tag(end_tag!(move |end| {
+                if !sdk_placed.replace(true) {
+                    end.before(&sdk, ContentType::Html);
+                }
+                Ok(())
+            }));
+            Ok(())
+        }));
+    }
+
+    let settings = RewriteStrSettings {
+        element_content_handlers: handlers,
+        document_content_handlers: vec![end!(|end| {
+            if !sdk_placed.get() {
+                end.append(&sdk, ContentType::Html);
+            }
+            Ok(())
+        })],
+        strict: false,
+        ..RewriteStrSettings::new()
+    };
+    match rewrite_str(html, settings) {
+        Ok(injected) if styled.get() => injected,
+        // No elements at all, just text
+        Ok(injected) => format!("{}{}", style, injected),
+        Err(_) => format!("{}{}{}", style, html, sdk),
+    }
+}
+
+/// Whether `html` contains an element matching `selector`
+fn has_element(html: &str, selector: &str) -> bool {
+    let found = Cell::new(false);
+    let _ = rewrite(
+        html,
+        vec![element!(selector, |_| {
+            found.set(true);
+            Ok(())
+        })],
+    );
+    found.get()
+}
+
+/// Run `html` through lol_html's HTML5 tokenizer with `handlers`. Strict
+/// mode is off so sloppy documents are read rather than rejected.
+fn rewrite<'h>(
+    html: &str,
+    handlers: Vec<(Cow<'_, Selector>, ElementContentHandlers<'h>)>,
+) -> Option<String> {
+    let settings = RewriteStrSettings {
+        element_content_handlers: handlers,
+        strict: false,
+        ..RewriteStrSettings::new()
+    };
+    rewrite_str(html, settings).ok()
+}
+
+/// Escape text for a double-quoted attribute value
+fn escape_attribute(raw: &str) -> String {
+    raw.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+/// Decoded, whitespace-collapsed text, or `None` if it is blank
+fn non_empty(raw: &str) -> Option<String> {
+    let text = decode_entities(raw)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+/// Decode the character references that show up in titles and attributes
+fn decode_entities(raw: &str) -> String {
+    let mut decoded = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let reference = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .map(|end| &rest[1..end + 1]);
+        let ch = reference.and_then(|name| match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => {
+                let code = name.strip_prefix('#')?;
+                let code = match code.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => code.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (ch, reference) {
+            (Some(ch), Some(name)) => {
+                decoded.push(ch);
+                rest = &rest[name.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_elements_are_closed() {
+        assert_eq!(
+            complete_partial("<!DOCTYPE html><html><body><div class=\"a\"><p>Hi<br>there"),
+            "<!DOCTYPE html><html><body><div class=\"a\"><p>Hi<br>there</p></div></body></html>"
+        );
+        assert_eq!(
+            complete_partial("<ul><li>One</li><li>Two</li></ul><div"),
+            "<ul><li>One</li><li>Two</li></ul>"
+        );
+    }
+
+    #[test]
+    fn test_unfinished_script_is_held_back() {
+        let partial = "<body><script>if (a < b) { go(); }</script><main><script>let x = '<div>";
+        assert_eq!(
+            complete_partial(partial),
+            "<body><script>if (a < b) { go(); }</script><main></main></body>"
+        );
+    }
+
+    #[test]
+    fn test_unfinished_style_and_comment() {
+        assert_eq!(
+            complete_partial("<head><style>body { color: red"),
+            "<head><style>body { color: red</style></head>"
+        );
+        assert_eq!(complete_partial("<div><!-- note"), "<div></div>");
+        assert_eq!(
+            complete_partial("<div title=\"a > b\"><span data-x='"),
+            "<div title=\"a > b\"></div>"
+        );
+    }
+
+    #[test]
+    fn test_title_skips_scripts_comments_and_svg() {
+        let html = "<!-- <title>Old</title> --><HTML><Head lang=\"en\">\
+            <script>document.write('<title>Fake</title>')</script>\
+            <TITLE>\n  Caf&#233; &amp; Crème \n</TITLE></Head></HTML>";
+        assert_eq!(title(html).as_deref(), Some("Café & Crème"));
+
+        let svg_only = "<body><svg><title>Icon</title></svg><title>Tasks</title></body>";
+        assert_eq!(title(svg_only).as_deref(), Some("Tasks"));
+        assert_eq!(title("<title>  </title>"), None);
+        assert_eq!(title("<p>No title</p>"), None);
+    }
+
+    #[test]
+    fn test_meta_and_external_scripts() {
+        let html = "<head><meta charset=utf-8>\
+            <META NAME=\"Description\" content='Tracks &quot;habits&quot;'>\
+            <script src=\"https://cdn.jsdelivr.net/npm/chart.js@4.4.1/dist/chart.umd.min.js\"></script>\
+            <script src=app.js></script><script src=//unpkg.com/d3></script></head>";
+        assert_eq!(
+            meta(html, "description").as_deref(),
+            Some("Tracks \"habits\"")
+        );
+        assert_eq!(meta(html, "keywords"), None);
+        assert_eq!(
+            external_scripts(html),
+            [
+                "https://cdn.jsdelivr.net/npm/chart.js@4.4.1/dist/chart.umd.min.js",
+                "//unpkg.com/d3"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_html() {
+        assert!(is_html("<!DOCTYPE html><html></html>"));
+        assert!(is_html("\u{feff}\n  <!-- app --><div></div>"));
+        assert!(!is_html("# Page\n\n<html> is the root element"));
+        assert!(!is_html("```html\n<html></html>\n```"));
+    }
+
+    #[test]
+    fn test_set_meta() {
+        let html = "<html><head><title>T</title></head><body></body></html>";
+        let with_id = set_meta(html, "blackbird-app-id", "1234");
+        assert_eq!(
+            with_id,
+            "<html><head><meta name=\"blackbird-app-id\" content=\"1234\"><title>T</title></head><body></body></html>"
+        );
+        assert_eq!(meta(&with_id, "blackbird-app-id").as_deref(), Some("1234"));
+
+        // An existing tag is updated rather than repeated
+        let updated = set_meta(&with_id, "Blackbird-App-Id", "5678");
+        assert_eq!(meta(&updated, "blackbird-app-id").as_deref(), Some("5678"));
+        assert_eq!(updated.matches("<meta").count(), 1);
+
+        let headless = set_meta("<html><p>Hi</p></html>", "id", "a\"b");
+        assert!(headless.starts_with("<html><head><meta name=\"id\" content=\"a&quot;b\"></head>"));
+        assert_eq!(meta(&headless, "id").as_deref(), Some("a\"b"));
+
+        // Markdown keeps its first line, which is its title
+        let markdown = set_meta("# Notes\n\nText", "id", "a");
+        assert!(markdown.starts_with("# Notes\n"));
+        assert_eq!(meta(&markdown, "id").as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn test_inline_scripts() {
+        let html = "<head>\n<script src=\"lib.js\"></script>\n  <script>let a = 1;</script>\n\
+            <script type=\"application/json\">{}</script>\n\
+            <script type=\"module\">\nimport x from 'y';</script></head>";
+        let scripts = inline_scripts(html);
+        assert_eq!(scripts.len(), 2);
+        assert_eq!(scripts[0].code, "let a = 1;");
+        assert_eq!((scripts[0].line, scripts[0].column), (3, 11));
+        assert!(!scripts[0].module);
+        assert_eq!(scripts[1].code, "\nimport x from 'y';");
+        assert_eq!(scripts[1].line, 5);
+        assert!(scripts[1].module);
+    }
+
+    #[test]
+    fn test_injection_points() {
+        let html = "<!DOCTYPE html><html lang=\"é\"><head lang=\"en\"><title>Ünïcode</title></head>\
+            <body><script>if (x) '</body>';</script></body></html>";
+        let injected = inject_theme_and_sdk(html, ":root{}", "app");
+        assert!(injected.starts_with(
+            "<!DOCTYPE html><html lang=\"é\"><head lang=\"en\"><style>:root{}</style><title>"
+        ));
+        let sdk = injected.find("const APP_ID = 'app'").unwrap();
+        assert!(sdk > injected.find("'</body>';</script>").unwrap());
+        assert!(injected.ends_with("</script></body></html>"));
+
+        let headless = inject_theme_and_sdk("<html><p>Hi</p></html>", "a{}", "app");
+        assert!(headless.starts_with("<html><head><style>a{}</style></head><p>Hi</p>"));
+        assert!(headless.ends_with("</script></html>"));
+
+        let fragment = inject_theme_and_sdk("<!doctype html><p>Hi</p>", "a{}", "app");
+        assert!(fragment.starts_with("<!doctype html><style>a{}</style><p>Hi</p><script>"));
+    }
+
+    #[test]
+    fn test_sdk_version() {
+        let current = inject_theme_and_sdk("<html><body></body></html>", "", "app");
+        assert!(current.contains("const PRIMED = null;"));
+
+        let marked = "<html><head><meta name=\"blackbird-sdk\" content=\"1\"></head></html>";
+        let sync = inject_theme_and_sdk(marked, "", "html-test-sync-sdk");
+        assert!(sync.contains("const PRIMED = {};"));
+    }
+}
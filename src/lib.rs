@@ -1,7 +1,11 @@
 pub mod ai;
 pub mod bridge;
+pub mod html;
+pub mod patch;
+pub mod storage;
 pub mod theme;
 pub mod tools;
 pub mod types;
 pub mod ui;
+pub mod validate;
 pub mod views;
@@ -0,0 +1,300 @@
+//! Edits to app source returned by the model instead of a whole document
+//!
+//! Two formats are accepted, and may be mixed in one reply:
+//!
+//! ```text
+//! <<<<<<< SEARCH
+//! <h1>Timer</h1>
+//! =======
+//! <h1>Pomodoro</h1>
+//! >>>>>>> REPLACE
+//! ```
+//!
+//! and unified diff hunks (`@@ ... @@` followed by ` `, `-` and `+` lines).
+//! Hunk line numbers are ignored; each hunk is located by its context and
+//! removed lines, like a search/replace block.
+
+/// Opens the text to find in a search/replace block
+const SEARCH_MARKER: &str = "<<<<<<< SEARCH";
+
+/// Separates the text to find from its replacement
+const DIVIDER_MARKER: &str = "=======";
+
+/// Closes a search/replace block
+const REPLACE_MARKER: &str = ">>>>>>> REPLACE";
+
+/// One change: replace `search` with `replace`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edit {
+    pub search: String,
+    pub replace: String,
+}
+
+/// Why a set of edits could not be applied
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum PatchError {
+    /// The reply contains no edits
+    #[error("No edits found")]
+    Empty,
+
+    /// An edit block or hunk is incomplete
+    #[error("Malformed edit: {0}")]
+    Malformed(String),
+
+    /// Edit `index` (counting from 1) was not found in the source
+    #[error("Edit {index} did not match the current source")]
+    NoMatch { index: usize },
+
+    /// Edit `index` (counting from 1) matched more than one place
+    #[error("Edit {index} matched {count} places in the current source")]
+    Ambiguous { index: usize, count: usize },
+}
+
+/// Whether a reply is written as edits rather than a whole document
+pub fn is_edit_reply(reply: &str) -> bool {
+    reply.lines().any(|line| {
+        let line = line.trim();
+        line.starts_with(SEARCH_MARKER) || line.starts_with("@@ ")
+    })
+}
+
+/// Parse every search/replace block and diff hunk in a reply, in order
+pub fn parse_edits(reply: &str) -> Result<Vec<Edit>, PatchError> {
+    let lines: Vec<&str> = reply.lines().collect();
+    let mut edits = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        if line.trim().starts_with(SEARCH_MARKER) {
+            let (edit, next) = parse_block(&lines, i + 1)?;
+            edits.push(edit);
+            i = next;
+        } else if line.starts_with("@@") {
+            let (edit, next) = parse_hunk(&lines, i + 1)?;
+            edits.push(edit);
+            i = next;
+        } else {
+            i += 1;
+        }
+    }
+
+    if edits.is_empty() {
+        return Err(PatchError::Empty);
+    }
+    Ok(edits)
+}
+
+/// Apply `edits` to `source` one after another. Nothing is applied unless
+/// every edit matches exactly one place.
+pub fn apply_edits(source: &str, edits: &[Edit]) -> Result<String, PatchError> {
+    let mut patched = source.to_string();
+    for (n, edit) in edits.iter().enumerate() {
+        let index = n + 1;
+        let range = locate(&patched, &edit.search, index)?;
+        patched.replace_range(range, &edit.replace);
+    }
+    Ok(patched)
+}
+
+/// Parse a reply and apply its edits to `source`
+pub fn apply_reply(source: &str, reply: &str) -> Result<String, PatchError> {
+    apply_edits(source, &parse_edits(reply)?)
+}
+
+fn parse_block(lines: &[&str], start: usize) -> Result<(Edit, usize), PatchError> {
+    let mut search = Vec::new();
+    let mut replace = Vec::new();
+    let mut in_replace = false;
+
+    for (offset, line) in lines[start..].iter().enumerate() {
+        let marker = line.trim();
+        if !in_replace && marker == DIVIDER_MARKER {
+            in_replace = true;
+        } else if in_replace && marker.starts_with(REPLACE_MARKER) {
+            let edit = Edit {
+                search: search.join("\n"),
+                replace: replace.join("\n"),
+            };
+            return Ok((edit, start + offset + 1));
+        } else if in_replace {
+            replace.push(*line);
+        } else {
+            search.push(*line);
+        }
+    }
+
+    Err(PatchError::Malformed(format!(
+        "search/replace block missing its `{}` line",
+        if in_replace {
+            REPLACE_MARKER
+        } else {
+            DIVIDER_MARKER
+        }
+    )))
+}
+
+fn parse_hunk(lines: &[&str], start: usize) -> Result<(Edit, usize), PatchError> {
+    let mut search = Vec::new();
+    let mut replace = Vec::new();
+    let mut end = start;
+
+    while end < lines.len() {
+        let line = lines[end];
+        let next = lines.get(end + 1).copied().unwrap_or_default();
+        // The header of the next file, or the end of the diff
+        if line.starts_with("@@")
+            || line.starts_with("diff ")
+            || line.starts_with("```")
+            || (line.starts_with("--- ") && next.starts_with("+++ "))
+        {
+            break;
+        }
+        if let Some(removed) = line.strip_prefix('-') {
+            search.push(removed);
+        } else if let Some(added) = line.strip_prefix('+') {
+            replace.push(added);
+        } else if let Some(context) = line.strip_prefix(' ') {
+            search.push(context);
+            replace.push(context);
+        } else if line.is_empty() {
+            // Blank context lines often lose their leading space
+            search.push("");
+            replace.push("");
+        } else if !line.starts_with('\\') {
+            // "\ No newline at end of file" is skipped; anything else ends the hunk
+            break;
+        }
+        end += 1;
+    }
+
+    // Trailing blank lines are usually separators rather than context
+    while search.last() == Some(&"") && replace.last() == Some(&"") {
+        search.pop();
+        replace.pop();
+    }
+    if search.iter().all(|line| line.trim().is_empty()) {
+        return Err(PatchError::Malformed(
+            "diff hunk has no context or removed lines to locate it".to_string(),
+        ));
+    }
+
+    let edit = Edit {
+        search: search.join("\n"),
+        replace: replace.join("\n"),
+    };
+    Ok((edit, end))
+}
+
+/// Byte range in `source` that `search` refers to: an exact match, or failing
+/// that, lines that match once surrounding whitespace is ignored
+fn locate(source: &str, search: &str, index: usize) -> Result<std::ops::Range<usize>, PatchError> {
+    if search.trim().is_empty() {
+        return Err(PatchError::Malformed(format!(
+            "edit {} has nothing to search for",
+            index
+        )));
+    }
+
+    let exact: Vec<usize> = source.match_indices(search).map(|(at, _)| at).collect();
+    match exact.len() {
+        1 => return Ok(exact[0]..exact[0] + search.len()),
+        0 => {}
+        count => return Err(PatchError::Ambiguous { index, count }),
+    }
+
+    let wanted: Vec<&str> = search
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| line.is_empty())
+        .collect();
+    let wanted_len = wanted
+        .iter()
+        .rposition(|line| !line.is_empty())
+        .map_or(0, |last| last + 1);
+    let wanted = &wanted[..wanted_len];
+
+    // (start, end) byte offsets of each line, without its line break
+    let mut spans = Vec::new();
+    let mut offset = 0;
+    for line in source.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\n', '\r']);
+        spans.push((offset, offset + text.len()));
+        offset += line.len();
+    }
+
+    let found: Vec<std::ops::Range<usize>> = spans
+        .windows(wanted.len())
+        .filter(|window| {
+            window
+                .iter()
+                .zip(wanted)
+                .all(|(&(start, end), want)| source[start..end].trim() == *want)
+        })
+        .map(|window| window[0].0..window[window.len() - 1].1)
+        .collect();
+    match found.len() {
+        1 => Ok(found[0].clone()),
+        0 => Err(PatchError::NoMatch { index }),
+        count => Err(PatchError::Ambiguous { index, count }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str =
+        "<html>\n<body>\n  <h1>Timer</h1>\n  <button>Start</button>\n</body>\n</html>";
+
+    #[test]
+    fn test_search_replace_block() {
+        let reply = "<<<<<<< SEARCH\n<h1>Timer</h1>\n=======\n<h1>Pomodoro</h1>\n>>>>>>> REPLACE\n[[app_tags: Timer]]";
+        assert!(is_edit_reply(reply));
+        let patched = apply_reply(SOURCE, reply).unwrap();
+        assert!(patched.contains("  <h1>Pomodoro</h1>\n  <button>"));
+    }
+
+    #[test]
+    fn test_whitespace_tolerant_match() {
+        let edits = [Edit {
+            search: "<h1>Timer</h1>\n<button>Start</button>\n".to_string(),
+            replace: "  <h1>Timer</h1>".to_string(),
+        }];
+        assert_eq!(
+            apply_edits(SOURCE, &edits).unwrap(),
+            "<html>\n<body>\n  <h1>Timer</h1>\n</body>\n</html>"
+        );
+    }
+
+    #[test]
+    fn test_unified_diff_hunk() {
+        let reply = "```diff\n--- a/index.html\n+++ b/index.html\n@@ -3,2 +3,3 @@\n   <h1>Timer</h1>\n-  <button>Start</button>\n+  <button>Go</button>\n+  <button>Reset</button>\n```";
+        let patched = apply_reply(SOURCE, reply).unwrap();
+        assert!(
+            patched.contains(
+                "<h1>Timer</h1>\n  <button>Go</button>\n  <button>Reset</button>\n</body>"
+            )
+        );
+    }
+
+    #[test]
+    fn test_failures() {
+        assert_eq!(apply_reply(SOURCE, "<html></html>"), Err(PatchError::Empty));
+        let missing = "<<<<<<< SEARCH\n<h2>Nope</h2>\n=======\n<h2>Yes</h2>\n>>>>>>> REPLACE";
+        assert_eq!(
+            apply_reply(SOURCE, missing),
+            Err(PatchError::NoMatch { index: 1 })
+        );
+        let twice = "<<<<<<< SEARCH\nhtml>\n=======\nx\n>>>>>>> REPLACE";
+        assert_eq!(
+            apply_reply(SOURCE, twice),
+            Err(PatchError::Ambiguous { index: 1, count: 2 })
+        );
+        let unterminated = "<<<<<<< SEARCH\n<h1>Timer</h1>\n=======\n<h1>";
+        assert!(matches!(
+            apply_reply(SOURCE, unterminated),
+            Err(PatchError::Malformed(_))
+        ));
+    }
+}
@@ -0,0 +1,283 @@
+use super::{StorageBackend, StorageError, StorageResult, StorageUsage};
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Longest file name stem used for an encoded key, leaving room for the
+/// extension within common 255-byte file name limits
+const MAX_KEY_STEM: usize = 200;
+
+/// Starts the file name of a key too long to encode; never produced by [`encode_key`]
+const HASHED_KEY_PREFIX: char = '~';
+
+/// App data as one file per key, in a directory per app
+///
+/// Values are written to a temporary file and renamed into place, so a crash
+/// leaves either the old value or the new one. Batches are not atomic.
+#[derive(Clone, Debug)]
+pub struct FileBackend {
+    root: PathBuf,
+}
+
+impl FileBackend {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The storage directory for a specific app
+    fn app_dir(&self, app_id: &str) -> PathBuf {
+        self.root.join(sanitize_app_id(app_id))
+    }
+}
+
+impl StorageBackend for FileBackend {
+    fn get(&self, app_id: &str, key: &str) -> StorageResult<Option<String>> {
+        let files = key_files(&self.app_dir(app_id), key);
+        if let Some(key_path) = &files.key
+            && read_optional(key_path)?.as_deref() != Some(key)
+        {
+            return Ok(None);
+        }
+        read_optional(&files.value)
+    }
+
+    fn set(&self, app_id: &str, key: &str, value: &str) -> StorageResult<()> {
+        let storage_dir = self.app_dir(app_id);
+        fs::create_dir_all(&storage_dir)?;
+        let files = key_files(&storage_dir, key);
+        if let Some(key_path) = &files.key {
+            match read_optional(key_path)? {
+                Some(existing) if existing != key => return Err(StorageError::KeyCollision),
+                Some(_) => {}
+                None => write_atomic(key_path, key)?,
+            }
+        }
+        write_atomic(&files.value, value)?;
+        Ok(())
+    }
+
+    fn delete(&self, app_id: &str, key: &str) -> StorageResult<()> {
+        let files = key_files(&self.app_dir(app_id), key);
+        if let Some(key_path) = &files.key {
+            if read_optional(key_path)?.as_deref() != Some(key) {
+                return Ok(());
+            }
+            fs::remove_file(key_path)?;
+        }
+        match fs::remove_file(files.value) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn keys(&self, app_id: &str) -> StorageResult<Vec<String>> {
+        let storage_dir = self.app_dir(app_id);
+        Ok(dir_entries(&storage_dir)?
+            .filter_map(|entry| entry_key(&storage_dir, &entry))
+            .collect())
+    }
+
+    fn app_ids(&self) -> StorageResult<Vec<String>> {
+        Ok(dir_entries(&self.root)?
+            .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect())
+    }
+
+    /// Sizes come from file metadata, so values are not read
+    fn usage(&self, app_id: &str) -> StorageResult<StorageUsage> {
+        let storage_dir = self.app_dir(app_id);
+        let mut usage = StorageUsage::default();
+        for entry in dir_entries(&storage_dir)? {
+            if let Some(key) = entry_key(&storage_dir, &entry)
+                && let Ok(metadata) = entry.metadata()
+            {
+                usage.bytes += key.len() as u64 + metadata.len();
+                usage.keys += 1;
+            }
+        }
+        Ok(usage)
+    }
+
+    fn clear(&self, app_id: &str) -> StorageResult<()> {
+        match fs::remove_dir_all(self.app_dir(app_id)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The entries of a storage directory, which may not exist yet
+fn dir_entries(storage_dir: &Path) -> StorageResult<impl Iterator<Item = fs::DirEntry>> {
+    let entries = match fs::read_dir(storage_dir) {
+        Ok(entries) => Some(entries),
+        Err(err) if err.kind() == ErrorKind::NotFound => None,
+        Err(err) => return Err(err.into()),
+    };
+    Ok(entries.into_iter().flatten().flatten())
+}
+
+/// The key whose value is stored in `entry`, if it holds one
+fn entry_key(storage_dir: &Path, entry: &fs::DirEntry) -> Option<String> {
+    // Not `file_stem`, which takes the empty key's `.json` for a dotfile
+    let name = entry.file_name();
+    let stem = name.to_str()?.strip_suffix(".json")?;
+    if stem.starts_with(HASHED_KEY_PREFIX) {
+        fs::read_to_string(storage_dir.join(format!("{}.key", stem))).ok()
+    } else {
+        decode_key(stem)
+    }
+}
+
+/// Sanitize app ID for filesystem use
+fn sanitize_app_id(app_id: &str) -> String {
+    app_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Encode a storage key as a file name stem. Lowercase ASCII letters, digits
+/// and `-` are kept; every other byte becomes `_` and two lowercase hex
+/// digits. Distinct keys never share a file, even on case-insensitive file
+/// systems, and [`decode_key`] recovers the original.
+fn encode_key(key: &str) -> String {
+    let mut encoded = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        match byte {
+            b'a'..=b'z' | b'0'..=b'9' | b'-' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("_{:02x}", byte)),
+        }
+    }
+    encoded
+}
+
+/// The key [`encode_key`] turned into `stem`, if it is one
+fn decode_key(stem: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(stem.len());
+    let mut rest = stem.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        match byte {
+            b'a'..=b'z' | b'0'..=b'9' | b'-' => {
+                bytes.push(byte);
+                rest = tail;
+            }
+            b'_' if tail.len() >= 2 => {
+                let hex = std::str::from_utf8(&tail[..2]).ok()?;
+                if hex.bytes().any(|b| b.is_ascii_uppercase()) {
+                    return None;
+                }
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                rest = &tail[2..];
+            }
+            _ => return None,
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Where a key's value is stored
+struct KeyFiles {
+    value: PathBuf,
+    /// Holds the key itself, for keys too long to encode in a file name
+    key: Option<PathBuf>,
+}
+
+/// Files for `key` in an app's storage directory. Keys whose encoding is too
+/// long for a file name are stored under a hash of the key instead.
+fn key_files(storage_dir: &Path, key: &str) -> KeyFiles {
+    let encoded = encode_key(key);
+    if encoded.len() <= MAX_KEY_STEM {
+        return KeyFiles {
+            value: storage_dir.join(format!("{}.json", encoded)),
+            key: None,
+        };
+    }
+    let stem = format!("{}{:016x}", HASHED_KEY_PREFIX, fnv1a(key.as_bytes()));
+    KeyFiles {
+        value: storage_dir.join(format!("{}.json", stem)),
+        key: Some(storage_dir.join(format!("{}.key", stem))),
+    }
+}
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` is stable between builds
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// A file's contents, or `None` if it does not exist
+fn read_optional(path: &Path) -> StorageResult<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Write `contents` to a temporary file beside `path`, then rename it into
+/// place, so a crash leaves either the old contents or the new ones
+fn write_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    static NEXT_TEMP: AtomicU64 = AtomicU64::new(0);
+    let temp = path.with_extension(format!(
+        "{}.{}.tmp",
+        std::process::id(),
+        NEXT_TEMP.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let written = fs::File::create(&temp).and_then(|mut file| {
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()
+    });
+    let result = written.and_then(|_| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_app_id() {
+        assert_eq!(sanitize_app_id("my-app"), "my-app");
+        assert_eq!(sanitize_app_id("my app!@#"), "my_app___");
+        assert_eq!(sanitize_app_id("/path/to/file.html"), "_path_to_file_html");
+    }
+
+    #[test]
+    fn test_key_encoding() {
+        assert_eq!(encode_key("todos"), "todos");
+        assert_eq!(encode_key("user:prefs"), "user_3aprefs");
+        assert_eq!(encode_key("user_prefs"), "user_5fprefs");
+        assert_eq!(encode_key("Todo"), "_54odo");
+
+        for key in [
+            "todos",
+            "user:prefs",
+            "user_prefs",
+            "Todo",
+            "café ☕",
+            "../x",
+            "",
+        ] {
+            assert_eq!(decode_key(&encode_key(key)).as_deref(), Some(key));
+        }
+
+        // Names that encode_key never produces
+        assert_eq!(decode_key("Todo"), None);
+        assert_eq!(decode_key("user_prefs"), None);
+        assert_eq!(decode_key("x_3A"), None);
+        assert_eq!(decode_key("x_3"), None);
+    }
+}
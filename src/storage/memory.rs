@@ -0,0 +1,95 @@
+use super::{StorageBackend, StorageError, StorageResult, StorageUsage};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+type Apps = HashMap<String, HashMap<String, String>>;
+
+/// App data kept in memory for the life of the process
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    apps: Mutex<Apps>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn apps(&self) -> StorageResult<MutexGuard<'_, Apps>> {
+        self.apps.lock().map_err(|_| StorageError::Unavailable)
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn get(&self, app_id: &str, key: &str) -> StorageResult<Option<String>> {
+        Ok(self
+            .apps()?
+            .get(app_id)
+            .and_then(|data| data.get(key))
+            .cloned())
+    }
+
+    fn set(&self, app_id: &str, key: &str, value: &str) -> StorageResult<()> {
+        self.apps()?
+            .entry(app_id.to_string())
+            .or_default()
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn delete(&self, app_id: &str, key: &str) -> StorageResult<()> {
+        if let Some(data) = self.apps()?.get_mut(app_id) {
+            data.remove(key);
+        }
+        Ok(())
+    }
+
+    fn keys(&self, app_id: &str) -> StorageResult<Vec<String>> {
+        Ok(self
+            .apps()?
+            .get(app_id)
+            .map(|data| data.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn clear(&self, app_id: &str) -> StorageResult<()> {
+        self.apps()?.remove(app_id);
+        Ok(())
+    }
+
+    fn usage(&self, app_id: &str) -> StorageResult<StorageUsage> {
+        Ok(self
+            .apps()?
+            .get(app_id)
+            .map(|data| StorageUsage {
+                bytes: data
+                    .iter()
+                    .map(|(key, value)| (key.len() + value.len()) as u64)
+                    .sum(),
+                keys: data.len(),
+            })
+            .unwrap_or_default())
+    }
+
+    fn app_ids(&self) -> StorageResult<Vec<String>> {
+        Ok(self.apps()?.keys().cloned().collect())
+    }
+
+    fn set_many(&self, app_id: &str, entries: &[(&str, &str)]) -> StorageResult<()> {
+        let mut apps = self.apps()?;
+        let data = apps.entry(app_id.to_string()).or_default();
+        for (key, value) in entries {
+            data.insert(key.to_string(), value.to_string());
+        }
+        Ok(())
+    }
+
+    fn delete_many(&self, app_id: &str, keys: &[&str]) -> StorageResult<()> {
+        if let Some(data) = self.apps()?.get_mut(app_id) {
+            for key in keys {
+                data.remove(*key);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+//! Storage module for app data
+//!
+//! Every app gets an isolated key-value namespace. The data can live in one of
+//! several backends behind the [`StorageBackend`] trait, chosen at runtime:
+//!
+//! - `file` - One file per key under the app data directory (the default)
+//! - `sqlite` - A single SQLite database, with atomic batch writes
+//! - `memory` - Kept for the life of the process (the only one on the web)
+//!
+//! Set `BLACKBIRD_STORAGE` to pick one, or call [`set_backend`].
+#[cfg(not(target_arch = "wasm32"))]
+mod file;
+mod memory;
+#[cfg(not(target_arch = "wasm32"))]
+mod sqlite;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use file::FileBackend;
+pub use memory::MemoryBackend;
+#[cfg(not(target_arch = "wasm32"))]
+pub use sqlite::SqliteBackend;
+
+use once_cell::sync::Lazy;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+/// Why a storage operation failed
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("Storage I/O failed: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("Storage database failed: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
+    /// Two keys map to the same place in the backend
+    #[error("Storage key collides with another key")]
+    KeyCollision,
+
+    /// The backend's lock was poisoned by a panic
+    #[error("Storage is unavailable")]
+    Unavailable,
+
+    /// A write would take the app past its byte quota
+    #[error("Storage quota exceeded: apps can store up to {0} bytes")]
+    TooManyBytes(u64),
+
+    /// A write would take the app past its key quota
+    #[error("Storage quota exceeded: apps can store up to {0} keys")]
+    TooManyKeys(usize),
+}
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+/// How much an app is storing. Bytes count the UTF-8 length of every key and
+/// value, so the figure is the same whichever backend holds the data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StorageUsage {
+    pub bytes: u64,
+    pub keys: usize,
+}
+
+/// A key-value store holding every app's data, namespaced by app id
+///
+/// The batch operations default to one call per key. Backends that can
+/// apply a batch atomically override them.
+pub trait StorageBackend: Send + Sync {
+    fn get(&self, app_id: &str, key: &str) -> StorageResult<Option<String>>;
+
+    fn set(&self, app_id: &str, key: &str, value: &str) -> StorageResult<()>;
+
+    /// Remove a key; removing one that is not there is not an error
+    fn delete(&self, app_id: &str, key: &str) -> StorageResult<()>;
+
+    /// Every key the app has set, exactly as it was given, in no particular order
+    fn keys(&self, app_id: &str) -> StorageResult<Vec<String>>;
+
+    /// Remove all of the app's data
+    fn clear(&self, app_id: &str) -> StorageResult<()>;
+
+    /// Every app with data, in no particular order. May include apps whose
+    /// keys have all been deleted. The file backend reports ids that are not
+    /// safe file names in their sanitized form, which still names the same
+    /// namespace.
+    fn app_ids(&self) -> StorageResult<Vec<String>>;
+
+    /// How much the app is storing
+    fn usage(&self, app_id: &str) -> StorageResult<StorageUsage> {
+        let keys = self.keys(app_id)?;
+        let key_refs: Vec<&str> = keys.iter().map(String::as_str).collect();
+        let values = self.get_many(app_id, &key_refs)?;
+        Ok(StorageUsage {
+            bytes: keys
+                .iter()
+                .zip(&values)
+                .map(|(key, value)| (key.len() + value.as_ref().map_or(0, String::len)) as u64)
+                .sum(),
+            keys: keys.len(),
+        })
+    }
+
+    fn get_many(&self, app_id: &str, keys: &[&str]) -> StorageResult<Vec<Option<String>>> {
+        keys.iter().map(|key| self.get(app_id, key)).collect()
+    }
+
+    fn set_many(&self, app_id: &str, entries: &[(&str, &str)]) -> StorageResult<()> {
+        entries
+            .iter()
+            .try_for_each(|(key, value)| self.set(app_id, key, value))
+    }
+
+    fn delete_many(&self, app_id: &str, keys: &[&str]) -> StorageResult<()> {
+        keys.iter().try_for_each(|key| self.delete(app_id, key))
+    }
+}
+
+/// The kinds of backend that can be selected at runtime. The web has no
+/// file system, so only memory is available there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackendKind {
+    #[cfg(not(target_arch = "wasm32"))]
+    File,
+    #[cfg(not(target_arch = "wasm32"))]
+    Sqlite,
+    Memory,
+}
+
+impl BackendKind {
+    /// Parse a `BLACKBIRD_STORAGE` value
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            #[cfg(not(target_arch = "wasm32"))]
+            "file" | "files" => Some(Self::File),
+            #[cfg(not(target_arch = "wasm32"))]
+            "sqlite" => Some(Self::Sqlite),
+            "memory" => Some(Self::Memory),
+            _ => None,
+        }
+    }
+
+    /// The backend used unless `BLACKBIRD_STORAGE` picks another
+    #[cfg(not(target_arch = "wasm32"))]
+    pub const DEFAULT: Self = Self::File;
+    #[cfg(target_arch = "wasm32")]
+    pub const DEFAULT: Self = Self::Memory;
+
+    /// Read `BLACKBIRD_STORAGE`, falling back to [`Self::DEFAULT`]
+    pub fn from_env() -> Self {
+        std::env::var("BLACKBIRD_STORAGE")
+            .ok()
+            .and_then(|name| Self::parse(&name))
+            .unwrap_or(Self::DEFAULT)
+    }
+}
+
+/// Where persistent backends keep app data
+#[cfg(not(target_arch = "wasm32"))]
+fn data_dir() -> PathBuf {
+    match dirs::data_local_dir() {
+        Some(dir) => dir.join("blackbird"),
+        None => PathBuf::from("cache"),
+    }
+}
+
+/// Open a backend of `kind` in the app data directory
+pub fn open(kind: BackendKind) -> StorageResult<Arc<dyn StorageBackend>> {
+    Ok(match kind {
+        #[cfg(not(target_arch = "wasm32"))]
+        BackendKind::File => Arc::new(FileBackend::new(data_dir().join("app_data"))),
+        #[cfg(not(target_arch = "wasm32"))]
+        BackendKind::Sqlite => {
+            let dir = data_dir();
+            std::fs::create_dir_all(&dir)?;
+            Arc::new(SqliteBackend::open(dir.join("app_data.sqlite3"))?)
+        }
+        BackendKind::Memory => Arc::new(MemoryBackend::new()),
+    })
+}
+
+static BACKEND: Lazy<RwLock<Arc<dyn StorageBackend>>> = Lazy::new(|| {
+    let kind = BackendKind::from_env();
+    let backend = open(kind).unwrap_or_else(|err| {
+        tracing::warn!(
+            "Failed to open {:?} storage ({}), using the default",
+            kind,
+            err
+        );
+        default_backend()
+    });
+    RwLock::new(backend)
+});
+
+/// The backend used when the chosen one cannot be opened
+#[cfg(not(target_arch = "wasm32"))]
+fn default_backend() -> Arc<dyn StorageBackend> {
+    Arc::new(FileBackend::new(data_dir().join("app_data")))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn default_backend() -> Arc<dyn StorageBackend> {
+    Arc::new(MemoryBackend::new())
+}
+
+/// The backend app data is read from and written to
+pub fn backend() -> Arc<dyn StorageBackend> {
+    match BACKEND.read() {
+        Ok(backend) => backend.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    }
+}
+
+/// Replace the backend for the rest of the process
+pub fn set_backend(backend: Arc<dyn StorageBackend>) {
+    match BACKEND.write() {
+        Ok(mut current) => *current = backend,
+        Err(poisoned) => *poisoned.into_inner() = backend,
+    }
+}
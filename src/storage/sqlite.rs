@@ -0,0 +1,133 @@
+use super::{StorageBackend, StorageError, StorageResult, StorageUsage};
+use rusqlite::{Connection, OptionalExtension, params};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS app_storage (
+    app_id TEXT NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (app_id, key)
+) WITHOUT ROWID";
+
+const UPSERT: &str = "INSERT INTO app_storage (app_id, key, value) VALUES (?1, ?2, ?3)
+    ON CONFLICT (app_id, key) DO UPDATE SET value = excluded.value";
+
+/// App data in one SQLite database. Batch writes are applied in a transaction.
+pub struct SqliteBackend {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteBackend {
+    /// Open (or create) the database at `path`
+    pub fn open(path: impl AsRef<Path>) -> StorageResult<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// A database that lives only as long as the backend
+    pub fn in_memory() -> StorageResult<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> StorageResult<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> StorageResult<MutexGuard<'_, Connection>> {
+        self.conn.lock().map_err(|_| StorageError::Unavailable)
+    }
+}
+
+impl StorageBackend for SqliteBackend {
+    fn get(&self, app_id: &str, key: &str) -> StorageResult<Option<String>> {
+        Ok(self
+            .conn()?
+            .query_row(
+                "SELECT value FROM app_storage WHERE app_id = ?1 AND key = ?2",
+                params![app_id, key],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn set(&self, app_id: &str, key: &str, value: &str) -> StorageResult<()> {
+        self.conn()?.execute(UPSERT, params![app_id, key, value])?;
+        Ok(())
+    }
+
+    fn delete(&self, app_id: &str, key: &str) -> StorageResult<()> {
+        self.conn()?.execute(
+            "DELETE FROM app_storage WHERE app_id = ?1 AND key = ?2",
+            params![app_id, key],
+        )?;
+        Ok(())
+    }
+
+    fn keys(&self, app_id: &str) -> StorageResult<Vec<String>> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare("SELECT key FROM app_storage WHERE app_id = ?1")?;
+        let keys = statement
+            .query_map(params![app_id], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(keys)
+    }
+
+    fn clear(&self, app_id: &str) -> StorageResult<()> {
+        self.conn()?
+            .execute("DELETE FROM app_storage WHERE app_id = ?1", params![app_id])?;
+        Ok(())
+    }
+
+    fn app_ids(&self) -> StorageResult<Vec<String>> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare("SELECT DISTINCT app_id FROM app_storage")?;
+        let app_ids = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(app_ids)
+    }
+
+    fn usage(&self, app_id: &str) -> StorageResult<StorageUsage> {
+        // Lengths of blobs are in bytes; lengths of text are in characters
+        let (bytes, keys): (i64, i64) = self.conn()?.query_row(
+            "SELECT COALESCE(SUM(length(CAST(key AS BLOB)) + length(CAST(value AS BLOB))), 0), COUNT(*)
+                FROM app_storage WHERE app_id = ?1",
+            params![app_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok(StorageUsage {
+            bytes: bytes as u64,
+            keys: keys as usize,
+        })
+    }
+
+    fn set_many(&self, app_id: &str, entries: &[(&str, &str)]) -> StorageResult<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        {
+            let mut statement = tx.prepare(UPSERT)?;
+            for (key, value) in entries {
+                statement.execute(params![app_id, key, value])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn delete_many(&self, app_id: &str, keys: &[&str]) -> StorageResult<()> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        {
+            let mut statement =
+                tx.prepare("DELETE FROM app_storage WHERE app_id = ?1 AND key = ?2")?;
+            for key in keys {
+                statement.execute(params![app_id, key])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
@@ -10,6 +10,17 @@ pub use apps::{GetAppsListTool, SearchAppsTool};
 pub use calculator::CalculatorTool;
 pub use settings::GetSettingTool;
 
+use crate::types::ThemeMode;
+use crate::views::shared::SavedApp;
+use once_cell::sync::Lazy;
+use rig::agent::{AgentBuilder, AgentBuilderSimple};
+use rig::completion::CompletionModel;
+use rig::tool::ToolSet;
+use std::sync::{Arc, RwLock};
+
+/// Maximum number of tool-calling rounds before the model must answer in text
+pub const MAX_TOOL_TURNS: usize = 5;
+
 /// Common error type for all tools
 #[derive(Debug, thiserror::Error)]
 pub enum ToolError {
@@ -22,3 +33,98 @@ pub enum ToolError {
     #[error("Not found: {0}")]
     NotFound(String),
 }
+
+static SHARED_CONTEXT: Lazy<ToolContext> = Lazy::new(ToolContext::default);
+
+/// Application state the tools read from
+///
+/// The UI keeps the shared instance in sync with its signals so tools called
+/// from a background generation see the current apps and settings.
+#[derive(Clone)]
+pub struct ToolContext {
+    pub apps: Arc<RwLock<Vec<SavedApp>>>,
+    pub theme: Arc<RwLock<ThemeMode>>,
+    pub base_font_px: Arc<RwLock<i32>>,
+}
+
+impl Default for ToolContext {
+    fn default() -> Self {
+        Self {
+            apps: Arc::new(RwLock::new(Vec::new())),
+            theme: Arc::new(RwLock::new(ThemeMode::Dark)),
+            base_font_px: Arc::new(RwLock::new(14)),
+        }
+    }
+}
+
+impl ToolContext {
+    /// The process-wide context used by `BlackbirdAI::from_env`
+    pub fn shared() -> Self {
+        SHARED_CONTEXT.clone()
+    }
+
+    pub fn set_apps(&self, apps: Vec<SavedApp>) {
+        if let Ok(mut guard) = self.apps.write() {
+            *guard = apps;
+        }
+    }
+
+    pub fn set_theme(&self, theme: ThemeMode) {
+        if let Ok(mut guard) = self.theme.write() {
+            *guard = theme;
+        }
+    }
+
+    pub fn set_base_font_px(&self, px: i32) {
+        if let Ok(mut guard) = self.base_font_px.write() {
+            *guard = px;
+        }
+    }
+
+    /// Register every Blackbird tool on a Rig agent
+    pub fn attach<M: CompletionModel>(&self, builder: AgentBuilder<M>) -> AgentBuilderSimple<M> {
+        builder
+            .tool(CalculatorTool)
+            .tool(SearchAppsTool::new(self.apps.clone()))
+            .tool(GetAppsListTool::new(self.apps.clone()))
+            .tool(GetSettingTool::new(
+                self.theme.clone(),
+                self.base_font_px.clone(),
+            ))
+    }
+
+    /// The same tools as a `ToolSet`, for providers that run their own tool loop
+    pub fn toolset(&self) -> ToolSet {
+        let mut toolset = ToolSet::default();
+        toolset.add_tool(CalculatorTool);
+        toolset.add_tool(SearchAppsTool::new(self.apps.clone()));
+        toolset.add_tool(GetAppsListTool::new(self.apps.clone()));
+        toolset.add_tool(GetSettingTool::new(
+            self.theme.clone(),
+            self.base_font_px.clone(),
+        ));
+        toolset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_toolset_exposes_all_tools() {
+        let context = ToolContext::default();
+        context.set_theme(ThemeMode::Octane);
+        let toolset = context.toolset();
+
+        for name in ["calculate", "search_apps", "get_apps_list", "get_setting"] {
+            assert!(toolset.contains(name), "missing tool {name}");
+        }
+
+        let result = toolset
+            .call("get_setting", r#"{"setting":"theme"}"#.to_string())
+            .await
+            .unwrap();
+        assert_eq!(result, "\"Octane\"");
+    }
+}
@@ -4,8 +4,12 @@ use time::OffsetDateTime;
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Role {
+    /// Instructions for the model; sent as the provider's system prompt
+    System,
     User,
     Assistant,
+    /// Output of a tool call, answering the call named by `tool_call_id`
+    Tool,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -25,4 +29,7 @@ pub struct ChatMessage {
     #[serde(skip)]
     /// Local-only tags captured for saved documents, not part of LLM requests.
     pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// For `Role::Tool` messages, the id of the tool call being answered.
+    pub tool_call_id: Option<String>,
 }
@@ -1,5 +1,7 @@
 use crate::theme::theme_definition;
+use crate::tools::ToolContext;
 use crate::types::ThemeMode;
+use crate::views::host::{AppConsoles, use_app_host};
 use crate::views::shared::{SavedApp, initial_saved_apps};
 use crate::views::{AppsView, SettingsView, WorkbenchView};
 use dioxus::prelude::*;
@@ -24,8 +26,10 @@ pub fn App() -> Element {
     let base_font_px = use_signal(|| 14i32);
     let theme = use_signal(|| ThemeMode::Dark);
     let show_splash = use_signal(|| true);
+    let consoles = use_app_host();
 
     use_splash_dismiss(show_splash);
+    use_tool_context_sync(saved_apps, theme, base_font_px);
 
     rsx! {
         ThemeStyles { base_font_px, theme }
@@ -38,6 +42,7 @@ pub fn App() -> Element {
             saved_apps,
             base_font_px,
             theme,
+            consoles,
         }
     }
 }
@@ -54,6 +59,20 @@ fn use_splash_dismiss(show_splash: Signal<bool>) {
     });
 }
 
+/// Mirror app state into the shared tool context so AI tools see current data
+fn use_tool_context_sync(
+    saved_apps: Signal<Vec<SavedApp>>,
+    theme: Signal<ThemeMode>,
+    base_font_px: Signal<i32>,
+) {
+    use_effect(move || {
+        let context = ToolContext::shared();
+        context.set_apps(saved_apps());
+        context.set_theme(theme());
+        context.set_base_font_px(base_font_px());
+    });
+}
+
 #[component]
 fn ThemeStyles(base_font_px: Signal<i32>, theme: Signal<ThemeMode>) -> Element {
     let root_style = format!(":root {{ font-size: {}px; }}", base_font_px());
@@ -82,13 +101,14 @@ fn TabPanels(
     saved_apps: Signal<Vec<SavedApp>>,
     base_font_px: Signal<i32>,
     theme: Signal<ThemeMode>,
+    consoles: AppConsoles,
 ) -> Element {
     rsx! {
         div { class: "tab-panels",
             TabPanel {
                 active_tab,
                 tab: AppTab::Workbench,
-                children: rsx!( WorkbenchView { saved_apps, base_font_px, theme } ),
+                children: rsx!( WorkbenchView { saved_apps, base_font_px, theme, consoles } ),
             }
             TabPanel {
                 active_tab,
@@ -0,0 +1,168 @@
+//! Syntax checks for the inline scripts of generated apps
+//!
+//! A script that fails to parse never runs, so the app renders but does
+//! nothing. Each inline `<script>` is parsed with boa's JavaScript parser, and
+//! errors are reported at their line and column in the whole document so the
+//! model can find them.
+
+use crate::html::{self, InlineScript};
+use boa_interner::Interner;
+use boa_parser::{Error as ParseError, Parser, Source};
+use std::fmt;
+
+/// A syntax error in one of a document's scripts
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScriptError {
+    /// Which inline script, counting from 1
+    pub script: usize,
+    /// Where the error is in the document, counting from 1
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Script {}, line {}, column {}: {}",
+            self.script, self.line, self.column, self.message
+        )
+    }
+}
+
+/// The result of checking every inline script in a document
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValidationReport {
+    /// Inline scripts that were parsed
+    pub scripts: usize,
+    pub errors: Vec<ScriptError>,
+}
+
+impl ValidationReport {
+    pub fn is_clean(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// One line for the Workbench log
+    pub fn summary(&self) -> String {
+        let scripts = match self.scripts {
+            1 => "1 script".to_string(),
+            n => format!("{} scripts", n),
+        };
+        match self.errors.len() {
+            0 => format!("{} parsed without errors", scripts),
+            1 => format!("1 syntax error in {}", scripts),
+            n => format!("{} syntax errors in {}", n, scripts),
+        }
+    }
+}
+
+/// Parse every inline JavaScript `<script>` in `html`
+pub fn check_scripts(html: &str) -> ValidationReport {
+    let scripts = html::inline_scripts(html);
+    let errors = scripts
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, script)| check_script(idx + 1, script))
+        .collect();
+    ValidationReport {
+        scripts: scripts.len(),
+        errors,
+    }
+}
+
+fn check_script(index: usize, script: &InlineScript) -> Option<ScriptError> {
+    let mut interner = Interner::default();
+    let mut parser = Parser::new(Source::from_bytes(script.code.as_bytes()));
+    let error = if script.module {
+        parser.parse_module(&mut interner).err()?
+    } else {
+        parser.parse_script(&mut interner).err()?
+    };
+
+    let (line, column) = error_position(&error).unwrap_or_else(|| end_position(&script.code));
+    Some(ScriptError {
+        script: index,
+        // The first line of the script shares its line with the `<script>` tag
+        line: script.line + line - 1,
+        column: if line == 1 {
+            script.column + column - 1
+        } else {
+            column
+        },
+        message: error_message(&error),
+    })
+}
+
+/// Line and column within the script, counting from 1
+fn error_position(error: &ParseError) -> Option<(usize, usize)> {
+    let position = match error {
+        ParseError::Expected { span, .. } | ParseError::Unexpected { span, .. } => span.start(),
+        ParseError::General { position, .. } => *position,
+        ParseError::Lex {
+            err: boa_parser::lexer::Error::Syntax(_, position),
+        } => *position,
+        _ => return None,
+    };
+    Some((
+        position.line_number() as usize,
+        position.column_number() as usize,
+    ))
+}
+
+/// The parser's message without its script-relative position
+fn error_message(error: &ParseError) -> String {
+    if matches!(error, ParseError::AbruptEnd) {
+        return "unexpected end of script (unclosed bracket, string or comment?)".to_string();
+    }
+    let message = error.to_string();
+    match message.rfind(" at line ") {
+        Some(at) => message[..at].to_string(),
+        None => message,
+    }
+}
+
+/// Where a script runs out, for errors reported at its end
+fn end_position(code: &str) -> (usize, usize) {
+    let line = code.matches('\n').count() + 1;
+    let last = code.rsplit('\n').next().unwrap_or_default();
+    (line, last.chars().count() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_scripts() {
+        let html = "<body><!-- <script>bad(</script> --><script>\
+            const items = [1, 2].map((n) => n * 2);\n\
+            document.title = `${items.length} items`;</script>\
+            <script type=\"module\">export const x = await Promise.resolve(1);</script>\
+            <script type=\"text/template\"><div>{{ not js }}</div></script></body>";
+        let report = check_scripts(html);
+        assert_eq!(report.scripts, 2);
+        assert!(report.is_clean());
+        assert_eq!(report.summary(), "2 scripts parsed without errors");
+    }
+
+    #[test]
+    fn test_errors_are_located_in_the_document() {
+        let html = "<!DOCTYPE html>\n<html><body>\n<script>let a = 1;</script>\n\
+            <script>\nfunction start() {\n  let x = ;\n}\n</script>\n\
+            <script>if (ready) { go();</script></body></html>";
+        let report = check_scripts(html);
+        assert_eq!(report.scripts, 3);
+        assert_eq!(report.errors.len(), 2);
+
+        let first = &report.errors[0];
+        assert_eq!((first.script, first.line, first.column), (2, 6, 11));
+        assert!(!first.message.contains("at line"));
+
+        let second = &report.errors[1];
+        assert_eq!((second.script, second.line), (3, 9));
+        assert!(second.message.contains("unexpected end"));
+        assert_eq!(report.summary(), "2 syntax errors in 3 scripts");
+    }
+}
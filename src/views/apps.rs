@@ -1,10 +1,13 @@
 use crate::bridge;
+use crate::html;
+use crate::storage::StorageUsage;
 use crate::types::ThemeMode;
-use crate::views::shared::SavedApp;
+use crate::views::shared::{SavedApp, delete_app, format_bytes};
 use dioxus::{
     events::{FormEvent, Key, KeyboardEvent, MouseEvent},
     prelude::*,
 };
+use std::collections::HashMap;
 use time::{OffsetDateTime, UtcOffset, format_description::FormatItem, macros::format_description};
 
 /// CSS that gets injected into apps to match Blackbird's theme
@@ -133,47 +136,6 @@ fn app_theme_css(theme: ThemeMode) -> &'static str {
     }
 }
 
-fn inject_theme_css(html: &str, theme_css: &str) -> String {
-    if let Some(pos) = html.to_lowercase().find("<head>") {
-        let insert_pos = pos + 6;
-        format!(
-            "{}<style>{}</style>{}",
-            &html[..insert_pos],
-            theme_css,
-            &html[insert_pos..]
-        )
-    } else if let Some(pos) = html.to_lowercase().find("<html") {
-        if let Some(end) = html[pos..].find('>') {
-            let insert_pos = pos + end + 1;
-            format!(
-                "{}<head><style>{}</style></head>{}",
-                &html[..insert_pos],
-                theme_css,
-                &html[insert_pos..]
-            )
-        } else {
-            format!("<style>{}</style>{}", theme_css, html)
-        }
-    } else {
-        format!("<style>{}</style>{}", theme_css, html)
-    }
-}
-
-/// Inject both theme CSS and the Blackbird SDK into app HTML
-fn inject_theme_and_sdk(html: &str, theme_css: &str, app_id: &str) -> String {
-    let sdk_script = bridge::get_sdk_script(app_id);
-    let themed = inject_theme_css(html, theme_css);
-
-    // Insert SDK script before </body> or at the end
-    if let Some(pos) = themed.to_lowercase().rfind("</body>") {
-        format!("{}{}{}", &themed[..pos], sdk_script, &themed[pos..])
-    } else if let Some(pos) = themed.to_lowercase().rfind("</html>") {
-        format!("{}{}{}", &themed[..pos], sdk_script, &themed[pos..])
-    } else {
-        format!("{}{}", themed, sdk_script)
-    }
-}
-
 const APP_DATE_FORMAT: &[FormatItem<'static>] =
     format_description!("[month repr:short] [day padding:zero], [year]");
 
@@ -191,7 +153,17 @@ pub fn AppsView(saved_apps: Signal<Vec<SavedApp>>, theme: Signal<ThemeMode>) ->
     let mut delete_confirm_id = use_signal(|| Option::<String>::None);
     let mut booted_app = use_signal(|| Option::<SavedApp>::None);
 
+    // Measured again when an app is opened or closed, since it may have written
+    let storage_usage = use_memo(move || {
+        booted_app.read();
+        saved_apps()
+            .iter()
+            .map(|app| (app.id.clone(), bridge::storage_usage(&app.id)))
+            .collect::<HashMap<_, _>>()
+    });
+
     let apps = saved_apps();
+    let usage = storage_usage.read();
 
     let mut all_tags: Vec<String> = apps
         .iter()
@@ -225,11 +197,12 @@ pub fn AppsView(saved_apps: Signal<Vec<SavedApp>>, theme: Signal<ThemeMode>) ->
             if let Some(app) = booted_app() {
                 {
                     let theme_css = app_theme_css(theme());
-                    let themed_content = inject_theme_and_sdk(&app.content, theme_css, &app.id);
+                    let themed_content = html::inject_theme_and_sdk(&app.content, theme_css, &app.id);
                     rsx! {
                         div { class: "booted-app-overlay",
                             iframe {
                                 class: "booted-app-frame",
+                                "data-app-id": "{app.id}",
                                 srcdoc: "{themed_content}",
                             }
                             button {
@@ -345,6 +318,9 @@ pub fn AppsView(saved_apps: Signal<Vec<SavedApp>>, theme: Signal<ThemeMode>) ->
                                         }
                                     }
                                     span { class: "app-card-date", "{app_saved_date(app.created_at)}" }
+                                    if let Some(label) = usage.get(&app.id).and_then(|usage| storage_label(*usage)) {
+                                        span { class: "app-card-usage", "{label}" }
+                                    }
                                 }
                             }
                         }
@@ -369,7 +345,10 @@ pub fn AppsView(saved_apps: Signal<Vec<SavedApp>>, theme: Signal<ThemeMode>) ->
                                 class: "btn btn-danger",
                                 onclick: move |_| {
                                     // Delete the app
-                                    delete_app(&app_id);
+                                    let app = saved_apps.peek().iter().find(|a| a.id == app_id).cloned();
+                                    if let Some(app) = app {
+                                        delete_app(&app);
+                                    }
                                     saved_apps.with_mut(|apps| {
                                         apps.retain(|a| a.id != app_id);
                                     });
@@ -403,15 +382,16 @@ fn app_saved_date(timestamp: u64) -> String {
         .unwrap_or_else(|_| "Unknown date".to_string())
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-fn delete_app(app_id: &str) {
-    // app_id is the file path
-    if let Err(e) = std::fs::remove_file(app_id) {
-        eprintln!("Failed to delete app file: {}", e);
+/// What an app stores, such as "12 keys · 3.4 KB", or `None` if nothing
+fn storage_label(usage: StorageUsage) -> Option<String> {
+    if usage.keys == 0 {
+        return None;
     }
-}
-
-#[cfg(target_arch = "wasm32")]
-fn delete_app(_app_id: &str) {
-    // No file to delete on wasm
+    let keys = if usage.keys == 1 { "key" } else { "keys" };
+    Some(format!(
+        "{} {} · {}",
+        usage.keys,
+        keys,
+        format_bytes(usage.bytes)
+    ))
 }
@@ -1,8 +1,12 @@
-use crate::ai::{chat_reply_stream_poll, chat_reply_stream_start};
+use crate::ai::{
+    RouteInfo, StreamEvent, Task, chat_reply_stream_cancel, chat_reply_stream_start,
+    chat_reply_stream_subscribe,
+};
 use crate::types::{ChatMessage, Role};
-use crate::views::shared::{SavedDoc, markdown_to_html, persist_markdown_doc};
+use crate::views::shared::{SavedDoc, continuation_label, markdown_to_html, persist_markdown_doc};
 use dioxus::events::Key;
 use dioxus::prelude::*;
+use std::collections::HashMap;
 use std::time::Instant;
 use time::{OffsetDateTime, UtcOffset, format_description::FormatItem, macros::format_description};
 
@@ -34,6 +38,8 @@ pub fn ChatView(saved_docs: Signal<Vec<SavedDoc>>, base_font_px: Signal<i32>) ->
 fn ChatHistory(state: ChatState, saved_docs: Signal<Vec<SavedDoc>>) -> Element {
     let messages = state.messages();
     let performances = state.performances();
+    let routes = state.routes();
+    let continuations = state.continuations();
     let streaming_index = state.streaming_index();
 
     rsx! {
@@ -44,6 +50,8 @@ fn ChatHistory(state: ChatState, saved_docs: Signal<Vec<SavedDoc>>) -> Element {
                         index,
                         message,
                         metrics: performances.get(index).copied().flatten(),
+                        route: routes.get(&index).cloned(),
+                        continuations: continuations.get(&index).copied(),
                         streaming_index,
                         saved_docs,
                     }
@@ -58,12 +66,15 @@ fn ChatMessageRow(
     index: usize,
     message: ChatMessage,
     metrics: Option<metrics::MessagePerformance>,
+    route: Option<RouteInfo>,
+    continuations: Option<u32>,
     streaming_index: Option<usize>,
     saved_docs: Signal<Vec<SavedDoc>>,
 ) -> Element {
     let role_class = match message.role {
         Role::User => "user",
         Role::Assistant => "assistant",
+        Role::System | Role::Tool => "system",
     };
 
     let is_streaming = is_streaming_message(streaming_index, index);
@@ -82,7 +93,7 @@ fn ChatMessageRow(
             tags: message.tags.clone(),
             saved_docs,
         }),
-        Role::User => rsx!("{message.content}"),
+        Role::User | Role::System | Role::Tool => rsx!("{message.content}"),
     };
 
     rsx! {
@@ -108,6 +119,12 @@ fn ChatMessageRow(
                             if let Some(perf) = metrics_label {
                                 span { class: "message-metrics", "{perf}" }
                             }
+                            if let Some(route) = route {
+                                span { class: "message-metrics", "{route}" }
+                            }
+                            if let Some(parts) = continuations {
+                                span { class: "message-metrics", "{continuation_label(parts)}" }
+                            }
                         }
                     }
                 }
@@ -164,12 +181,21 @@ fn ChatComposer(state: ChatState, base_font_px: Signal<i32>) -> Element {
                         disabled: sending,
                         autofocus: true,
                     }
-                    button {
-                        class: "btn btn-primary",
-                        r#type: "button",
-                        disabled: send_disabled,
-                        onclick: move |_| chat.submit_input(),
-                        "Send"
+                    if sending {
+                        button {
+                            class: "btn",
+                            r#type: "button",
+                            onclick: move |_| chat.stop(),
+                            "Stop"
+                        }
+                    } else {
+                        button {
+                            class: "btn btn-primary",
+                            r#type: "button",
+                            disabled: send_disabled,
+                            onclick: move |_| chat.submit_input(),
+                            "Send"
+                        }
                     }
                 }
             }
@@ -242,7 +268,13 @@ struct ChatState {
     input: Signal<String>,
     sending: Signal<bool>,
     streaming_index: Signal<Option<usize>>,
+    stream_id: Signal<Option<u64>>,
+    /// Set by Stop; the running reply cancels its stream as soon as it has one
+    stop_requested: Signal<bool>,
     performances: Signal<Vec<Option<metrics::MessagePerformance>>>,
+    routes: Signal<HashMap<usize, RouteInfo>>,
+    /// Continuation requests made for replies cut off by the output limit
+    continuations: Signal<HashMap<usize, u32>>,
     processing_started_at: Signal<Option<Instant>>,
 }
 
@@ -258,7 +290,11 @@ fn use_chat_state() -> ChatState {
         input: use_signal(String::new),
         sending: use_signal(|| false),
         streaming_index: use_signal(|| None),
+        stream_id: use_signal(|| None),
+        stop_requested: use_signal(|| false),
         performances: use_signal(Vec::<Option<metrics::MessagePerformance>>::new),
+        routes: use_signal(HashMap::new),
+        continuations: use_signal(HashMap::new),
         processing_started_at: use_signal(|| None),
     }
 }
@@ -272,6 +308,14 @@ impl ChatState {
         (self.performances)()
     }
 
+    fn routes(&self) -> HashMap<usize, RouteInfo> {
+        (self.routes)()
+    }
+
+    fn continuations(&self) -> HashMap<usize, u32> {
+        (self.continuations)()
+    }
+
     fn streaming_index(&self) -> Option<usize> {
         (self.streaming_index)()
     }
@@ -313,6 +357,8 @@ impl ChatState {
         let conversation_snapshot = self.messages();
 
         self.set_sending(true);
+        let mut stop_requested = self.stop_requested;
+        stop_requested.set(false);
         let assistant_index = self.insert_assistant_placeholder();
         performances.with_mut(|slots| slots.push(None));
         let mut streaming_index = self.streaming_index;
@@ -327,6 +373,19 @@ impl ChatState {
         self.spawn_stream(assistant_index, server_messages);
     }
 
+    /// Cancel the in-flight response, keeping the partial reply. Works before
+    /// the stream has started; the reply then finishes as usual.
+    fn stop(&self) {
+        if !self.sending() {
+            return;
+        }
+        let mut stop_requested = self.stop_requested;
+        stop_requested.set(true);
+        if let Some(id) = (self.stream_id)() {
+            cancel_stream(id);
+        }
+    }
+
     fn push_user_message(&self, content: &str) {
         let mut messages = self.messages;
         messages.with_mut(|msgs| {
@@ -335,6 +394,7 @@ impl ChatState {
                 content: content.to_string(),
                 created_at: Some(current_time()),
                 tags: Vec::new(),
+                tool_call_id: None,
             });
         });
     }
@@ -349,6 +409,7 @@ impl ChatState {
                 content: String::new(),
                 created_at: Some(current_time()),
                 tags: Vec::new(),
+                tool_call_id: None,
             });
         });
         index
@@ -357,37 +418,73 @@ impl ChatState {
     fn spawn_stream(&self, index: usize, server_messages: Vec<ChatMessage>) {
         let state = *self;
         spawn(async move {
-            let mut stream_failed = false;
-            match chat_reply_stream_start(server_messages).await {
-                Ok(stream_id) => loop {
-                    match chat_reply_stream_poll(stream_id).await {
-                        Ok((content, done)) => {
-                            state.update_assistant_content(index, content);
-                            if done {
-                                break;
+            let mut failure = None;
+            match chat_reply_stream_start(server_messages, Task::Chat).await {
+                Ok(stream_id) => {
+                    let mut active_stream = state.stream_id;
+                    active_stream.set(Some(stream_id));
+                    // Stop was pressed while the stream was starting
+                    if (state.stop_requested)() {
+                        cancel_stream(stream_id);
+                    }
+                    match chat_reply_stream_subscribe(stream_id).await {
+                        Ok(mut subscription) => {
+                            let mut content = String::new();
+                            loop {
+                                match subscription.recv().await {
+                                    Some(StreamEvent::Routed(route)) => {
+                                        let mut routes = state.routes;
+                                        routes.with_mut(|map| {
+                                            map.insert(index, route);
+                                        });
+                                    }
+                                    Some(StreamEvent::Continued(parts)) => {
+                                        let mut continuations = state.continuations;
+                                        continuations.with_mut(|map| {
+                                            map.insert(index, parts);
+                                        });
+                                    }
+                                    Some(StreamEvent::Delta(chunk)) => {
+                                        content.push_str(&chunk);
+                                        state.update_assistant_content(index, content.clone());
+                                    }
+                                    Some(StreamEvent::Failed(err)) => {
+                                        eprintln!("stream error: {}", err);
+                                        failure = Some(err);
+                                        break;
+                                    }
+                                    Some(StreamEvent::Finished | StreamEvent::Cancelled) | None => {
+                                        break;
+                                    }
+                                }
                             }
                         }
                         Err(err) => {
-                            eprintln!("stream poll error: {}", err);
-                            stream_failed = true;
-                            break;
+                            eprintln!("stream subscribe error: {}", err);
+                            failure = Some(err);
                         }
                     }
-                    tokio::time::sleep(std::time::Duration::from_millis(80)).await;
-                },
+                }
                 Err(err) => {
                     eprintln!("chat start error: {}", err);
-                    stream_failed = true;
+                    failure = Some(err);
                 }
             }
 
-            if stream_failed {
-                state.update_assistant_content(index, "Unable to generate a response.".to_string());
+            if let Some(err) = failure {
+                state.update_assistant_content(
+                    index,
+                    format!("Unable to generate a response. {}", err.user_message()),
+                );
             }
 
             state.finalize_response(index);
+            let mut active_stream = state.stream_id;
+            active_stream.set(None);
             let mut streaming_index = state.streaming_index;
             streaming_index.set(None);
+            let mut stop_requested = state.stop_requested;
+            stop_requested.set(false);
             state.set_sending(false);
         });
     }
@@ -430,12 +527,21 @@ impl ChatState {
     }
 }
 
+fn cancel_stream(id: u64) {
+    spawn(async move {
+        if let Err(err) = chat_reply_stream_cancel(id).await {
+            eprintln!("chat cancel error: {}", err);
+        }
+    });
+}
+
 fn system_prompt_message() -> ChatMessage {
     ChatMessage {
-        role: Role::User,
+        role: Role::System,
         content: DOC_TAG_SYSTEM_PROMPT.to_string(),
         created_at: None,
         tags: Vec::new(),
+        tool_call_id: None,
     }
 }
 
@@ -0,0 +1,188 @@
+//! The host side of the app SDK: messages posted by app iframes, the
+//! replies to those that ask for something, and storage changes for every
+//! iframe of the app they belong to
+
+use crate::ai::{
+    BlackbirdAI, StreamEvent, Task, chat_reply_stream_subscribe, estimate_messages, estimate_tokens,
+};
+use crate::bridge::{
+    self, APP_AI_USAGE, AiRequest, AppEvent, AppMessage, ConsoleEntry, HostChunk, HostReply,
+    MAX_CONSOLE_ENTRIES, StorageChange,
+};
+use crate::types::ChatMessage;
+use dioxus::prelude::*;
+use futures::StreamExt;
+use futures::channel::mpsc::{self, UnboundedSender};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Console output of each app since it last loaded, by app id
+pub type AppConsoles = Signal<HashMap<String, Vec<ConsoleEntry>>>;
+
+/// Answers on their way to the host listener, from the message loop and from
+/// requests still running in the background
+type Outbox = UnboundedSender<serde_json::Value>;
+
+enum Traffic {
+    /// A message from an app
+    Incoming(serde_json::Value),
+    /// An answer to send back
+    Outgoing(serde_json::Value),
+    /// A storage change, from an app or from Rust
+    Changed(StorageChange),
+}
+
+/// Listen for SDK messages from every app iframe. Call once, at the root.
+pub fn use_app_host() -> AppConsoles {
+    let consoles = use_signal(HashMap::new);
+
+    use_hook(move || {
+        spawn(async move {
+            let mut listener = document::eval(bridge::HOST_LISTENER_SCRIPT);
+            let (outbox, mut outgoing) = mpsc::unbounded();
+            let mut changes = bridge::subscribe_storage_changes();
+            loop {
+                let traffic = tokio::select! {
+                    received = listener.recv::<serde_json::Value>() => match received {
+                        Ok(value) => Traffic::Incoming(value),
+                        Err(e) => {
+                            eprintln!("app host listener stopped: {}", e);
+                            return;
+                        }
+                    },
+                    Some(answer) = outgoing.next() => Traffic::Outgoing(answer),
+                    Some(change) = changes.recv() => Traffic::Changed(change),
+                };
+
+                let value = match traffic {
+                    Traffic::Incoming(value) => value,
+                    Traffic::Outgoing(answer) => {
+                        if let Err(e) = listener.send(answer) {
+                            eprintln!("failed to answer app: {}", e);
+                        }
+                        continue;
+                    }
+                    Traffic::Changed(change) => {
+                        if let Err(e) = listener.send(serde_json::json!({ "change": change })) {
+                            eprintln!("failed to send storage change to apps: {}", e);
+                        }
+                        continue;
+                    }
+                };
+
+                // Requests carry a number from the listener and must be answered,
+                // even when they cannot be understood, or the app waits for a timeout
+                let request = value.get("request").and_then(|r| r.as_u64());
+                match serde_json::from_value::<AppMessage>(value) {
+                    Ok(message) => handle_message(consoles, &outbox, message),
+                    Err(e) => {
+                        eprintln!("unrecognised app message: {}", e);
+                        if let Some(request) = request {
+                            answer(&outbox, HostReply::new(request, Err(e.to_string())));
+                        }
+                    }
+                }
+            }
+        });
+    });
+
+    consoles
+}
+
+fn handle_message(mut consoles: AppConsoles, outbox: &Outbox, message: AppMessage) {
+    match message.event {
+        AppEvent::Loaded => {
+            consoles.with_mut(|consoles| consoles.remove(&message.app_id));
+        }
+        AppEvent::Console(entry) => consoles.with_mut(|consoles| {
+            let entries = consoles.entry(message.app_id).or_default();
+            if entries.len() >= MAX_CONSOLE_ENTRIES {
+                entries.remove(0);
+            }
+            entries.push(entry);
+        }),
+        AppEvent::Storage(storage) => {
+            let result = bridge::handle_storage(&message.app_id, storage.op);
+            answer(outbox, HostReply::new(storage.request, result));
+        }
+        AppEvent::Ai(request) => start_ai_request(message.app_id, request, outbox.clone()),
+    }
+}
+
+/// Admit an app's model call under its quota, then run it in the background
+fn start_ai_request(app_id: String, request: AiRequest, outbox: Outbox) {
+    let messages = request.messages();
+    let admitted = match APP_AI_USAGE.lock() {
+        Ok(mut usage) => usage
+            .begin(&app_id, estimate_messages(&messages), Instant::now())
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    if let Err(e) = admitted {
+        answer(&outbox, HostReply::new(request.request, Err(e)));
+        return;
+    }
+
+    spawn(async move {
+        let result = app_ai_reply(&request, messages, &outbox).await;
+        if let Ok(reply) = &result
+            && let Ok(mut usage) = APP_AI_USAGE.lock()
+        {
+            usage.record(&app_id, estimate_tokens(reply), Instant::now());
+        }
+        let result = result.map(serde_json::Value::String);
+        answer(&outbox, HostReply::new(request.request, result));
+    });
+}
+
+/// The model's reply to an app. Apps get no tools, so they cannot read the
+/// user's other apps or settings, and errors never include provider details.
+async fn app_ai_reply(
+    request: &AiRequest,
+    messages: Vec<ChatMessage>,
+    outbox: &Outbox,
+) -> Result<String, String> {
+    let ai = BlackbirdAI::from_env()
+        .map_err(|e| e.user_message().to_string())?
+        .without_tools();
+    if !request.stream {
+        return ai
+            .reply(messages, Task::Chat)
+            .await
+            .map(|reply| reply.content)
+            .map_err(|e| e.user_message().to_string());
+    }
+
+    let id = ai.start_stream(messages, Task::Chat);
+    let mut subscription = chat_reply_stream_subscribe(id)
+        .await
+        .map_err(|e| e.user_message().to_string())?;
+    let mut content = String::new();
+    loop {
+        match subscription.recv().await {
+            Some(StreamEvent::Delta(chunk)) => {
+                content.push_str(&chunk);
+                answer(
+                    outbox,
+                    HostChunk {
+                        request: request.request,
+                        chunk,
+                    },
+                );
+            }
+            Some(StreamEvent::Failed(e)) => return Err(e.user_message().to_string()),
+            Some(StreamEvent::Finished | StreamEvent::Cancelled) | None => return Ok(content),
+            Some(StreamEvent::Routed(_) | StreamEvent::Continued(_)) => {}
+        }
+    }
+}
+
+fn answer(outbox: &Outbox, message: impl Serialize) {
+    match serde_json::to_value(message) {
+        Ok(value) => {
+            let _ = outbox.unbounded_send(value);
+        }
+        Err(e) => eprintln!("failed to encode answer to app: {}", e),
+    }
+}
@@ -1,5 +1,6 @@
 pub mod apps;
 pub mod chat;
+pub mod host;
 pub mod settings;
 pub mod shared;
 pub mod workbench;
@@ -1,7 +1,7 @@
 use comrak::plugins::syntect::SyntectAdapter;
 use comrak::{ComrakOptions, ComrakPlugins, markdown_to_html_with_plugins};
 use once_cell::sync::Lazy;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -33,9 +33,15 @@ static MARKDOWN_OPTIONS: Lazy<ComrakOptions> = Lazy::new(|| {
     options
 });
 
+/// `<meta>` name holding a saved app's id, so the id stays with the app when
+/// its file is renamed or moved
+pub const APP_ID_META: &str = "blackbird-app-id";
+
 /// A saved app that can be rendered in the workbench
 #[derive(Clone, PartialEq)]
 pub struct SavedApp {
+    /// A UUID, kept in the app's [`APP_ID_META`] meta tag. Storage, deletion
+    /// and tool results all refer to the app by it.
     pub id: String,
     pub title: String,
     /// The raw HTML/JS/CSS content of the app
@@ -133,7 +139,9 @@ pub fn persist_app(
         format!("{}-{}.html", slug, timestamp)
     };
     let path = apps_dir.join(filename);
-    if let Err(err) = fs::write(&path, content) {
+    let id = new_app_id();
+    let content = crate::html::set_meta(content, APP_ID_META, &id);
+    if let Err(err) = fs::write(&path, &content) {
         eprintln!("failed to write saved app to {:?}: {}", path, err);
         return None;
     }
@@ -143,9 +151,9 @@ pub fn persist_app(
         _ => vec!["App".to_string()],
     };
     Some(SavedApp {
-        id: path_str.clone(),
+        id,
         title: title.to_string(),
-        content: content.to_string(),
+        content,
         file_path: Some(path_str),
         created_at: timestamp,
         tags,
@@ -168,7 +176,7 @@ pub fn persist_app(
         _ => vec!["App".to_string()],
     };
     Some(SavedApp {
-        id: format!("mem-{}", timestamp),
+        id: new_app_id(),
         title: title.to_string(),
         content: content.to_string(),
         file_path: None,
@@ -177,11 +185,69 @@ pub fn persist_app(
     })
 }
 
-// Backwards compatibility - keep for now
+/// Remove an app's file and its storage
+pub fn delete_app(app: &SavedApp) {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = &app.file_path
+        && let Err(e) = fs::remove_file(path)
+    {
+        eprintln!("Failed to delete app file: {}", e);
+        return;
+    }
+    if let Err(e) = crate::bridge::storage_clear(&app.id) {
+        eprintln!("Failed to delete app storage: {}", e);
+    }
+}
+
+/// Persist a markdown document from Chat to disk. Documents are not apps, so
+/// they are saved as `.md` exactly as written, and their path is their id.
 #[cfg(not(target_arch = "wasm32"))]
 pub fn persist_markdown_doc(content: &str, tags_override: Option<&[String]>) -> Option<SavedDoc> {
+    persist_markdown_doc_in_dir(&get_apps_dir(), content, tags_override)
+}
+
+/// [`persist_markdown_doc`] into `dir`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn persist_markdown_doc_in_dir(
+    dir: &Path,
+    content: &str,
+    tags_override: Option<&[String]>,
+) -> Option<SavedDoc> {
+    if content.trim().is_empty() {
+        return None;
+    }
+
+    if let Err(err) = fs::create_dir_all(dir) {
+        eprintln!("failed to create apps directory at {:?}: {}", dir, err);
+        return None;
+    }
+
     let title = extract_title(content, "Untitled");
-    persist_app(content, &title, tags_override)
+    let timestamp = current_timestamp();
+    let slug = slugify_for_filename(&title);
+    let filename = if slug.is_empty() {
+        format!("doc-{}.md", timestamp)
+    } else {
+        format!("{}-{}.md", slug, timestamp)
+    };
+    let path = dir.join(filename);
+    if let Err(err) = fs::write(&path, content) {
+        eprintln!("failed to write saved document to {:?}: {}", path, err);
+        return None;
+    }
+    let path_str = path.to_string_lossy().into_owned();
+    let tags = match tags_override {
+        Some(tags) if !tags.is_empty() => tags.to_vec(),
+        _ => generate_tags(content),
+    };
+    Some(SavedApp {
+        id: path_str.clone(),
+        title,
+        content: content.to_string(),
+        file_path: Some(path_str),
+        created_at: timestamp,
+        tags,
+    })
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -198,56 +264,97 @@ pub fn display_file_name(path: &str) -> String {
         .to_string()
 }
 
+/// A byte count as B, KB or MB
+pub fn format_bytes(bytes: u64) -> String {
+    const KB: f64 = 1024.0;
+    const MB: f64 = KB * 1024.0;
+    let bytes_f = bytes as f64;
+    if bytes_f >= MB {
+        format!("{:.1} MB", bytes_f / MB)
+    } else if bytes_f >= KB {
+        format!("{:.1} KB", bytes_f / KB)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+/// Note shown with a reply that needed continuation requests to finish
+pub fn continuation_label(parts: u32) -> String {
+    match parts {
+        1 => "Continued once".to_string(),
+        n => format!("Continued {} times", n),
+    }
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 fn load_apps_from_disk() -> Vec<SavedApp> {
-    let dir = get_apps_dir();
-    if !dir.exists() {
+    load_apps_from_dir(&get_apps_dir())
+}
+
+/// Load every app saved in `dir`, newest first. Files are visited oldest
+/// first, so when a copied file shares an id with the original, the original
+/// keeps it.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_apps_from_dir(dir: &Path) -> Vec<SavedApp> {
+    let Ok(entries) = fs::read_dir(dir) else {
         return Vec::new();
-    }
+    };
+    let mut files: Vec<(Option<SystemTime>, PathBuf)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        // Apps are .html files; .md documents are listed too
+        .filter(|path| {
+            let ext = path.extension().and_then(|ext| ext.to_str());
+            ext == Some("html") || ext == Some("md")
+        })
+        .map(|path| {
+            let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok();
+            (modified, path)
+        })
+        .collect();
+    files.sort();
 
     let mut apps_with_time: Vec<(u64, SavedApp)> = Vec::new();
-    if let Ok(entries) = fs::read_dir(&dir) {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            // Accept .html files for apps
-            let ext = path.extension().and_then(|ext| ext.to_str());
-            if ext != Some("html") && ext != Some("md") {
-                continue;
-            }
-            if let Ok(content) = fs::read_to_string(&path) {
-                let fallback = path
-                    .file_stem()
-                    .and_then(|stem| stem.to_str())
-                    .unwrap_or("Untitled");
-                // For HTML files, use the filename as title; for md, extract from content
-                let title = if ext == Some("html") {
-                    extract_html_title(&content).unwrap_or_else(|| fallback.to_string())
-                } else {
-                    extract_title(&content, fallback)
-                };
-                let tags = if ext == Some("html") {
-                    vec!["App".to_string()]
-                } else {
-                    generate_tags(&content)
-                };
-                let timestamp = entry
-                    .metadata()
-                    .ok()
-                    .and_then(|meta| meta.modified().ok())
-                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
-                    .map(|dur| dur.as_secs())
-                    .unwrap_or(0);
-                let path_str = path.to_string_lossy().into_owned();
-                let app = SavedApp {
-                    id: path_str.clone(),
-                    title,
-                    content,
-                    file_path: Some(path_str),
-                    created_at: timestamp,
-                    tags,
-                };
-                apps_with_time.push((app.created_at, app));
-            }
+    let mut seen_ids = HashSet::new();
+    for (modified, path) in files {
+        let ext = path.extension().and_then(|ext| ext.to_str());
+        if let Ok(content) = fs::read_to_string(&path) {
+            let path_str = path.to_string_lossy().into_owned();
+            // Markdown documents are not apps, so they keep their path as id.
+            // Chat once saved them as .html too.
+            let (id, content) = if ext == Some("html") && crate::html::is_html(&content) {
+                saved_app_id(&path_str, content, modified, &mut seen_ids)
+            } else {
+                (path_str.clone(), content)
+            };
+            let fallback = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or("Untitled");
+            // For HTML files, use the filename as title; for md, extract from content
+            let title = if ext == Some("html") {
+                crate::html::title(&content).unwrap_or_else(|| fallback.to_string())
+            } else {
+                extract_title(&content, fallback)
+            };
+            let tags = if ext == Some("html") {
+                vec!["App".to_string()]
+            } else {
+                generate_tags(&content)
+            };
+            let timestamp = modified
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|dur| dur.as_secs())
+                .unwrap_or(0);
+            let app = SavedApp {
+                id,
+                title,
+                content,
+                file_path: Some(path_str),
+                created_at: timestamp,
+                tags,
+            };
+            apps_with_time.push((app.created_at, app));
         }
     }
 
@@ -255,19 +362,66 @@ fn load_apps_from_disk() -> Vec<SavedApp> {
     apps_with_time.into_iter().map(|(_, app)| app).collect()
 }
 
-/// Extract title from HTML <title> tag
-fn extract_html_title(content: &str) -> Option<String> {
-    let lower = content.to_lowercase();
-    if let Some(start) = lower.find("<title>") {
-        let after_tag = start + 7;
-        if let Some(end) = lower[after_tag..].find("</title>") {
-            let title = content[after_tag..after_tag + end].trim();
-            if !title.is_empty() {
-                return Some(title.to_string());
-            }
+fn new_app_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// The id of the app saved at `path`, and its content. Apps saved before ids
+/// existed are given one, written into the file, and their storage, which was
+/// kept under the file path, is moved to it; they are also marked as using
+/// synchronous storage calls, which the SDK had then, and with the path, so
+/// the SDK can move what they kept in localStorage. A copied file gets an id
+/// of its own, with empty storage. The file keeps its `modified` time, which
+/// orders the gallery. If the file cannot be written the path stays the id, so
+/// storage is never moved to an id that is not kept.
+#[cfg(not(target_arch = "wasm32"))]
+fn saved_app_id(
+    path: &str,
+    content: String,
+    modified: Option<SystemTime>,
+    seen: &mut HashSet<String>,
+) -> (String, String) {
+    let existing = crate::html::meta(&content, APP_ID_META);
+    if let Some(id) = &existing
+        && seen.insert(id.clone())
+    {
+        return (id.clone(), content);
+    }
+
+    // Apps saved before ids were kept use synchronous storage calls, and
+    // their SDK finds what they saved in localStorage under the path
+    let content = match existing {
+        Some(_) => content,
+        None => {
+            let content = crate::html::set_meta(
+                &content,
+                crate::bridge::SDK_VERSION_META,
+                crate::bridge::SYNC_STORAGE_SDK_VERSION,
+            );
+            crate::html::set_meta(&content, crate::bridge::LEGACY_ID_META, path)
         }
+    };
+    let id = new_app_id();
+    let content_with_id = crate::html::set_meta(&content, APP_ID_META, &id);
+    if let Err(err) = fs::write(path, &content_with_id) {
+        eprintln!("failed to record an id for {}: {}", path, err);
+        return (path.to_string(), content);
     }
-    None
+    if let Some(modified) = modified
+        && let Err(err) = fs::File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_modified(modified))
+    {
+        eprintln!("failed to keep the modified time of {}: {}", path, err);
+    }
+    seen.insert(id.clone());
+    if existing.is_none()
+        && let Err(err) = crate::bridge::storage_move(path, &id)
+    {
+        eprintln!("failed to move storage of {} to its id: {}", path, err);
+    }
+    (id, content_with_id)
 }
 
 fn current_timestamp() -> u64 {
@@ -319,3 +473,25 @@ fn slugify_for_filename(input: &str) -> String {
     }
     slug.trim_matches('-').to_string()
 }
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::bridge::{storage_clear, storage_get, storage_set};
+
+    #[test]
+    fn test_unwritable_app_keeps_its_path_as_id() {
+        // The directory does not exist, so the id cannot be written
+        let dir = std::env::temp_dir().join(format!("blackbird-missing-{}", new_app_id()));
+        let path = dir.join("legacy.html").to_string_lossy().into_owned();
+        storage_set(&path, "score", "3").unwrap();
+
+        let (id, content) = saved_app_id(&path, "<html></html>".into(), None, &mut HashSet::new());
+
+        assert_eq!(id, path);
+        assert_eq!(crate::html::meta(&content, APP_ID_META), None);
+        // Storage stays where the app will look for it
+        assert_eq!(storage_get(&path, "score").as_deref(), Some("3"));
+        storage_clear(&path).unwrap();
+    }
+}
@@ -1,11 +1,29 @@
-use crate::ai::{chat_reply_stream_poll, chat_reply_stream_start};
-use crate::bridge;
+use crate::ai::{
+    BlackbirdAI, ChatResult, JsonSchema, ResponseFormat, RouteInfo, StreamEvent,
+    StreamSubscription, Task, chat_reply, chat_reply_stream_cancel, chat_reply_stream_subscribe,
+    extract_json,
+};
+use crate::bridge::{self, ConsoleEntry, ConsoleLevel};
+use crate::html;
+use crate::patch::{self, PatchError};
 use crate::types::{ChatMessage, Role, ThemeMode};
-use crate::views::shared::{SavedApp, persist_app};
+use crate::validate::{self, ValidationReport};
+use crate::views::host::AppConsoles;
+use crate::views::shared::{SavedApp, continuation_label, format_bytes, persist_app};
 use dioxus::events::Key;
 use dioxus::prelude::*;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use time::OffsetDateTime;
 
+/// Minimum time between live preview updates while a build streams
+const PREVIEW_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Storage namespace for the live preview's SDK, kept apart from the finished
+/// app's so half-built scripts cannot touch its data. Cleared with the other
+/// preview namespaces when the Workbench starts.
+const LIVE_PREVIEW_APP_ID: &str = "preview-live";
+
 /// CSS that gets injected into generated apps to match Blackbird's theme
 fn app_theme_css(theme: ThemeMode) -> &'static str {
     match theme {
@@ -145,33 +163,62 @@ RULES:
 
 ## BLACKBIRD SDK
 
-Every app has access to `window.blackbird` - a SDK for persistent storage.
+Every app has access to `window.blackbird` - a SDK for persistent storage and AI.
 
 ### Storage API (Persistent, App-Isolated)
 Data persists across app reloads. Each app has isolated storage.
+Every call is async and returns a Promise - always `await` it.
 
 ```javascript
 // Save data (auto-serializes objects/arrays to JSON)
-blackbird.storage.set('todos', [{text: 'Buy milk', done: false}]);
+await blackbird.storage.set('todos', [{text: 'Buy milk', done: false}]);
 
 // Load data (auto-parses JSON)
-const todos = blackbird.storage.get('todos'); // Returns array or null
+const todos = await blackbird.storage.get('todos'); // Returns array or null
 
 // Delete a key
-blackbird.storage.delete('todos');
+await blackbird.storage.delete('todos');
 
 // List all keys
-const keys = blackbird.storage.keys(); // ['todos', 'settings', ...]
+const keys = await blackbird.storage.keys(); // ['todos', 'settings', ...]
 
 // Clear all app storage
-blackbird.storage.clear();
+await blackbird.storage.clear();
+
+// React when storage changes, including from another open copy of this app.
+// Pass a key to hear about just that key. Returns a function that stops it.
+const stop = blackbird.storage.onChange('todos', ({ key, value }) => render(value));
 ```
 
+Each app can store up to {storage_quota}. A `set` past that throws an
+error named `QuotaExceededError`; catch it and tell the user.
+
+### AI API
+Apps can call the AI model. Every call returns a Promise of the reply text.
+Pass `onChunk` to show the reply as it is written.
+
+```javascript
+// Optional: instructions for every call this app makes
+blackbird.ai.setSystemPrompt('You are a helpful chef. Answer with a recipe.');
+
+// One question
+const answer = await blackbird.ai.chat('How do I make pancakes?');
+
+// With earlier turns, streaming into the page
+const reply = await blackbird.ai.chatWithHistory('And for four people?', [
+  {role: 'user', content: 'How do I make pancakes?'},
+  {role: 'assistant', content: answer}
+], {onChunk: (text) => { output.textContent += text; }});
+```
+
+Calls can fail (for example when the app has made too many requests), so
+wrap them in try/catch and show the error message to the user.
+
 ### SDK Ready Event
 ```javascript
-window.addEventListener('blackbird:ready', () => {
+window.addEventListener('blackbird:ready', async () => {
   // SDK is loaded and ready
-  const saved = blackbird.storage.get('data');
+  const saved = await blackbird.storage.get('data');
 });
 ```
 
@@ -225,40 +272,49 @@ For rich apps, use CDN-hosted libraries:
             <input type="text" id="input" placeholder="Add todo...">
             <button onclick="addTodo()">Add</button>
         </div>
+        <p id="error" style="color:var(--text-muted)"></p>
         <div id="list"></div>
     </div>
     <script>
         let todos = [];
 
-        function init() {
-            todos = blackbird.storage.get('todos') || [];
+        async function init() {
+            todos = (await blackbird.storage.get('todos')) || [];
             render();
         }
 
-        function save() {
-            blackbird.storage.set('todos', todos);
+        async function save() {
+            const error = document.getElementById('error');
+            try {
+                await blackbird.storage.set('todos', todos);
+                error.textContent = '';
+            } catch (e) {
+                error.textContent = e.name === 'QuotaExceededError'
+                    ? 'Storage is full. Remove some todos to save more.'
+                    : 'Could not save: ' + e.message;
+            }
         }
 
-        function addTodo() {
+        async function addTodo() {
             const input = document.getElementById('input');
             if (input.value.trim()) {
                 todos.push({text: input.value, done: false});
                 input.value = '';
-                save();
                 render();
+                await save();
             }
         }
 
-        function toggle(i) {
+        async function toggle(i) {
             todos[i].done = !todos[i].done;
-            save();
             render();
+            await save();
         }
 
-        function remove(i) {
+        async function remove(i) {
             todos.splice(i, 1);
-            save();
             render();
+            await save();
         }
 
         function render() {
@@ -285,18 +341,47 @@ For rich apps, use CDN-hosted libraries:
 [[app_tags: Utility, Productivity]]
 "#;
 
+/// Appended to the builder prompt in structured output mode, overriding its
+/// raw-HTML and tag-line rules
+const APP_JSON_OUTPUT_PROMPT: &str = "OUTPUT FORMAT OVERRIDE: instead of raw HTML and a [[app_tags: ...]] line, \
+reply with a JSON object. Put the complete HTML document in `html`, the app's name in `title`, \
+a one-sentence summary in `description` and 1-3 category tags in `tags`.";
+
+/// Appended to the builder prompt once an app exists, so follow-ups come back
+/// as edits to its source instead of a regenerated document
+const APP_EDIT_PROMPT: &str = r#"EDIT MODE: an app already exists and its current source is your latest reply above.
+Unless the user asks for a different app, reply with edits to that source instead of the whole document, as one or more blocks in this exact format:
+
+<<<<<<< SEARCH
+lines copied exactly from the current source
+=======
+the lines that replace them
+>>>>>>> REPLACE
+
+Each SEARCH section must match exactly one place in the source, so include enough surrounding lines to make it unique. Blocks are applied in order. Unified diff hunks (@@ ... @@) are also accepted.
+Put the edits where the HTML document would otherwise go, and still end with the [[app_tags: ...]] line.
+Reply with a complete document only when the user asks for a new app or most of the app changes."#;
+
+/// Repair turns allowed when a build's scripts do not parse
+const MAX_SCRIPT_REPAIRS: u32 = 1;
+
+/// Sent to the cheap extraction model when a build omits its tag line
+const APP_TAG_PROMPT: &str = "Suggest 1-3 short category tags for the app below. \
+Reply with a single line in the exact format [[app_tags: Tag1, Tag2]] and nothing else.";
+
 #[component]
 pub fn WorkbenchView(
     saved_apps: Signal<Vec<SavedApp>>,
     base_font_px: Signal<i32>,
     theme: Signal<ThemeMode>,
+    consoles: AppConsoles,
 ) -> Element {
     let state = use_workbench_state();
     let mut show_clear_confirm = use_signal(|| false);
 
     rsx! {
         div { class: "workbench-container",
-            WorkbenchDisplay { state, saved_apps, theme, show_clear_confirm }
+            WorkbenchDisplay { state, saved_apps, theme, consoles, show_clear_confirm }
             WorkbenchComposer { state, base_font_px }
 
             // Clear confirmation overlay
@@ -333,11 +418,18 @@ fn WorkbenchDisplay(
     state: WorkbenchState,
     saved_apps: Signal<Vec<SavedApp>>,
     theme: Signal<ThemeMode>,
+    consoles: AppConsoles,
     show_clear_confirm: Signal<bool>,
 ) -> Element {
     let messages = state.messages();
     let is_streaming = state.streaming_index().is_some();
     let logs_expanded = state.logs_expanded();
+    let console_expanded = state.console_expanded();
+    let route = state.route();
+    let continuations = state.continuations();
+    let preview = state.preview();
+    let repairing = state.repairing();
+    let script_checks = state.script_checks();
 
     let latest_app = messages
         .iter()
@@ -346,12 +438,41 @@ fn WorkbenchDisplay(
         .map(|msg| extract_html_content(&msg.content));
 
     let has_content = !messages.is_empty();
+    // Output of the app on show, once it has finished building
+    let console: Vec<ConsoleEntry> = match latest_app.as_deref() {
+        Some(html) if !is_streaming && !html.is_empty() => consoles
+            .read()
+            .get(&state.preview_app_id())
+            .cloned()
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    };
+    let console_errors = console
+        .iter()
+        .filter(|entry| entry.level == ConsoleLevel::Error)
+        .count();
+    // CDN libraries the current app loads, listed in the log
+    let external_scripts = latest_app
+        .as_deref()
+        .map(html::external_scripts)
+        .unwrap_or_default();
 
     rsx! {
         div { class: "workbench-display",
             if is_streaming {
-                div { class: "workbench-status",
-                    span { class: "shimmer-text", "Building..." }
+                if let Some(ref html) = preview {
+                    PreviewFrame { html: html.clone(), theme }
+                }
+                div { class: if preview.is_some() { "workbench-status live" } else { "workbench-status" },
+                    if repairing {
+                        span { class: "shimmer-text", "Fixing script errors..." }
+                    } else if continuations > 0 {
+                        span { class: "shimmer-text", "Output limit reached, continuing (part {continuations + 1})..." }
+                    } else if let Some(ref route) = route {
+                        span { class: "shimmer-text", "Building with {route}..." }
+                    } else {
+                        span { class: "shimmer-text", "Building..." }
+                    }
                 }
             } else if let Some(ref html) = latest_app {
                 if !html.is_empty() {
@@ -372,19 +493,91 @@ fn WorkbenchDisplay(
                         onclick: move |_| state.toggle_logs(),
                         if logs_expanded { "Hide log" } else { "Log" }
                     }
+                    button {
+                        class: "logs-toggle",
+                        onclick: move |_| state.toggle_console(),
+                        if console_expanded {
+                            "Hide console"
+                        } else if console_errors > 0 {
+                            "Console ({console_errors})"
+                        } else {
+                            "Console"
+                        }
+                    }
                     button {
                         class: "clear-btn",
                         onclick: move |_| show_clear_confirm.set(true),
                         "Clear"
                     }
+                    if let Some(ref route) = route {
+                        span { class: "workbench-route", "{route}" }
+                    }
+                    if continuations > 0 {
+                        span { class: "workbench-route", "{continuation_label(continuations)}" }
+                    }
                 }
                 if logs_expanded {
                     div { class: "workbench-logs",
-                        for msg in messages.iter() {
+                        for (i, msg) in messages.iter().enumerate() {
                             div { class: format_args!("log-entry {}", if matches!(msg.role, Role::User) { "user" } else { "assistant" }),
                                 span { class: "log-role", if matches!(msg.role, Role::User) { "You" } else { "AI" } }
                                 span { class: "log-content", "{truncate(&msg.content, 80)}" }
                             }
+                            if let Some(check) = script_checks.get(&i) {
+                                div { class: if check.report.is_clean() { "log-entry check" } else { "log-entry check failed" },
+                                    span { class: "log-role", "JS" }
+                                    span { class: "log-content",
+                                        "{check.report.summary()}"
+                                        if check.repaired { " after a repair" }
+                                    }
+                                }
+                                for error in check.report.errors.iter() {
+                                    div { class: "log-entry check failed",
+                                        span { class: "log-role" }
+                                        span { class: "log-content", "{error}" }
+                                    }
+                                }
+                            }
+                        }
+                        for src in external_scripts.iter() {
+                            div { class: "log-entry library",
+                                span { class: "log-role", "Lib" }
+                                span { class: "log-content", "{src}" }
+                            }
+                        }
+                    }
+                }
+                if console_expanded {
+                    div { class: "workbench-logs workbench-console",
+                        if console.is_empty() {
+                            div { class: "log-entry",
+                                span { class: "log-content text-muted", "No console output" }
+                            }
+                        }
+                        for entry in console.into_iter() {
+                            div { class: "log-entry console {entry.level.label()}",
+                                span { class: "log-role", "{entry.level.label()}" }
+                                span { class: "log-content",
+                                    "{entry.message}"
+                                    if let Some(ref stack) = entry.stack {
+                                        pre { class: "console-stack", "{stack}" }
+                                    }
+                                }
+                                if entry.level == ConsoleLevel::Error {
+                                    button {
+                                        class: "logs-toggle console-fix",
+                                        disabled: state.sending(),
+                                        onclick: {
+                                            let prompt = fix_error_prompt(&entry);
+                                            move |_| {
+                                                state.set_input(prompt.clone());
+                                                state.submit_input();
+                                            }
+                                        },
+                                        "Fix this error"
+                                    }
+                                }
+                            }
                         }
                     }
                 }
@@ -393,6 +586,23 @@ fn WorkbenchDisplay(
     }
 }
 
+/// The partially streamed app, rendered as it grows
+#[component]
+fn PreviewFrame(html: String, theme: Signal<ThemeMode>) -> Element {
+    let themed_html =
+        html::inject_theme_and_sdk(&html, app_theme_css(theme()), LIVE_PREVIEW_APP_ID);
+
+    rsx! {
+        div { class: "app-container",
+            iframe {
+                class: "app-frame",
+                "data-app-id": LIVE_PREVIEW_APP_ID,
+                srcdoc: "{themed_html}",
+            }
+        }
+    }
+}
+
 #[component]
 fn AppRenderer(
     html: String,
@@ -403,30 +613,92 @@ fn AppRenderer(
     let html_for_save = html.clone();
     let tags = state.current_tags();
     let theme_css = app_theme_css(theme());
+    // The saved app that may take over the data entered in the preview
+    let mut offer_data_to = use_signal(|| Option::<String>::None);
+    // Why copying or moving that data failed, shown in the dialog
+    let mut data_error = use_signal(|| Option::<String>::None);
 
-    // Generate a stable app ID based on content hash for the preview
-    // This will be replaced with the actual file path when saved
-    let app_id = format!("preview-{}", simple_hash(&html));
+    // Every iteration shares the session's namespace, so data entered while
+    // testing survives rebuilds
+    let app_id = state.preview_app_id();
 
     // Inject theme CSS and Blackbird SDK into the HTML
-    let themed_html = inject_theme_and_sdk(&html, theme_css, &app_id);
-
-    let on_save = move |_| {
-        let content = html_for_save.clone();
-        let title = extract_app_title(&content).unwrap_or_else(|| "Untitled App".to_string());
-        let tag_refs: Vec<String> = tags.clone();
-        if let Some(app) = persist_app(&content, &title, Some(&tag_refs)) {
-            saved_apps.with_mut(|apps| {
-                apps.retain(|existing| existing.id != app.id);
-                apps.insert(0, app);
-            });
+    let themed_html = html::inject_theme_and_sdk(&html, theme_css, &app_id);
+
+    let on_save = {
+        let app_id = app_id.clone();
+        move |_| {
+            let content = html_for_save.clone();
+            let title = state
+                .current_title()
+                .or_else(|| html::title(&content))
+                .unwrap_or_else(|| "Untitled App".to_string());
+            let tag_refs: Vec<String> = tags.clone();
+            if let Some(app) = persist_app(&content, &title, Some(&tag_refs)) {
+                if bridge::storage_usage(&app_id).keys > 0 {
+                    data_error.set(None);
+                    offer_data_to.set(Some(app.id.clone()));
+                }
+                saved_apps.with_mut(|apps| {
+                    apps.retain(|existing| existing.id != app.id);
+                    apps.insert(0, app);
+                });
+            }
         }
     };
 
     rsx! {
         div { class: "app-container",
             button { class: "app-save-btn action-btn", onclick: on_save, "Save" }
-            iframe { class: "app-frame", srcdoc: "{themed_html}" }
+            iframe { class: "app-frame", "data-app-id": "{app_id}", srcdoc: "{themed_html}" }
+        }
+
+        if let Some(saved_id) = offer_data_to() {
+            div { class: "confirm-overlay",
+                onclick: move |_| offer_data_to.set(None),
+                div { class: "confirm-dialog",
+                    onclick: move |e| e.stop_propagation(),
+                    p { "Keep the data you entered in the preview?" }
+                    if let Some(error) = data_error() {
+                        p { class: "confirm-error", "{error}" }
+                    }
+                    div { class: "confirm-actions",
+                        button {
+                            class: "btn",
+                            onclick: move |_| offer_data_to.set(None),
+                            "Start empty"
+                        }
+                        button {
+                            class: "btn",
+                            onclick: {
+                                let (from, to) = (app_id.clone(), saved_id.clone());
+                                move |_| match bridge::storage_copy(&from, &to) {
+                                    Ok(_) => offer_data_to.set(None),
+                                    Err(e) => {
+                                        eprintln!("failed to copy preview data: {}", e);
+                                        data_error.set(Some(format!("Could not copy the data. {}", e)));
+                                    }
+                                }
+                            },
+                            "Copy"
+                        }
+                        button {
+                            class: "btn btn-primary",
+                            onclick: {
+                                let (from, to) = (app_id.clone(), saved_id.clone());
+                                move |_| match bridge::storage_move(&from, &to) {
+                                    Ok(_) => offer_data_to.set(None),
+                                    Err(e) => {
+                                        eprintln!("failed to move preview data: {}", e);
+                                        data_error.set(Some(format!("Could not move the data. {}", e)));
+                                    }
+                                }
+                            },
+                            "Move"
+                        }
+                    }
+                }
+            }
         }
     }
 }
@@ -467,11 +739,19 @@ fn WorkbenchComposer(state: WorkbenchState, base_font_px: Signal<i32>) -> Elemen
                 disabled: sending,
                 autofocus: true,
             }
-            button {
-                class: "btn btn-primary",
-                disabled: sending || input_value.trim().is_empty(),
-                onclick: move |_| state.submit_input(),
-                "Build"
+            if sending {
+                button {
+                    class: "btn",
+                    onclick: move |_| state.stop(),
+                    "Stop"
+                }
+            } else {
+                button {
+                    class: "btn btn-primary",
+                    disabled: input_value.trim().is_empty(),
+                    onclick: move |_| state.submit_input(),
+                    "Build"
+                }
             }
         }
     }
@@ -487,8 +767,35 @@ struct WorkbenchState {
     input: Signal<String>,
     sending: Signal<bool>,
     streaming_index: Signal<Option<usize>>,
+    stream_id: Signal<Option<u64>>,
     logs_expanded: Signal<bool>,
+    console_expanded: Signal<bool>,
     current_tags: Signal<Vec<String>>,
+    /// Title from a structured build, preferred over the HTML `<title>`
+    current_title: Signal<Option<String>>,
+    route: Signal<Option<RouteInfo>>,
+    /// Continuation requests the latest build needed after being cut off
+    continuations: Signal<u32>,
+    /// The streaming document, completed enough to render
+    preview: Signal<Option<String>>,
+    /// Script syntax check of each built app, by message index
+    script_checks: Signal<HashMap<usize, ScriptCheck>>,
+    /// Whether the running request is a repair of script errors
+    repairing: Signal<bool>,
+    /// Set by Stop; the running request cancels its stream as soon as it has one
+    stop_requested: Signal<bool>,
+    /// Bumped by Clear, so a request still finishing leaves the new conversation alone
+    generation: Signal<u64>,
+    /// Storage and console namespace of built apps until they are saved
+    preview_app_id: Signal<String>,
+}
+
+/// The script syntax check of a build, kept for the log
+#[derive(Clone, PartialEq)]
+struct ScriptCheck {
+    report: ValidationReport,
+    /// Whether a repair turn ran before this report
+    repaired: bool,
 }
 
 impl PartialEq for WorkbenchState {
@@ -503,8 +810,23 @@ fn use_workbench_state() -> WorkbenchState {
         input: use_signal(String::new),
         sending: use_signal(|| false),
         streaming_index: use_signal(|| None),
+        stream_id: use_signal(|| None),
         logs_expanded: use_signal(|| false),
+        console_expanded: use_signal(|| false),
         current_tags: use_signal(Vec::<String>::new),
+        current_title: use_signal(|| None),
+        route: use_signal(|| None),
+        continuations: use_signal(|| 0),
+        preview: use_signal(|| None),
+        script_checks: use_signal(HashMap::new),
+        repairing: use_signal(|| false),
+        stop_requested: use_signal(|| false),
+        generation: use_signal(|| 0),
+        preview_app_id: use_signal(|| {
+            let app_id = new_preview_app_id();
+            bridge::collect_preview_storage(&app_id);
+            app_id
+        }),
     }
 }
 
@@ -532,17 +854,108 @@ impl WorkbenchState {
         let mut l = self.logs_expanded;
         l.set(!l());
     }
+    fn console_expanded(&self) -> bool {
+        (self.console_expanded)()
+    }
+    fn toggle_console(&self) {
+        let mut c = self.console_expanded;
+        c.set(!c());
+    }
     fn current_tags(&self) -> Vec<String> {
         (self.current_tags)()
     }
+    fn current_title(&self) -> Option<String> {
+        (self.current_title)()
+    }
+    fn preview_app_id(&self) -> String {
+        (self.preview_app_id)()
+    }
+    fn route(&self) -> Option<RouteInfo> {
+        (self.route)()
+    }
+    fn continuations(&self) -> u32 {
+        (self.continuations)()
+    }
+    fn preview(&self) -> Option<String> {
+        (self.preview)()
+    }
+    fn script_checks(&self) -> HashMap<usize, ScriptCheck> {
+        (self.script_checks)()
+    }
+    fn repairing(&self) -> bool {
+        (self.repairing)()
+    }
+
+    /// Cancel the in-flight generation. Works before the stream has started;
+    /// the request then finishes as usual with whatever has streamed so far.
+    fn stop(&self) {
+        if !self.sending() {
+            return;
+        }
+        let mut stop_requested = self.stop_requested;
+        stop_requested.set(true);
+        if let Some(id) = (self.stream_id)() {
+            cancel_stream(id);
+        }
+    }
 
     fn clear(&self) {
+        self.stop();
+        let mut generation = self.generation;
+        generation.set(generation() + 1);
         let mut messages = self.messages;
         messages.set(Vec::new());
         let mut current_tags = self.current_tags;
         current_tags.set(Vec::new());
+        let mut current_title = self.current_title;
+        current_title.set(None);
+        let mut route = self.route;
+        route.set(None);
+        let mut continuations = self.continuations;
+        continuations.set(0);
+        let mut script_checks = self.script_checks;
+        script_checks.set(HashMap::new());
         let mut logs = self.logs_expanded;
         logs.set(false);
+        let mut console = self.console_expanded;
+        console.set(false);
+        // The next app starts with empty storage
+        if let Err(e) = bridge::storage_clear(&self.preview_app_id()) {
+            eprintln!("failed to clear preview storage: {}", e);
+        }
+    }
+
+    /// Ask the cheap extraction model for tags when a build came back without any
+    fn suggest_tags(&self, idx: usize, html: String) {
+        let state = *self;
+        let generation = (self.generation)();
+        spawn(async move {
+            let reply = match chat_reply(tag_request(html), Task::Extraction).await {
+                Ok(reply) => reply,
+                Err(e) => {
+                    eprintln!("tag extraction error: {}", e);
+                    return;
+                }
+            };
+            // Cleared meanwhile; the tags belong to a conversation that is gone
+            if (state.generation)() != generation || !reply.content.contains("[[app_tags:") {
+                return;
+            }
+
+            let (_, tags) = extract_app_tags(&reply.content);
+            let mut messages = state.messages;
+            messages.with_mut(|msgs| {
+                if let Some(msg) = msgs.get_mut(idx) {
+                    msg.tags = tags.clone();
+                }
+            });
+            // Only replace the tags if no newer build has finished meanwhile
+            let is_latest = state.messages.with(|msgs| msgs.len() == idx + 1);
+            if is_latest {
+                let mut current_tags = state.current_tags;
+                current_tags.set(tags);
+            }
+        });
     }
 
     fn submit_input(&self) {
@@ -558,6 +971,7 @@ impl WorkbenchState {
                 content: text,
                 created_at: Some(OffsetDateTime::now_utc()),
                 tags: Vec::new(),
+                tool_call_id: None,
             });
         });
 
@@ -568,6 +982,9 @@ impl WorkbenchState {
 
         let mut sending = self.sending;
         sending.set(true);
+        let mut stop_requested = self.stop_requested;
+        stop_requested.set(false);
+        let generation = (self.generation)();
 
         let idx = {
             let mut index = 0;
@@ -578,6 +995,7 @@ impl WorkbenchState {
                     content: String::new(),
                     created_at: Some(OffsetDateTime::now_utc()),
                     tags: Vec::new(),
+                    tool_call_id: None,
                 });
             });
             index
@@ -586,81 +1004,481 @@ impl WorkbenchState {
         let mut streaming_index = self.streaming_index;
         streaming_index.set(Some(idx));
 
-        let mut server_msgs = vec![ChatMessage {
-            role: Role::User,
-            content: APP_BUILDER_SYSTEM_PROMPT.to_string(),
-            created_at: None,
-            tags: Vec::new(),
-        }];
-        server_msgs.extend(snapshot);
+        let structured = structured_output_enabled();
+        // Once an app exists, ask for edits to it rather than a new document
+        let mut source = current_source(&snapshot);
+        let server_msgs = match source {
+            Some(_) => edit_request(snapshot.clone(), structured),
+            None => build_request(snapshot.clone(), structured),
+        };
 
         let state = *self;
         spawn(async move {
-            let mut failed = false;
-            match chat_reply_stream_start(server_msgs).await {
-                Ok(id) => loop {
-                    match chat_reply_stream_poll(id).await {
-                        Ok((content, done)) => {
+            let mut request = server_msgs;
+            let mut repairs = 0;
+            // The build and its report while a repair turn runs, kept if the
+            // repair cannot be applied
+            let mut unrepaired: Option<(AppOutput, ValidationReport)> = None;
+            let failure = loop {
+                let (streamed, stopped) = match state.stream_build(idx, request, structured).await {
+                    Some(result) => (result, false),
+                    None => (Ok(()), true),
+                };
+                // Cleared meanwhile; the conversation this was for is gone
+                if (state.generation)() != generation {
+                    break None;
+                }
+                let built = match streamed {
+                    Ok(()) => {
+                        let content = state
+                            .messages
+                            .with(|msgs| msgs.get(idx).map(|m| m.content.clone()))
+                            .unwrap_or_default();
+                        resolve_build(&content, source.as_deref()).map(|output| {
+                            let report = validate::check_scripts(&output.html);
+                            (output, report)
+                        })
+                    }
+                    Err(e) => match unrepaired.take() {
+                        Some(unrepaired) => {
+                            eprintln!("script repair error: {}", e);
+                            Ok(unrepaired)
+                        }
+                        None => break Some(format!("Failed to build. {}", e.user_message())),
+                    },
+                };
+                // A stopped build is only used if the whole document arrived
+                let built = match built {
+                    Ok((output, _)) if stopped && !is_finished_document(&output.html) => Err(
+                        PatchError::Malformed("stopped before the app was finished".to_string()),
+                    ),
+                    built => built,
+                };
+                let (output, report) = match (built, unrepaired.take()) {
+                    (Ok(built), _) => built,
+                    (Err(e), Some(unrepaired)) => {
+                        eprintln!("script repair could not be applied ({})", e);
+                        unrepaired
+                    }
+                    (Err(_), None) if stopped => {
+                        // Keep showing the app this request was editing
+                        if let Some(source) = source {
                             let mut messages = state.messages;
                             messages.with_mut(|msgs| {
                                 if let Some(msg) = msgs.get_mut(idx) {
-                                    msg.content = content;
+                                    msg.content = source;
                                 }
                             });
-                            if done {
-                                break;
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("poll error: {}", e);
-                            failed = true;
-                            break;
                         }
+                        break None;
                     }
-                    tokio::time::sleep(std::time::Duration::from_millis(80)).await;
-                },
-                Err(e) => {
-                    eprintln!("start error: {}", e);
-                    failed = true;
+                    (Err(e), None) if source.is_some() => {
+                        eprintln!("edit could not be applied ({}), regenerating", e);
+                        source = None;
+                        request = regenerate_request(snapshot.clone(), structured, &e);
+                        continue;
+                    }
+                    (Err(e), None) => break Some(format!("Failed to build. {}", e)),
+                };
+
+                // Ask for a fix before the broken app is shown
+                if !stopped && !report.is_clean() && repairs < MAX_SCRIPT_REPAIRS {
+                    repairs += 1;
+                    source = Some(output.html.clone());
+                    request = repair_request(snapshot.clone(), &output.html, &report, structured);
+                    unrepaired = Some((output, report));
+                    let mut repairing = state.repairing;
+                    repairing.set(true);
+                    continue;
                 }
-            }
+                let mut script_checks = state.script_checks;
+                script_checks.with_mut(|checks| {
+                    checks.insert(
+                        idx,
+                        ScriptCheck {
+                            report,
+                            repaired: repairs > 0,
+                        },
+                    )
+                });
 
-            let mut messages = state.messages;
-            if failed {
+                let tags = if output.tags.is_empty() {
+                    vec!["App".to_string()]
+                } else {
+                    output.tags.clone()
+                };
+                let mut messages = state.messages;
                 messages.with_mut(|msgs| {
                     if let Some(msg) = msgs.get_mut(idx) {
-                        msg.content = "Failed to build. Try again.".to_string();
+                        msg.content = output.html.clone();
+                        msg.tags = tags.clone();
                     }
                 });
-            } else {
-                let content = state
-                    .messages
-                    .with(|msgs| msgs.get(idx).map(|m| m.content.clone()));
-                if let Some(content) = content {
-                    let (clean, tags) = extract_app_tags(&content);
-                    messages.with_mut(|msgs| {
-                        if let Some(msg) = msgs.get_mut(idx) {
-                            msg.content = clean;
-                            msg.tags = tags.clone();
-                        }
-                    });
-                    let mut current_tags = state.current_tags;
-                    current_tags.set(tags);
+                let mut current_tags = state.current_tags;
+                current_tags.set(tags);
+                let mut current_title = state.current_title;
+                current_title.set(output.title);
+
+                if output.tags.is_empty() {
+                    state.suggest_tags(idx, extract_html_content(&output.html));
                 }
+                break None;
+            };
+
+            if let Some(message) = failure {
+                let mut messages = state.messages;
+                messages.with_mut(|msgs| {
+                    if let Some(msg) = msgs.get_mut(idx) {
+                        msg.content = message;
+                    }
+                });
             }
 
+            let mut stream_id = state.stream_id;
+            stream_id.set(None);
             let mut streaming_index = state.streaming_index;
             streaming_index.set(None);
+            let mut repairing = state.repairing;
+            repairing.set(false);
+            let mut stop_requested = state.stop_requested;
+            stop_requested.set(false);
             let mut sending = state.sending;
             sending.set(false);
         });
     }
+
+    /// Stream one build request into message `idx`. Returns `None` if the
+    /// user stopped it, leaving what streamed in the message.
+    async fn stream_build(
+        &self,
+        idx: usize,
+        request: Vec<ChatMessage>,
+        structured: bool,
+    ) -> Option<ChatResult<()>> {
+        let (id, mut subscription) = match start_subscription(request, structured).await {
+            Ok(started) => started,
+            Err(e) => {
+                eprintln!("start error: {}", e);
+                return Some(Err(e));
+            }
+        };
+
+        let mut stream_id = self.stream_id;
+        stream_id.set(Some(id));
+        // Stop was pressed while the stream was starting
+        if (self.stop_requested)() {
+            cancel_stream(id);
+        }
+        let mut continuations = self.continuations;
+        continuations.set(0);
+        let mut preview = self.preview;
+        preview.set(None);
+        let mut previewed_at: Option<Instant> = None;
+        let mut content = String::new();
+        let result = loop {
+            match subscription.recv().await {
+                Some(StreamEvent::Routed(info)) => {
+                    let mut route = self.route;
+                    route.set(Some(info));
+                }
+                Some(StreamEvent::Continued(parts)) => {
+                    continuations.set(parts);
+                }
+                Some(StreamEvent::Delta(chunk)) => {
+                    content.push_str(&chunk);
+                    let mut messages = self.messages;
+                    messages.with_mut(|msgs| {
+                        if let Some(msg) = msgs.get_mut(idx) {
+                            msg.content = content.clone();
+                        }
+                    });
+
+                    if previewed_at.is_none_or(|at| at.elapsed() >= PREVIEW_INTERVAL)
+                        && let Some(html) = streaming_preview(&content)
+                        && preview.peek().as_deref() != Some(html.as_str())
+                    {
+                        preview.set(Some(html));
+                        previewed_at = Some(Instant::now());
+                    }
+                }
+                Some(StreamEvent::Cancelled) => break None,
+                Some(StreamEvent::Finished) | None => break Some(Ok(())),
+                Some(StreamEvent::Failed(e)) => {
+                    eprintln!("stream error: {}", e);
+                    break Some(Err(e));
+                }
+            }
+        };
+        stream_id.set(None);
+        preview.set(None);
+        result
+    }
 }
 
 // ============================================
 // Helpers
 // ============================================
 
+/// The messages sent for a build: the app builder prompt, then the conversation.
+/// `structured` switches the prompt to the JSON reply described by [`app_schema`].
+pub fn build_request(conversation: Vec<ChatMessage>, structured: bool) -> Vec<ChatMessage> {
+    let mut prompt = APP_BUILDER_SYSTEM_PROMPT.replace("{storage_quota}", &storage_quota());
+    if structured {
+        prompt.push('\n');
+        prompt.push_str(APP_JSON_OUTPUT_PROMPT);
+    }
+    let mut messages = vec![ChatMessage {
+        role: Role::System,
+        content: prompt,
+        created_at: None,
+        tags: Vec::new(),
+        tool_call_id: None,
+    }];
+    messages.extend(conversation);
+    messages
+}
+
+/// The storage quota apps are held to, as the app builder prompt states it
+fn storage_quota() -> String {
+    let quota = &*bridge::APP_STORAGE_QUOTA;
+    format!(
+        "{} in {} keys",
+        format_bytes(quota.max_bytes),
+        quota.max_keys
+    )
+}
+
+/// The messages for a follow-up once an app exists: [`build_request`] plus
+/// instructions to reply with edits, which [`resolve_build`] applies
+pub fn edit_request(conversation: Vec<ChatMessage>, structured: bool) -> Vec<ChatMessage> {
+    let mut messages = build_request(conversation, structured);
+    messages[0].content.push('\n');
+    messages[0].content.push_str(APP_EDIT_PROMPT);
+    messages
+}
+
+/// The messages asking for the whole document again after the edits in a
+/// reply could not be applied
+pub fn regenerate_request(
+    conversation: Vec<ChatMessage>,
+    structured: bool,
+    error: &PatchError,
+) -> Vec<ChatMessage> {
+    let mut messages = build_request(conversation, structured);
+    messages.insert(
+        1,
+        ChatMessage {
+            role: Role::System,
+            content: format!(
+                "Your edits could not be applied to the current app ({}). \
+Reply with the complete updated HTML document instead.",
+                error
+            ),
+            created_at: None,
+            tags: Vec::new(),
+            tool_call_id: None,
+        },
+    );
+    messages
+}
+
+/// The messages asking for fixes to the syntax errors `report` found in
+/// `html`, the app just built
+pub fn repair_request(
+    conversation: Vec<ChatMessage>,
+    html: &str,
+    report: &ValidationReport,
+    structured: bool,
+) -> Vec<ChatMessage> {
+    let errors: Vec<String> = report
+        .errors
+        .iter()
+        .map(|error| format!("- {}", error))
+        .collect();
+    let mut conversation = conversation;
+    conversation.push(ChatMessage {
+        role: Role::Assistant,
+        content: html.to_string(),
+        created_at: None,
+        tags: Vec::new(),
+        tool_call_id: None,
+    });
+    conversation.push(ChatMessage {
+        role: Role::User,
+        content: format!(
+            "The app you just built has JavaScript syntax errors, so its scripts never run:\n{}\n\
+Fix these errors and change nothing else. Lines and columns count from the start of the document.",
+            errors.join("\n")
+        ),
+        created_at: None,
+        tags: Vec::new(),
+        tool_call_id: None,
+    });
+    edit_request(conversation, structured)
+}
+
+/// The latest app source in the conversation
+fn current_source(messages: &[ChatMessage]) -> Option<String> {
+    messages
+        .iter()
+        .rev()
+        .filter(|msg| msg.role == Role::Assistant)
+        .map(|msg| extract_html_content(&msg.content))
+        .find(|html| !html.is_empty())
+}
+
+/// Parse a build reply, applying it to `source` when it is written as edits
+pub fn resolve_build(reply: &str, source: Option<&str>) -> Result<AppOutput, PatchError> {
+    let mut output = parse_app_output(reply);
+    if !patch::is_edit_reply(&output.html) {
+        return Ok(output);
+    }
+    let Some(source) = source else {
+        return Err(PatchError::Malformed(
+            "edits were returned but there is no app to apply them to".to_string(),
+        ));
+    };
+
+    let html = patch::apply_reply(source, &output.html)?;
+    output.title = html::title(&html).or(output.title);
+    output.html = html;
+    Ok(output)
+}
+
+/// Whether builds ask for structured JSON output (`WORKBENCH_STRUCTURED_OUTPUT`)
+fn structured_output_enabled() -> bool {
+    std::env::var("WORKBENCH_STRUCTURED_OUTPUT").is_ok_and(|value| {
+        matches!(
+            value.trim().to_ascii_lowercase().as_str(),
+            "1" | "true" | "yes" | "on"
+        )
+    })
+}
+
+/// Schema for structured builds
+pub fn app_schema() -> JsonSchema {
+    JsonSchema::new(
+        "app",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "title": { "type": "string" },
+                "description": { "type": "string" },
+                "tags": { "type": "array", "items": { "type": "string" } },
+                "html": { "type": "string" }
+            },
+            "required": ["title", "description", "tags", "html"],
+            "additionalProperties": false
+        }),
+    )
+}
+
+/// A build reply, from structured JSON or the text markers
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AppOutput {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// Empty when the reply named no tags
+    pub tags: Vec<String>,
+    /// The HTML document, or the cleaned reply when no document was found
+    pub html: String,
+}
+
+#[derive(serde::Deserialize)]
+struct JsonAppOutput {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    html: String,
+}
+
+/// Parse a build reply, trying the JSON object first and falling back to
+/// ```` ```html ```` fences, `<title>` and the `[[app_tags: ...]]` line
+pub fn parse_app_output(content: &str) -> AppOutput {
+    let structured = extract_json(content)
+        .and_then(|value| serde_json::from_value::<JsonAppOutput>(value).ok())
+        .filter(|output| !output.html.trim().is_empty());
+    if let Some(output) = structured {
+        let non_empty = |s: String| {
+            let s = s.trim().to_string();
+            (!s.is_empty()).then_some(s)
+        };
+        return AppOutput {
+            title: non_empty(output.title),
+            description: non_empty(output.description),
+            tags: output
+                .tags
+                .iter()
+                .filter_map(|t| capitalize_tag(t))
+                .collect(),
+            html: output.html.trim().to_string(),
+        };
+    }
+
+    let has_tag_line = content.contains("[[app_tags:");
+    let (clean, tags) = extract_app_tags(content);
+    let html = extract_html_content(&clean);
+    let html = if html.is_empty() { clean } else { html };
+    AppOutput {
+        title: html::title(&html),
+        description: html::meta(&html, "description"),
+        tags: if has_tag_line { tags } else { Vec::new() },
+        html,
+    }
+}
+
+/// The messages asking the extraction model to tag a built app
+pub fn tag_request(html: String) -> Vec<ChatMessage> {
+    vec![
+        ChatMessage {
+            role: Role::System,
+            content: APP_TAG_PROMPT.to_string(),
+            created_at: None,
+            tags: Vec::new(),
+            tool_call_id: None,
+        },
+        ChatMessage {
+            role: Role::User,
+            content: html,
+            created_at: None,
+            tags: Vec::new(),
+            tool_call_id: None,
+        },
+    ]
+}
+
+fn cancel_stream(id: u64) {
+    spawn(async move {
+        if let Err(e) = chat_reply_stream_cancel(id).await {
+            eprintln!("cancel error: {}", e);
+        }
+    });
+}
+
+/// Whether a build got as far as closing its document
+fn is_finished_document(html: &str) -> bool {
+    html.to_ascii_lowercase().contains("</html>")
+}
+
+async fn start_subscription(
+    messages: Vec<ChatMessage>,
+    structured: bool,
+) -> ChatResult<(u64, StreamSubscription)> {
+    let format = if structured {
+        ResponseFormat::Json(app_schema())
+    } else {
+        ResponseFormat::Text
+    };
+    let id = BlackbirdAI::from_env()?
+        .with_response_format(format)
+        .start_stream(messages, Task::Build);
+    let subscription = chat_reply_stream_subscribe(id).await?;
+    Ok((id, subscription))
+}
+
 fn truncate(s: &str, max: usize) -> String {
     if s.len() <= max {
         s.to_string()
@@ -669,15 +1487,59 @@ fn truncate(s: &str, max: usize) -> String {
     }
 }
 
-/// Simple hash for generating stable app IDs
-fn simple_hash(s: &str) -> u64 {
-    use std::hash::{Hash, Hasher};
-    let mut hasher = std::collections::hash_map::DefaultHasher::new();
-    s.hash(&mut hasher);
-    hasher.finish()
+/// A namespace for this Workbench session's previews
+fn new_preview_app_id() -> String {
+    format!(
+        "{}{:x}",
+        bridge::PREVIEW_APP_PREFIX,
+        OffsetDateTime::now_utc().unix_timestamp_nanos()
+    )
+}
+
+/// A follow-up asking the model to fix an error the app hit while running
+fn fix_error_prompt(entry: &ConsoleEntry) -> String {
+    match entry.stack {
+        Some(ref stack) => format!(
+            "The app throws this error when it runs:\n\n{}\n\n{}\n\nFix it.",
+            entry.message, stack
+        ),
+        None => format!(
+            "The app throws this error when it runs:\n\n{}\n\nFix it.",
+            entry.message
+        ),
+    }
+}
+
+/// What to render while a build streams: the document so far with open tags
+/// closed and any unfinished script held back. `None` until the `<head>` is
+/// complete, and for edit or JSON replies, which are not a document.
+pub fn streaming_preview(content: &str) -> Option<String> {
+    if patch::is_edit_reply(content) {
+        return None;
+    }
+    let mut partial = content.trim_start();
+    if let Some(fenced) = partial.strip_prefix("```html") {
+        partial = fenced;
+    }
+    if let Some(end) = partial.find("```") {
+        partial = &partial[..end];
+    }
+    if let Some(end) = partial.find("[[app_tags:") {
+        partial = &partial[..end];
+    }
+    let partial = partial.trim();
+    if !partial.starts_with('<') {
+        return None;
+    }
+    let lower = partial.to_ascii_lowercase();
+    if lower.contains("<head") && !lower.contains("</head>") {
+        return None;
+    }
+    Some(html::complete_partial(partial))
 }
 
-fn extract_html_content(content: &str) -> String {
+/// The HTML document in a build reply, from a ```html fence or the raw reply
+pub fn extract_html_content(content: &str) -> String {
     if let Some(start) = content.find("```html") {
         let after = start + 7;
         if let Some(end) = content[after..].find("```") {
@@ -695,80 +1557,21 @@ fn extract_html_content(content: &str) -> String {
     String::new()
 }
 
-fn extract_app_title(html: &str) -> Option<String> {
-    let lower = html.to_lowercase();
-    let start = lower.find("<title>")? + 7;
-    let end = lower[start..].find("</title>")?;
-    let title = html[start..start + end].trim();
-    if title.is_empty() {
-        None
-    } else {
-        Some(title.to_string())
-    }
-}
-
-fn extract_app_tags(content: &str) -> (String, Vec<String>) {
+/// Split the trailing `[[app_tags: ...]]` line off a build reply
+pub fn extract_app_tags(content: &str) -> (String, Vec<String>) {
     if let Some(start) = content.rfind("[[app_tags:")
         && let Some(end) = content[start..].find("]]")
     {
         let raw = &content[start + 11..start + end];
         let clean = content[..start].trim_end().to_string();
-        let tags: Vec<String> = raw
-            .split(',')
-            .filter_map(|t| {
-                let t = t.trim();
-                if t.is_empty() {
-                    None
-                } else {
-                    let mut c = t.chars();
-                    Some(format!("{}{}", c.next()?.to_uppercase(), c.as_str()))
-                }
-            })
-            .collect();
+        let tags: Vec<String> = raw.split(',').filter_map(capitalize_tag).collect();
         return (clean, tags);
     }
     (content.to_string(), vec!["App".to_string()])
 }
 
-fn inject_theme_css(html: &str, theme_css: &str) -> String {
-    // Insert theme CSS right after <head> or at the start if no head
-    if let Some(pos) = html.to_lowercase().find("<head>") {
-        let insert_pos = pos + 6;
-        format!(
-            "{}<style>{}</style>{}",
-            &html[..insert_pos],
-            theme_css,
-            &html[insert_pos..]
-        )
-    } else if let Some(pos) = html.to_lowercase().find("<html") {
-        // Find end of <html> tag
-        if let Some(end) = html[pos..].find('>') {
-            let insert_pos = pos + end + 1;
-            format!(
-                "{}<head><style>{}</style></head>{}",
-                &html[..insert_pos],
-                theme_css,
-                &html[insert_pos..]
-            )
-        } else {
-            format!("<style>{}</style>{}", theme_css, html)
-        }
-    } else {
-        format!("<style>{}</style>{}", theme_css, html)
-    }
-}
-
-/// Inject both theme CSS and the Blackbird SDK into app HTML
-fn inject_theme_and_sdk(html: &str, theme_css: &str, app_id: &str) -> String {
-    let sdk_script = bridge::get_sdk_script(app_id);
-    let themed = inject_theme_css(html, theme_css);
-
-    // Insert SDK script before </body> or at the end
-    if let Some(pos) = themed.to_lowercase().rfind("</body>") {
-        format!("{}{}{}", &themed[..pos], sdk_script, &themed[pos..])
-    } else if let Some(pos) = themed.to_lowercase().rfind("</html>") {
-        format!("{}{}{}", &themed[..pos], sdk_script, &themed[pos..])
-    } else {
-        format!("{}{}", themed, sdk_script)
-    }
+fn capitalize_tag(tag: &str) -> Option<String> {
+    let mut chars = tag.trim().chars();
+    let first = chars.next()?;
+    Some(format!("{}{}", first.to_uppercase(), chars.as_str()))
 }
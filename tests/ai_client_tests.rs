@@ -0,0 +1,270 @@
+//! Integration tests for AI client error handling
+//!
+//! Runs the Blackbird provider against a local mock HTTP server to check
+//! error classification, retry/backoff and timeouts
+
+use std::time::Duration;
+
+use blackbird::ai::{
+    BlackbirdAI, BlackbirdClient, ChatError, JsonSchema, OpenAICompatibleClient, ProviderClient,
+    ProviderRoute, ProviderRouter, ResponseFormat, RetryPolicy, Task, Timeouts,
+};
+use blackbird::types::{ChatMessage, Role};
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn fast_retry(max_retries: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_retries,
+        base_delay: Duration::from_millis(10),
+        max_delay: Duration::from_secs(2),
+    }
+}
+
+fn client_for(server: &MockServer, timeouts: Timeouts, retry: RetryPolicy) -> BlackbirdAI {
+    let client = BlackbirdClient::new(
+        server.uri(),
+        "test".to_string(),
+        "test-model".to_string(),
+        Some("key".to_string()),
+    )
+    .with_http_client(timeouts.http_client());
+    BlackbirdAI::new(ProviderClient::Blackbird(client)).with_retry_policy(retry)
+}
+
+fn ok_body(content: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(serde_json::json!({
+        "choices": [{ "message": { "content": content } }]
+    }))
+}
+
+#[tokio::test]
+async fn test_rate_limit_honours_retry_after_then_succeeds() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(ok_body("hello"))
+        .mount(&server)
+        .await;
+
+    let ai = client_for(&server, Timeouts::default(), fast_retry(2));
+    let started = std::time::Instant::now();
+    let reply = ai.prompt("hi").await.expect("retry should succeed");
+
+    assert_eq!(reply, "hello");
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert_eq!(server.received_requests().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_retry_after_beyond_max_delay_is_not_retried() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "600"))
+        .mount(&server)
+        .await;
+
+    let ai = client_for(&server, Timeouts::default(), fast_retry(3));
+    let err = ai.prompt("hi").await.unwrap_err();
+
+    assert_eq!(
+        err,
+        ChatError::RateLimited {
+            retry_after: Some(Duration::from_secs(600))
+        }
+    );
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_auth_error_is_not_retried() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(401).set_body_string("invalid key"))
+        .mount(&server)
+        .await;
+
+    let ai = client_for(&server, Timeouts::default(), fast_retry(3));
+    let err = ai.prompt("hi").await.unwrap_err();
+
+    assert_eq!(err, ChatError::Auth("invalid key".to_string()));
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_server_errors_exhaust_retries() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(503).set_body_string("overloaded"))
+        .mount(&server)
+        .await;
+
+    let ai = client_for(&server, Timeouts::default(), fast_retry(2));
+    let err = ai.prompt("hi").await.unwrap_err();
+
+    assert_eq!(
+        err,
+        ChatError::Provider {
+            status: Some(503),
+            body: "overloaded".to_string()
+        }
+    );
+    assert_eq!(server.received_requests().await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_slow_response_times_out() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ok_body("late").set_delay(Duration::from_secs(2)))
+        .mount(&server)
+        .await;
+
+    let timeouts = Timeouts {
+        connect: Duration::from_secs(1),
+        read: Duration::from_millis(200),
+    };
+    let ai = client_for(&server, timeouts, RetryPolicy::none());
+    let err = ai.prompt("hi").await.unwrap_err();
+
+    assert_eq!(err, ChatError::Timeout);
+}
+
+#[tokio::test]
+async fn test_unreachable_endpoint_is_network_error() {
+    // Reserve a free port, then release it so nothing is listening there
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .unwrap();
+    let uri = format!("http://{}", addr);
+
+    let client = BlackbirdClient::new(uri, "test".to_string(), "m".to_string(), None);
+    let ai =
+        BlackbirdAI::new(ProviderClient::Blackbird(client)).with_retry_policy(RetryPolicy::none());
+    let err = ai.prompt("hi").await.unwrap_err();
+
+    assert!(matches!(err, ChatError::Network(_)), "got {:?}", err);
+}
+
+#[tokio::test]
+async fn test_openai_compatible_uses_base_url_and_headers() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .and(header("x-team", "inference"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "local-model",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "from the local box" },
+                "finish_reason": "stop"
+            }],
+            "usage": { "prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2 }
+        })))
+        .mount(&server)
+        .await;
+
+    let client = OpenAICompatibleClient::new(
+        &format!("{}/v1/", server.uri()),
+        None,
+        "local-model".to_string(),
+        &[("X-Team".to_string(), "inference".to_string())],
+        Timeouts::default(),
+    )
+    .unwrap();
+    let ai = BlackbirdAI::new(ProviderClient::OpenAICompatible(client))
+        .with_retry_policy(RetryPolicy::none());
+
+    assert_eq!(ai.prompt("hi").await.unwrap(), "from the local box");
+}
+
+#[tokio::test]
+async fn test_router_fails_over_and_reports_route() {
+    let primary = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(401).set_body_string("expired key"))
+        .mount(&primary)
+        .await;
+    let backup = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ok_body("from backup"))
+        .mount(&backup)
+        .await;
+
+    let route = |server: &MockServer| {
+        let client = BlackbirdClient::new(server.uri(), "t".to_string(), "m".to_string(), None);
+        ProviderRoute::new(ProviderClient::Blackbird(client)).with_models("strong", "cheap")
+    };
+    let router = ProviderRouter::new(vec![route(&primary), route(&backup)]).unwrap();
+    let ai = BlackbirdAI::with_router(router).with_retry_policy(RetryPolicy::none());
+
+    let reply = ai
+        .chat(Task::Extraction, "tag this", Vec::new())
+        .await
+        .unwrap();
+
+    assert_eq!(reply.content, "from backup");
+    assert_eq!(reply.route.model, "cheap");
+    let sent: serde_json::Value =
+        serde_json::from_slice(&backup.received_requests().await.unwrap()[0].body).unwrap();
+    assert_eq!(sent["model"], "cheap");
+}
+
+#[tokio::test]
+async fn test_system_message_replaces_default_prompt() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ok_body("ok"))
+        .mount(&server)
+        .await;
+
+    let history = vec![ChatMessage {
+        role: Role::System,
+        content: "You build apps.".to_string(),
+        created_at: None,
+        tags: Vec::new(),
+        tool_call_id: None,
+    }];
+    let ai = client_for(&server, Timeouts::default(), RetryPolicy::none());
+    ai.chat(Task::Build, "make a timer", history).await.unwrap();
+
+    let sent: serde_json::Value =
+        serde_json::from_slice(&server.received_requests().await.unwrap()[0].body).unwrap();
+    let roles: Vec<&str> = sent["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|msg| msg["role"].as_str().unwrap())
+        .collect();
+    assert_eq!(roles, ["system", "user"]);
+    assert_eq!(sent["messages"][0]["content"], "You build apps.");
+}
+
+#[tokio::test]
+async fn test_json_mode_sends_response_format() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ok_body(r#"{"answer": "yes"}"#))
+        .mount(&server)
+        .await;
+
+    let schema = JsonSchema::new(
+        "answer",
+        serde_json::json!({ "type": "object", "properties": { "answer": { "type": "string" } } }),
+    );
+    let ai = client_for(&server, Timeouts::default(), RetryPolicy::none())
+        .with_response_format(ResponseFormat::Json(schema));
+    ai.prompt("yes or no?").await.unwrap();
+
+    let sent: serde_json::Value =
+        serde_json::from_slice(&server.received_requests().await.unwrap()[0].body).unwrap();
+    assert_eq!(sent["response_format"]["type"], "json_schema");
+    assert_eq!(sent["response_format"]["json_schema"]["name"], "answer");
+}
@@ -0,0 +1,561 @@
+//! Integration tests driven by the mock provider
+//!
+//! Covers Workbench generation, tag extraction, tool loops, error injection
+//! and fixture record/replay without contacting a real LLM
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use blackbird::ai::{
+    BlackbirdAI, ChatError, FixtureStore, MAX_CONTINUATIONS, MockClient, MockResponse,
+    ProviderClient, ProviderRoute, ProviderRouter, ResponseFormat, RetryPolicy, StreamEvent, Task,
+    chat_reply_stream_subscribe,
+};
+use blackbird::tools::ToolContext;
+use blackbird::types::{ChatMessage, Role, ThemeMode};
+use blackbird::validate::check_scripts;
+use blackbird::views::workbench::{
+    app_schema, build_request, edit_request, extract_app_tags, extract_html_content,
+    parse_app_output, regenerate_request, repair_request, resolve_build, streaming_preview,
+    tag_request,
+};
+
+const BUILD_REPLY: &str = "<!DOCTYPE html>\n<html><head><title>Timer</title></head>\
+<body><button id=\"start\">Start</button></body></html>\n[[app_tags: timer, productivity]]";
+
+fn user(content: &str) -> ChatMessage {
+    ChatMessage {
+        role: Role::User,
+        content: content.to_string(),
+        created_at: None,
+        tags: Vec::new(),
+        tool_call_id: None,
+    }
+}
+
+fn ai_for(mock: &MockClient) -> BlackbirdAI {
+    let route =
+        ProviderRoute::new(ProviderClient::Mock(mock.clone())).with_models("strong", "cheap");
+    BlackbirdAI::with_router(ProviderRouter::new(vec![route]).unwrap())
+        .with_retry_policy(RetryPolicy::none())
+        .with_tool_context(ToolContext::default())
+}
+
+/// Collect a stream's text and its final event
+async fn collect(id: u64) -> (String, StreamEvent) {
+    let mut subscription = chat_reply_stream_subscribe(id).await.unwrap();
+    let mut content = String::new();
+    loop {
+        match subscription
+            .recv()
+            .await
+            .expect("stream ended without a final event")
+        {
+            StreamEvent::Delta(chunk) => content.push_str(&chunk),
+            StreamEvent::Routed(_) | StreamEvent::Continued(_) => {}
+            done => return (content, done),
+        }
+    }
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "blackbird-fixtures-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[tokio::test]
+async fn test_workbench_generation_streams_app() {
+    let mock = MockClient::new()
+        .with_responses([MockResponse::text(BUILD_REPLY)])
+        .with_chunking(16, Duration::from_millis(1));
+
+    let id = ai_for(&mock).start_stream(
+        build_request(vec![user("make a timer")], false),
+        Task::Build,
+    );
+    let (content, done) = collect(id).await;
+
+    assert_eq!(done, StreamEvent::Finished);
+    assert_eq!(content, BUILD_REPLY);
+
+    let (clean, tags) = extract_app_tags(&content);
+    assert_eq!(tags, ["Timer", "Productivity"]);
+    assert!(extract_html_content(&clean).ends_with("</html>"));
+
+    let request = &mock.requests()[0];
+    assert_eq!(request.model, "strong");
+    assert_eq!(request.messages[0].role, Role::System);
+    assert_eq!(
+        request.messages[0].content,
+        build_request(Vec::new(), false)[0].content
+    );
+    assert_eq!(request.messages.last().unwrap().content, "make a timer");
+}
+
+#[test]
+fn test_prompt_example_handles_storage_errors() {
+    let prompt = &build_request(Vec::new(), false)[0].content;
+    let start = prompt.find("<!DOCTYPE html>").unwrap();
+    let end = prompt[start..].find("</html>").unwrap() + start + "</html>".len();
+    let example = &prompt[start..end];
+
+    assert!(check_scripts(example).is_clean());
+    assert!(example.contains("await blackbird.storage.set('todos', todos);"));
+    assert!(example.contains("e.name === 'QuotaExceededError'"));
+}
+
+#[tokio::test]
+async fn test_structured_build_requests_schema() {
+    let reply = serde_json::json!({
+        "title": "Pomodoro",
+        "description": "A focus timer.",
+        "tags": ["timer", "focus"],
+        "html": "<!DOCTYPE html><html><body>[[app_tags: not a tag line]]</body></html>"
+    });
+    let mock = MockClient::new().with_responses([MockResponse::text(reply.to_string())]);
+
+    let content = ai_for(&mock)
+        .with_response_format(ResponseFormat::Json(app_schema()))
+        .reply(build_request(vec![user("make a timer")], true), Task::Build)
+        .await
+        .unwrap()
+        .content;
+
+    let request = &mock.requests()[0];
+    assert_eq!(request.schema.as_ref(), Some(&app_schema()));
+    assert!(
+        request.messages[0]
+            .content
+            .contains("OUTPUT FORMAT OVERRIDE")
+    );
+    assert!(request.messages[0].content.contains("JSON schema"));
+
+    let output = parse_app_output(&content);
+    assert_eq!(output.title.as_deref(), Some("Pomodoro"));
+    assert_eq!(output.description.as_deref(), Some("A focus timer."));
+    assert_eq!(output.tags, ["Timer", "Focus"]);
+    assert!(output.html.contains("not a tag line"));
+}
+
+#[test]
+fn test_structured_output_falls_back_to_text_markers() {
+    let output = parse_app_output(BUILD_REPLY);
+    assert_eq!(output.title.as_deref(), Some("Timer"));
+    assert_eq!(output.tags, ["Timer", "Productivity"]);
+    assert!(output.html.starts_with("<!DOCTYPE html>"));
+    assert!(!output.html.contains("app_tags"));
+
+    let untagged = parse_app_output("<html><body></body></html>");
+    assert!(untagged.tags.is_empty());
+
+    let described = parse_app_output(
+        "<html><head lang=\"en\"><meta name=\"description\" content=\"Counts down.\"><Title>Café</Title></head></html>",
+    );
+    assert_eq!(described.title.as_deref(), Some("Café"));
+    assert_eq!(described.description.as_deref(), Some("Counts down."));
+}
+
+#[tokio::test]
+async fn test_tag_extraction_uses_cheap_model() {
+    let mock = MockClient::new().with_responses([MockResponse::text("[[app_tags: games]]")]);
+    let html = "<html><body><canvas></canvas></body></html>".to_string();
+
+    let reply = ai_for(&mock)
+        .reply(tag_request(html.clone()), Task::Extraction)
+        .await
+        .unwrap();
+
+    let (_, tags) = extract_app_tags(&reply.content);
+    assert_eq!(tags, ["Games"]);
+    assert_eq!(reply.route.model, "cheap");
+
+    let request = &mock.requests()[0];
+    assert_eq!(request.model, "cheap");
+    let roles: Vec<_> = request.messages.iter().map(|m| m.role.clone()).collect();
+    assert_eq!(roles, [Role::System, Role::User]);
+    assert_eq!(request.messages[1].content, html);
+}
+
+#[tokio::test]
+async fn test_tool_loop_sends_results_back() {
+    let mock = MockClient::new().with_responses([
+        MockResponse::tool_call("get_setting", serde_json::json!({ "setting": "theme" })),
+        MockResponse::text("You are using the Octane theme."),
+    ]);
+    let tools = ToolContext::default();
+    tools.set_theme(ThemeMode::Octane);
+
+    let reply = ai_for(&mock)
+        .with_tool_context(tools)
+        .prompt("Which theme am I using?")
+        .await
+        .unwrap();
+
+    assert_eq!(reply, "You are using the Octane theme.");
+    let requests = mock.requests();
+    assert_eq!(requests.len(), 2);
+    let result = requests[1].messages.last().unwrap();
+    assert_eq!(result.role, Role::Tool);
+    assert_eq!(result.content, "\"Octane\"");
+    assert!(result.tool_call_id.is_some());
+}
+
+#[tokio::test]
+async fn test_without_tools_offers_none() {
+    let mock = MockClient::new().with_responses([MockResponse::tool_call(
+        "get_setting",
+        serde_json::json!({ "setting": "theme" }),
+    )]);
+
+    let result = ai_for(&mock).without_tools().prompt("Which theme?").await;
+
+    assert!(result.unwrap_err().to_string().contains("without tools"));
+}
+
+#[tokio::test]
+async fn test_injected_error_is_retried() {
+    let mock = MockClient::new().with_responses([
+        MockResponse::Error(ChatError::RateLimited { retry_after: None }),
+        MockResponse::text("recovered"),
+    ]);
+    let ai = ai_for(&mock).with_retry_policy(RetryPolicy {
+        max_retries: 1,
+        base_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(10),
+    });
+
+    assert_eq!(ai.prompt("hi").await.unwrap(), "recovered");
+    assert_eq!(mock.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn test_interrupted_stream_keeps_partial_and_is_not_retried() {
+    let mock = MockClient::new().with_responses([
+        MockResponse::Interrupted {
+            partial: "<!DOCTYPE html><html>".into(),
+            error: ChatError::Network("connection reset".into()),
+        },
+        MockResponse::text("should not be requested"),
+    ]);
+    let ai = ai_for(&mock).with_retry_policy(RetryPolicy {
+        max_retries: 3,
+        base_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(10),
+    });
+
+    let id = ai.start_stream(vec![user("make a timer")], Task::Build);
+    let (content, done) = collect(id).await;
+
+    assert_eq!(content, "<!DOCTYPE html><html>");
+    assert_eq!(
+        done,
+        StreamEvent::Failed(ChatError::Network("connection reset".into()))
+    );
+    assert_eq!(mock.remaining(), 1);
+}
+
+#[tokio::test]
+async fn test_record_then_replay() {
+    let dir = scratch_dir("roundtrip");
+    let recording = MockClient::new().with_responses([MockResponse::text("recorded answer")]);
+    ai_for(&recording)
+        .with_recorder(FixtureStore::new(&dir))
+        .reply(vec![user("hello")], Task::Chat)
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    let replay = MockClient::replay(FixtureStore::new(&dir));
+    let reply = ai_for(&replay)
+        .reply(vec![user("hello")], Task::Chat)
+        .await
+        .unwrap();
+    assert_eq!(reply.content, "recorded answer");
+
+    let missing = ai_for(&replay)
+        .reply(vec![user("something else")], Task::Chat)
+        .await
+        .unwrap_err();
+    assert!(matches!(missing, ChatError::Config(_)), "got {:?}", missing);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_replays_checked_in_fixture() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/llm");
+    let replay = MockClient::replay(FixtureStore::new(dir));
+    let html = "<html><head><title>Pomodoro</title></head><body></body></html>".to_string();
+
+    let reply = ai_for(&replay)
+        .reply(tag_request(html), Task::Extraction)
+        .await
+        .unwrap();
+
+    let (_, tags) = extract_app_tags(&reply.content);
+    assert_eq!(tags, ["Productivity", "Timer"]);
+}
+
+fn assistant(content: &str) -> ChatMessage {
+    ChatMessage {
+        role: Role::Assistant,
+        ..user(content)
+    }
+}
+
+fn iteration_history() -> Vec<ChatMessage> {
+    vec![
+        user("make a timer"),
+        assistant("<!DOCTYPE html><html><body>v1</body></html>"),
+        user("make it red"),
+        assistant("<!DOCTYPE html><html><body>v2</body></html>"),
+        user("add a sound"),
+    ]
+}
+
+#[tokio::test]
+async fn test_only_latest_app_source_is_sent() {
+    let mock = MockClient::new().with_responses([MockResponse::text(BUILD_REPLY)]);
+
+    ai_for(&mock)
+        .reply(build_request(iteration_history(), false), Task::Build)
+        .await
+        .unwrap();
+
+    let sent = &mock.requests()[0].messages;
+    let sources: Vec<_> = sent
+        .iter()
+        .filter(|msg| msg.role == Role::Assistant && msg.content.contains("<html>"))
+        .collect();
+    assert_eq!(sources.len(), 1);
+    assert!(sources[0].content.contains("v2"));
+}
+
+#[tokio::test]
+async fn test_over_budget_history_is_summarised() {
+    let mock = MockClient::new().with_responses([
+        MockResponse::text("- A countdown timer\n- Red theme"),
+        MockResponse::text(BUILD_REPLY),
+    ]);
+
+    ai_for(&mock)
+        .with_context_window(4_096 + 100)
+        .reply(build_request(iteration_history(), false), Task::Build)
+        .await
+        .unwrap();
+
+    let requests = mock.requests();
+    assert_eq!(requests.len(), 2);
+    // The summary comes from the extraction model and never sees app source
+    assert_eq!(requests[0].model, "cheap");
+    let transcript = &requests[0].messages.last().unwrap().content;
+    assert!(transcript.contains("User: make a timer"));
+    assert!(!transcript.contains("<html>"));
+
+    let build = &requests[1].messages;
+    assert!(build[0].content.contains("- Red theme"));
+    assert!(build.iter().all(|msg| msg.content != "make a timer"));
+    assert!(build.iter().any(|msg| msg.content.contains("v2")));
+    assert_eq!(build.last().unwrap().content, "add a sound");
+}
+
+#[tokio::test]
+async fn test_summary_keeps_default_system_prompt() {
+    let mock = MockClient::new().with_responses([
+        MockResponse::text("- A countdown timer"),
+        MockResponse::text("Done"),
+    ]);
+
+    ai_for(&mock)
+        .with_context_window(4_096 + 100)
+        .reply(iteration_history(), Task::Build)
+        .await
+        .unwrap();
+
+    let build = &mock.requests()[1].messages;
+    assert!(build[0].content.starts_with("You are Blackbird"));
+    assert!(build[0].content.contains("- A countdown timer"));
+}
+
+#[tokio::test]
+async fn test_summary_falls_back_to_request_list() {
+    let mock = MockClient::new().with_responses([
+        MockResponse::Error(ChatError::Auth("no key".into())),
+        MockResponse::text(BUILD_REPLY),
+    ]);
+
+    ai_for(&mock)
+        .with_context_window(4_096 + 100)
+        .reply(build_request(iteration_history(), false), Task::Build)
+        .await
+        .unwrap();
+
+    let build = &mock.requests()[1].messages;
+    assert!(build[0].content.contains("- make a timer"));
+}
+
+#[tokio::test]
+async fn test_truncated_stream_is_continued_and_stitched() {
+    let mock = MockClient::new()
+        .with_responses([
+            MockResponse::Truncated(
+                "<!DOCTYPE html>\n<html><head><title>Timer</title></head><bo".into(),
+            ),
+            MockResponse::text("```html\n<title>Timer</title></head><body></body></html>"),
+        ])
+        .with_chunking(8, Duration::ZERO);
+
+    let id = ai_for(&mock).start_stream(
+        build_request(vec![user("make a timer")], false),
+        Task::Build,
+    );
+    let mut subscription = chat_reply_stream_subscribe(id).await.unwrap();
+    let mut content = String::new();
+    let mut parts = Vec::new();
+    loop {
+        match subscription.recv().await.unwrap() {
+            StreamEvent::Delta(chunk) => content.push_str(&chunk),
+            StreamEvent::Continued(part) => parts.push(part),
+            StreamEvent::Routed(_) => {}
+            done => {
+                assert_eq!(done, StreamEvent::Finished);
+                break;
+            }
+        }
+    }
+
+    assert_eq!(parts, [1]);
+    assert_eq!(
+        content,
+        "<!DOCTYPE html>\n<html><head><title>Timer</title></head><body></body></html>"
+    );
+
+    let requests = mock.requests();
+    let followup = &requests[1].messages;
+    let n = followup.len();
+    assert_eq!(followup[n - 3].content, "make a timer");
+    assert_eq!(followup[n - 2].role, Role::Assistant);
+    assert!(followup[n - 2].content.ends_with("<bo"));
+    assert!(followup[n - 1].content.contains("cut off"));
+}
+
+#[tokio::test]
+async fn test_unclosed_build_is_continued_up_to_limit() {
+    let mock = MockClient::new();
+    mock.push(MockResponse::text("<html><body>"));
+    for _ in 0..MAX_CONTINUATIONS {
+        mock.push(MockResponse::text("<p>more</p>"));
+    }
+
+    let reply = ai_for(&mock)
+        .reply(
+            build_request(vec![user("make a timer")], false),
+            Task::Build,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(reply.continuations, MAX_CONTINUATIONS);
+    assert_eq!(
+        reply.content,
+        "<html><body><p>more</p><p>more</p><p>more</p>"
+    );
+    assert_eq!(mock.remaining(), 0);
+
+    // Chat replies are only continued when the provider reports the limit
+    let mock = MockClient::new().with_responses([MockResponse::text("Try <html>")]);
+    let reply = ai_for(&mock)
+        .reply(vec![user("which tag?")], Task::Chat)
+        .await
+        .unwrap();
+    assert_eq!(reply.continuations, 0);
+}
+
+#[tokio::test]
+async fn test_follow_up_edit_is_applied_to_current_source() {
+    let source = "<!DOCTYPE html>\n<html><head><title>Timer</title></head>\n<body>\n<h1>Timer</h1>\n</body></html>";
+    let edit = "<<<<<<< SEARCH\n<h1>Timer</h1>\n=======\n<h1 style=\"color: red\">Timer</h1>\n>>>>>>> REPLACE\n[[app_tags: Timer]]";
+    let mock = MockClient::new().with_responses([MockResponse::text(edit)]);
+    let history = vec![user("make a timer"), assistant(source), user("make it red")];
+
+    let reply = ai_for(&mock)
+        .reply(edit_request(history, false), Task::Build)
+        .await
+        .unwrap();
+    assert_eq!(reply.continuations, 0);
+    assert!(mock.requests()[0].messages[0].content.contains("EDIT MODE"));
+
+    let output = resolve_build(&reply.content, Some(source)).unwrap();
+    assert_eq!(output.title.as_deref(), Some("Timer"));
+    assert_eq!(output.tags, ["Timer"]);
+    assert!(output.html.contains("<h1 style=\"color: red\">Timer</h1>"));
+    assert!(extract_html_content(&output.html).ends_with("</body></html>"));
+
+    // A full document is taken as-is even in edit mode
+    let output = resolve_build(BUILD_REPLY, Some(source)).unwrap();
+    assert!(output.html.contains("id=\"start\""));
+}
+
+#[test]
+fn test_failed_edit_falls_back_to_regeneration() {
+    let source = "<html><body><h1>Timer</h1></body></html>";
+    let edit = "<<<<<<< SEARCH\n<h2>Clock</h2>\n=======\n<h2>Timer</h2>\n>>>>>>> REPLACE";
+    let err = resolve_build(edit, Some(source)).unwrap_err();
+
+    let request = regenerate_request(vec![user("rename it")], false, &err);
+    assert!(!request[0].content.contains("EDIT MODE"));
+    assert_eq!(request[1].role, Role::System);
+    assert!(request[1].content.contains("did not match"));
+    assert_eq!(request.last().unwrap().content, "rename it");
+
+    assert!(resolve_build(edit, None).is_err());
+}
+
+#[tokio::test]
+async fn test_script_errors_get_a_repair_turn() {
+    let broken = "<!DOCTYPE html>\n<html><body>\n<script>\nconst start = () => {\n  go(;\n};\n</script></body></html>";
+    let fix = "<<<<<<< SEARCH\n  go(;\n=======\n  go();\n>>>>>>> REPLACE\n[[app_tags: Timer]]";
+    let mock = MockClient::new().with_responses([MockResponse::text(fix)]);
+
+    let output = resolve_build(broken, None).unwrap();
+    let report = check_scripts(&output.html);
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].line, 5);
+
+    let history = vec![user("make a timer")];
+    let request = repair_request(history, &output.html, &report, false);
+    assert!(request[0].content.contains("EDIT MODE"));
+    assert_eq!(request[2].content, output.html);
+    assert!(request[3].content.contains("Script 1, line 5"));
+
+    let reply = ai_for(&mock).reply(request, Task::Build).await.unwrap();
+    let repaired = resolve_build(&reply.content, Some(&output.html)).unwrap();
+    assert!(repaired.html.contains("  go();"));
+    assert!(check_scripts(&repaired.html).is_clean());
+}
+
+#[test]
+fn test_streaming_preview_renders_partial_document() {
+    let cut = BUILD_REPLY.find("<button").unwrap();
+    assert_eq!(
+        streaming_preview(&BUILD_REPLY[..cut + 10]).as_deref(),
+        Some("<!DOCTYPE html>\n<html><head><title>Timer</title></head><body></body></html>")
+    );
+    // Nothing to show while the head is still streaming
+    assert_eq!(
+        streaming_preview("```html\n<!DOCTYPE html><html><head><sty"),
+        None
+    );
+
+    let done = streaming_preview(BUILD_REPLY).unwrap();
+    assert!(done.ends_with("</body></html>"));
+    assert!(!done.contains("app_tags"));
+
+    assert_eq!(streaming_preview("<<<<<<< SEARCH\n<h1>"), None);
+    assert_eq!(streaming_preview("{\"title\": \"Timer\""), None);
+}
@@ -0,0 +1,81 @@
+//! Tests for Workbench preview storage: handing it to saved apps and
+//! collecting namespaces left over from earlier sessions
+//!
+//! These run against an in-memory backend, in their own test binary, so they
+//! never clear previews in the real app data directory.
+
+use blackbird::bridge::{
+    self, PREVIEW_APP_PREFIX, storage_clear, storage_get, storage_keys, storage_set,
+};
+use blackbird::storage::{self, MemoryBackend};
+use std::sync::{Arc, Mutex, MutexGuard, Once};
+
+/// Switch to memory storage, and keep other tests out until the guard drops,
+/// since collecting previews clears theirs
+fn use_memory_storage() -> MutexGuard<'static, ()> {
+    static INIT: Once = Once::new();
+    static LOCK: Mutex<()> = Mutex::new(());
+    INIT.call_once(|| storage::set_backend(Arc::new(MemoryBackend::new())));
+    LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn sorted(mut keys: Vec<String>) -> Vec<String> {
+    keys.sort();
+    keys
+}
+
+#[test]
+fn test_copy_preview_storage() {
+    let _storage = use_memory_storage();
+    let preview = "preview-copy";
+    let saved = "saved-app-copy";
+    storage_set(preview, "todos", "[1,2]").unwrap();
+    storage_set(preview, "theme", "dark").unwrap();
+    storage_set(saved, "theme", "light").unwrap();
+
+    assert_eq!(bridge::storage_copy(preview, saved).unwrap(), 2);
+    assert_eq!(storage_get(saved, "todos").as_deref(), Some("[1,2]"));
+    assert_eq!(storage_get(saved, "theme").as_deref(), Some("dark"));
+    // The preview keeps its data
+    assert_eq!(sorted(storage_keys(preview)), vec!["theme", "todos"]);
+}
+
+#[test]
+fn test_move_preview_storage() {
+    let _storage = use_memory_storage();
+    let preview = "preview-move";
+    let saved = "saved-app-move";
+    storage_set(preview, "score", "9001").unwrap();
+
+    assert_eq!(bridge::storage_move(preview, saved).unwrap(), 1);
+    assert_eq!(storage_get(saved, "score").as_deref(), Some("9001"));
+    assert!(storage_keys(preview).is_empty());
+
+    // Moving an empty namespace changes nothing
+    assert_eq!(bridge::storage_move(preview, saved).unwrap(), 0);
+    assert_eq!(storage_keys(saved), vec!["score"]);
+}
+
+#[test]
+fn test_collect_preview_storage() {
+    let _storage = use_memory_storage();
+    let current = format!("{}current", PREVIEW_APP_PREFIX);
+    let orphans = ["preview-live", "preview-0123abcd"];
+    for app_id in orphans
+        .iter()
+        .copied()
+        .chain([current.as_str(), "saved-app-gc"])
+    {
+        storage_set(app_id, "key", "value").unwrap();
+    }
+
+    assert!(bridge::collect_preview_storage(&current) >= orphans.len());
+    for orphan in orphans {
+        assert!(storage_keys(orphan).is_empty(), "{orphan}");
+    }
+    assert_eq!(storage_keys(&current), vec!["key"]);
+    assert_eq!(storage_keys("saved-app-gc"), vec!["key"]);
+
+    storage_clear(&current).unwrap();
+    storage_clear("saved-app-gc").unwrap();
+}
@@ -0,0 +1,204 @@
+//! Tests for loading and deleting saved apps: giving apps saved before ids
+//! existed a UUID, and keeping their storage with them
+//!
+//! These run against an in-memory backend and a temporary apps directory, in
+//! their own test binary.
+
+use blackbird::bridge::{self, storage_get, storage_keys, storage_set};
+use blackbird::html;
+use blackbird::storage::{self, MemoryBackend};
+use blackbird::views::shared::{
+    APP_ID_META, delete_app, load_apps_from_dir, persist_markdown_doc_in_dir,
+};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Once};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn use_memory_storage() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| storage::set_backend(Arc::new(MemoryBackend::new())));
+}
+
+/// Write `content` to `dir/name`, last modified `secs` after the epoch
+fn write_app(dir: &Path, name: &str, content: &str, secs: u64) -> String {
+    let path = dir.join(name);
+    fs::write(&path, content).unwrap();
+    set_modified(&path, secs);
+    path.to_string_lossy().into_owned()
+}
+
+fn set_modified(path: &Path, secs: u64) {
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(UNIX_EPOCH + Duration::from_secs(secs)))
+        .unwrap();
+}
+
+fn modified(path: &str) -> SystemTime {
+    fs::metadata(path).and_then(|meta| meta.modified()).unwrap()
+}
+
+#[test]
+fn test_legacy_app_gets_a_uuid_and_its_storage() {
+    use_memory_storage();
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_app(
+        dir.path(),
+        "timer.html",
+        "<html><head><title>Timer</title></head></html>",
+        1_000_000,
+    );
+    storage_set(&path, "laps", "[1,2]").unwrap();
+
+    let apps = load_apps_from_dir(dir.path());
+    assert_eq!(apps.len(), 1);
+    let app = &apps[0];
+    assert!(uuid::Uuid::parse_str(&app.id).is_ok());
+    assert_eq!(app.title, "Timer");
+
+    // The id is written into the file, whose age is kept
+    let saved = fs::read_to_string(&path).unwrap();
+    assert_eq!(
+        html::meta(&saved, APP_ID_META).as_deref(),
+        Some(app.id.as_str())
+    );
+    assert_eq!(
+        html::meta(&saved, bridge::SDK_VERSION_META).as_deref(),
+        Some(bridge::SYNC_STORAGE_SDK_VERSION)
+    );
+    assert_eq!(
+        html::meta(&saved, bridge::LEGACY_ID_META).as_deref(),
+        Some(path.as_str())
+    );
+    assert_eq!(app.created_at, 1_000_000);
+    assert_eq!(modified(&path), UNIX_EPOCH + Duration::from_secs(1_000_000));
+
+    // Storage kept under the path moved to the id
+    assert_eq!(storage_get(&app.id, "laps").as_deref(), Some("[1,2]"));
+    assert!(storage_keys(&path).is_empty());
+
+    // The next load finds the same id
+    assert_eq!(load_apps_from_dir(dir.path())[0].id, app.id);
+}
+
+#[test]
+fn test_legacy_app_finds_its_local_storage() {
+    use_memory_storage();
+    let dir = tempfile::tempdir().unwrap();
+    // Saved by a version whose SDK kept values in localStorage, under the path
+    let path = write_app(
+        dir.path(),
+        "counter.html",
+        "<!DOCTYPE html><html><head></head><body></body></html>",
+        1_000_000,
+    );
+
+    let app = load_apps_from_dir(dir.path()).remove(0);
+    let rendered = html::inject_theme_and_sdk(&app.content, "", &app.id);
+
+    assert!(rendered.contains(&format!("const APP_ID = '{}';", app.id)));
+    assert!(rendered.contains(&format!(
+        "const LEGACY_PREFIX = \"blackbird_app_{}_\";",
+        path
+    )));
+    assert!(rendered.contains("localStorage.removeItem(prefix + key);"));
+}
+
+#[test]
+fn test_copied_app_gets_its_own_id() {
+    use_memory_storage();
+    let dir = tempfile::tempdir().unwrap();
+    let id = "7d3f6a2e-0c1b-4f7e-9a51-2b8c4d6e8f00";
+    let content = format!(
+        "<html><head><meta name=\"{}\" content=\"{}\"></head></html>",
+        APP_ID_META, id
+    );
+    // The copy sorts first by name, but the original is older
+    let copy = write_app(dir.path(), "a-copy.html", &content, 2_000_000);
+    let original = write_app(dir.path(), "z-original.html", &content, 1_000_000);
+    storage_set(id, "notes", "mine").unwrap();
+
+    let apps = load_apps_from_dir(dir.path());
+    let find = |path: &str| {
+        apps.iter()
+            .find(|app| app.file_path.as_deref() == Some(path))
+            .unwrap()
+    };
+    assert_eq!(find(&original).id, id);
+    let copy_id = &find(&copy).id;
+    assert_ne!(copy_id, id);
+    assert!(uuid::Uuid::parse_str(copy_id).is_ok());
+
+    // Only apps that had no id have storage moved
+    assert_eq!(storage_get(id, "notes").as_deref(), Some("mine"));
+    assert!(storage_keys(copy_id).is_empty());
+    // Not a legacy app, so the copy keeps the current SDK
+    let saved = fs::read_to_string(&copy).unwrap();
+    assert_eq!(html::meta(&saved, bridge::SDK_VERSION_META), None);
+
+    // Newest first
+    assert_eq!(apps[0].file_path.as_deref(), Some(copy.as_str()));
+    assert_eq!(apps[0].created_at, 2_000_000);
+}
+
+#[test]
+fn test_markdown_documents_are_left_alone() {
+    use_memory_storage();
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_app(dir.path(), "notes.md", "# Notes\n\nHello", 1_000_000);
+
+    let apps = load_apps_from_dir(dir.path());
+    assert_eq!(apps[0].id, path);
+    assert_eq!(apps[0].title, "Notes");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "# Notes\n\nHello");
+}
+
+#[test]
+fn test_saved_markdown_is_kept_as_written() {
+    use_memory_storage();
+    let dir = tempfile::tempdir().unwrap();
+    let markdown = "# Shopping\n\n- eggs\n- <b>milk</b>\n";
+
+    let doc = persist_markdown_doc_in_dir(dir.path(), markdown, None).unwrap();
+    let path = doc.file_path.clone().unwrap();
+    assert!(path.ends_with(".md"));
+    assert_eq!(doc.id, path);
+    assert_eq!(doc.title, "Shopping");
+    assert_eq!(fs::read_to_string(&path).unwrap(), markdown);
+
+    let apps = load_apps_from_dir(dir.path());
+    assert_eq!(apps[0].id, path);
+    assert_eq!(apps[0].content, markdown);
+    assert_eq!(fs::read_to_string(&path).unwrap(), markdown);
+}
+
+#[test]
+fn test_markdown_saved_as_html_is_left_alone() {
+    use_memory_storage();
+    let dir = tempfile::tempdir().unwrap();
+    // Chat once saved documents as .html files
+    let markdown = "# Trip\n\nPack the tent";
+    let path = write_app(dir.path(), "trip.html", markdown, 1_000_000);
+
+    let apps = load_apps_from_dir(dir.path());
+    assert_eq!(apps[0].id, path);
+    assert_eq!(apps[0].content, markdown);
+    assert_eq!(fs::read_to_string(&path).unwrap(), markdown);
+}
+
+#[test]
+fn test_delete_app_clears_its_storage() {
+    use_memory_storage();
+    let dir = tempfile::tempdir().unwrap();
+    write_app(dir.path(), "todo.html", "<html></html>", 1_000_000);
+    let app = load_apps_from_dir(dir.path()).remove(0);
+    storage_set(&app.id, "todos", "[]").unwrap();
+
+    delete_app(&app);
+
+    assert!(!Path::new(app.file_path.as_deref().unwrap()).exists());
+    assert!(storage_keys(&app.id).is_empty());
+    assert!(load_apps_from_dir(dir.path()).is_empty());
+}
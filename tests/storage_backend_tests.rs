@@ -0,0 +1,258 @@
+//! Conformance tests for the app storage backends
+//!
+//! Every backend runs the same suite, so apps behave the same whichever one
+//! is selected.
+
+use blackbird::storage::{
+    BackendKind, FileBackend, MemoryBackend, SqliteBackend, StorageBackend, StorageUsage,
+};
+use tempfile::TempDir;
+
+/// The checks every backend must pass
+mod suite {
+    use super::*;
+
+    fn sorted(mut keys: Vec<String>) -> Vec<String> {
+        keys.sort();
+        keys
+    }
+
+    pub fn set_and_get(backend: &dyn StorageBackend) {
+        let value = r#"{"name": "test", "count": 42}"#;
+        backend.set("app", "data", value).unwrap();
+        assert_eq!(backend.get("app", "data").unwrap().as_deref(), Some(value));
+        assert_eq!(backend.get("app", "missing").unwrap(), None);
+        assert_eq!(backend.get("other-app", "data").unwrap(), None);
+    }
+
+    pub fn overwrite(backend: &dyn StorageBackend) {
+        backend.set("app", "score", "1").unwrap();
+        backend.set("app", "score", "2").unwrap();
+        assert_eq!(backend.get("app", "score").unwrap().as_deref(), Some("2"));
+        assert_eq!(backend.keys("app").unwrap(), vec!["score".to_string()]);
+    }
+
+    pub fn delete(backend: &dyn StorageBackend) {
+        backend.set("app", "a", "1").unwrap();
+        backend.set("app", "b", "2").unwrap();
+        backend.delete("app", "a").unwrap();
+        backend.delete("app", "never-set").unwrap();
+        backend.delete("no-such-app", "a").unwrap();
+
+        assert_eq!(backend.get("app", "a").unwrap(), None);
+        assert_eq!(backend.keys("app").unwrap(), vec!["b".to_string()]);
+    }
+
+    pub fn keys_round_trip(backend: &dyn StorageBackend) {
+        let long = "recipe/".repeat(60);
+        let keys = [
+            "user:prefs",
+            "user_prefs",
+            "User:Prefs",
+            "user prefs ",
+            "café",
+            "日本語のキー",
+            "🎉",
+            "../../escape",
+            "",
+            long.as_str(),
+        ];
+        for (i, key) in keys.iter().enumerate() {
+            backend.set("app", key, &i.to_string()).unwrap();
+        }
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(
+                backend.get("app", key).unwrap(),
+                Some(i.to_string()),
+                "{key}"
+            );
+        }
+        assert_eq!(
+            sorted(backend.keys("app").unwrap()),
+            sorted(keys.iter().map(|k| k.to_string()).collect())
+        );
+    }
+
+    pub fn clear(backend: &dyn StorageBackend) {
+        backend.set("app", "a", "1").unwrap();
+        backend.set("app", "b", "2").unwrap();
+        backend.set("other-app", "a", "kept").unwrap();
+        backend.clear("app").unwrap();
+        backend.clear("no-such-app").unwrap();
+
+        assert!(backend.keys("app").unwrap().is_empty());
+        assert_eq!(backend.get("app", "a").unwrap(), None);
+        assert_eq!(
+            backend.get("other-app", "a").unwrap().as_deref(),
+            Some("kept")
+        );
+        assert!(backend.keys("no-such-app").unwrap().is_empty());
+    }
+
+    pub fn batches(backend: &dyn StorageBackend) {
+        backend
+            .set_many("app", &[("a", "1"), ("b", "2"), ("c", "3")])
+            .unwrap();
+        assert_eq!(
+            backend.get_many("app", &["a", "missing", "c"]).unwrap(),
+            vec![Some("1".to_string()), None, Some("3".to_string())]
+        );
+
+        backend.delete_many("app", &["a", "c", "missing"]).unwrap();
+        assert_eq!(backend.keys("app").unwrap(), vec!["b".to_string()]);
+        assert!(backend.get_many("app", &[]).unwrap().is_empty());
+    }
+
+    pub fn usage(backend: &dyn StorageBackend) {
+        assert_eq!(backend.usage("app").unwrap(), StorageUsage::default());
+
+        backend.set("app", "a", "123").unwrap();
+        backend.set("app", "café", "日本").unwrap();
+        backend.set("other-app", "a", "not counted").unwrap();
+        let bytes = ("a".len() + "123".len() + "café".len() + "日本".len()) as u64;
+        assert_eq!(
+            backend.usage("app").unwrap(),
+            StorageUsage { bytes, keys: 2 }
+        );
+
+        backend.set("app", "a", "1").unwrap();
+        assert_eq!(backend.usage("app").unwrap().bytes, bytes - 2);
+
+        backend.clear("app").unwrap();
+        assert_eq!(backend.usage("app").unwrap(), StorageUsage::default());
+    }
+
+    pub fn app_ids(backend: &dyn StorageBackend) {
+        assert!(backend.app_ids().unwrap().is_empty());
+
+        backend.set("preview-1a2b", "a", "1").unwrap();
+        backend.set("my-app", "a", "1").unwrap();
+        assert_eq!(
+            sorted(backend.app_ids().unwrap()),
+            vec!["my-app".to_string(), "preview-1a2b".to_string()]
+        );
+
+        backend.clear("preview-1a2b").unwrap();
+        assert_eq!(backend.app_ids().unwrap(), vec!["my-app".to_string()]);
+    }
+}
+
+macro_rules! conformance {
+    ($backend:ident, $open:expr) => {
+        mod $backend {
+            use super::*;
+
+            fn open() -> (Box<dyn StorageBackend>, Option<TempDir>) {
+                $open
+            }
+
+            #[test]
+            fn set_and_get() {
+                let (backend, _dir) = open();
+                suite::set_and_get(&*backend);
+            }
+
+            #[test]
+            fn overwrite() {
+                let (backend, _dir) = open();
+                suite::overwrite(&*backend);
+            }
+
+            #[test]
+            fn delete() {
+                let (backend, _dir) = open();
+                suite::delete(&*backend);
+            }
+
+            #[test]
+            fn keys_round_trip() {
+                let (backend, _dir) = open();
+                suite::keys_round_trip(&*backend);
+            }
+
+            #[test]
+            fn clear() {
+                let (backend, _dir) = open();
+                suite::clear(&*backend);
+            }
+
+            #[test]
+            fn batches() {
+                let (backend, _dir) = open();
+                suite::batches(&*backend);
+            }
+
+            #[test]
+            fn usage() {
+                let (backend, _dir) = open();
+                suite::usage(&*backend);
+            }
+
+            #[test]
+            fn app_ids() {
+                let (backend, _dir) = open();
+                suite::app_ids(&*backend);
+            }
+        }
+    };
+}
+
+conformance!(memory, (Box::new(MemoryBackend::new()), None));
+
+conformance!(file, {
+    let dir = TempDir::new().unwrap();
+    (Box::new(FileBackend::new(dir.path())), Some(dir))
+});
+
+conformance!(
+    sqlite,
+    (Box::new(SqliteBackend::in_memory().unwrap()), None)
+);
+
+conformance!(sqlite_file, {
+    let dir = TempDir::new().unwrap();
+    let backend = SqliteBackend::open(dir.path().join("app_data.sqlite3")).unwrap();
+    (Box::new(backend), Some(dir))
+});
+
+#[test]
+fn test_file_backend_persists_between_instances() {
+    let dir = TempDir::new().unwrap();
+    FileBackend::new(dir.path())
+        .set("app", "user:prefs", "dark")
+        .unwrap();
+
+    let reopened = FileBackend::new(dir.path());
+    assert_eq!(
+        reopened.get("app", "user:prefs").unwrap().as_deref(),
+        Some("dark")
+    );
+    assert_eq!(
+        reopened.keys("app").unwrap(),
+        vec!["user:prefs".to_string()]
+    );
+}
+
+#[test]
+fn test_sqlite_backend_persists_between_instances() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("app_data.sqlite3");
+    SqliteBackend::open(&path)
+        .unwrap()
+        .set("app", "user:prefs", "dark")
+        .unwrap();
+
+    let reopened = SqliteBackend::open(&path).unwrap();
+    assert_eq!(
+        reopened.get("app", "user:prefs").unwrap().as_deref(),
+        Some("dark")
+    );
+}
+
+#[test]
+fn test_backend_kind_names() {
+    assert_eq!(BackendKind::parse("sqlite"), Some(BackendKind::Sqlite));
+    assert_eq!(BackendKind::parse(" File "), Some(BackendKind::File));
+    assert_eq!(BackendKind::parse("memory"), Some(BackendKind::Memory));
+    assert_eq!(BackendKind::parse("redis"), None);
+}
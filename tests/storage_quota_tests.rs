@@ -0,0 +1,145 @@
+//! Tests for the storage quota as apps write through the bridge
+//!
+//! These run in their own test binary, with a small quota set before the
+//! bridge first reads it, against a slowed in-memory backend.
+
+use blackbird::bridge::{
+    self, storage_clear, storage_delete, storage_get, storage_set, storage_usage,
+};
+use blackbird::storage::{
+    self, MemoryBackend, StorageBackend, StorageError, StorageResult, StorageUsage,
+};
+use std::sync::{Arc, Once};
+use std::thread;
+use std::time::Duration;
+
+const MAX_KEYS: usize = 5;
+const MAX_BYTES: u64 = 100;
+
+/// Memory storage with slow reads, so writers that check the quota
+/// without holding the app's lock all see it with room to spare
+struct SlowBackend(MemoryBackend);
+
+impl StorageBackend for SlowBackend {
+    fn get(&self, app_id: &str, key: &str) -> StorageResult<Option<String>> {
+        thread::sleep(Duration::from_millis(2));
+        self.0.get(app_id, key)
+    }
+
+    fn set(&self, app_id: &str, key: &str, value: &str) -> StorageResult<()> {
+        self.0.set(app_id, key, value)
+    }
+
+    fn delete(&self, app_id: &str, key: &str) -> StorageResult<()> {
+        self.0.delete(app_id, key)
+    }
+
+    fn keys(&self, app_id: &str) -> StorageResult<Vec<String>> {
+        self.0.keys(app_id)
+    }
+
+    fn clear(&self, app_id: &str) -> StorageResult<()> {
+        self.0.clear(app_id)
+    }
+
+    fn app_ids(&self) -> StorageResult<Vec<String>> {
+        self.0.app_ids()
+    }
+}
+
+fn use_small_quota() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        // SAFETY: runs once, before any test reads the environment
+        unsafe {
+            std::env::set_var("APP_STORAGE_MAX_KEYS", MAX_KEYS.to_string());
+            std::env::set_var("APP_STORAGE_MAX_BYTES", MAX_BYTES.to_string());
+        }
+        storage::set_backend(Arc::new(SlowBackend(MemoryBackend::new())));
+    });
+}
+
+#[test]
+fn test_concurrent_writes_stay_within_quota() {
+    use_small_quota();
+    let app_id = "quota-concurrent";
+
+    let writers: Vec<_> = (0..4 * MAX_KEYS)
+        .map(|i| thread::spawn(move || storage_set(app_id, &format!("key{i}"), "1")))
+        .collect();
+    let results: Vec<_> = writers.into_iter().map(|w| w.join().unwrap()).collect();
+
+    assert_eq!(results.iter().filter(|r| r.is_ok()).count(), MAX_KEYS);
+    assert!(
+        results
+            .iter()
+            .filter_map(|r| r.as_ref().err())
+            .all(|err| matches!(err, StorageError::TooManyKeys(MAX_KEYS)))
+    );
+    assert_eq!(storage_usage(app_id).keys, MAX_KEYS);
+}
+
+#[test]
+fn test_quota_follows_deletes_and_clears() {
+    use_small_quota();
+    let app_id = "quota-tracking";
+
+    for i in 0..MAX_KEYS {
+        storage_set(app_id, &format!("key{i}"), "1").unwrap();
+    }
+    assert!(storage_set(app_id, "extra", "1").is_err());
+
+    storage_delete(app_id, "key0").unwrap();
+    storage_set(app_id, "extra", "1").unwrap();
+
+    // Overwrites count only the difference
+    storage_set(app_id, "extra", &"x".repeat(60)).unwrap();
+    assert!(matches!(
+        storage_set(app_id, "key1", &"x".repeat(60)),
+        Err(StorageError::TooManyBytes(MAX_BYTES))
+    ));
+
+    storage_clear(app_id).unwrap();
+    assert_eq!(storage_usage(app_id), StorageUsage::default());
+    storage_set(app_id, "key1", &"x".repeat(60)).unwrap();
+    assert_eq!(storage_usage(app_id), StorageUsage { bytes: 64, keys: 1 });
+}
+
+#[test]
+fn test_copy_stays_within_quota() {
+    use_small_quota();
+    let (from, to) = ("quota-copy-from", "quota-copy-to");
+    for i in 0..3 {
+        storage_set(from, &format!("key{i}"), "1").unwrap();
+        storage_set(to, &format!("own{i}"), "1").unwrap();
+    }
+
+    assert!(matches!(
+        bridge::storage_copy(from, to),
+        Err(StorageError::TooManyKeys(MAX_KEYS))
+    ));
+    assert!(matches!(
+        bridge::storage_move(from, to),
+        Err(StorageError::TooManyKeys(MAX_KEYS))
+    ));
+    // Nothing was copied, and the source kept its data
+    assert_eq!(storage_usage(to).keys, 3);
+    assert_eq!(storage_get(from, "key0").as_deref(), Some("1"));
+
+    storage_delete(to, "own0").unwrap();
+    storage_delete(to, "own1").unwrap();
+    assert_eq!(bridge::storage_copy(from, to).unwrap(), 3);
+    assert_eq!(storage_usage(to).keys, MAX_KEYS - 1);
+    // The copy's usage is tracked for the next write
+    storage_set(to, "last", "1").unwrap();
+    assert!(storage_set(to, "extra", "1").is_err());
+}
+
+#[test]
+fn test_app_builder_prompt_states_the_quota() {
+    use_small_quota();
+    let prompt = &blackbird::views::workbench::build_request(Vec::new(), false)[0].content;
+
+    assert!(prompt.contains("Each app can store up to 100 B in 5 keys."));
+    assert!(!prompt.contains("{storage_quota}"));
+}